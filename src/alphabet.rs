@@ -0,0 +1,862 @@
+//! The phonetic alphabets the ph attribute of the phoneme element can be written in and
+//! conversion between them. Engines each support different alphabets so a transcription can be
+//! rewritten for the engine with `Ssml::convert_phonemes`.
+//!
+//! Conversion goes through IPA. X-SAMPA is a direct ASCII encoding of IPA so nothing is lost
+//! between them, ARPAbet and the Microsoft SAPI phone set only cover the sounds of American
+//! English. Those two put stress on the vowel where IPA puts it at the start of the syllable, so
+//! when converting to IPA the stress mark is moved in front of the consonants before the vowel,
+//! leaving one with the previous syllable if there's a cluster.
+use crate::elements::*;
+use crate::{ParserEvent, Ssml, TransformedSsml};
+use lazy_static::lazy_static;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// A phonetic alphabet which can be converted to and from IPA.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Alphabet {
+    /// The International Phonetic Alphabet.
+    Ipa,
+    /// Extended Speech Assessment Methods Phonetic Alphabet, an ASCII encoding of IPA.
+    XSampa,
+    /// ARPAbet as used by the CMU pronouncing dictionary, phones are separated by spaces and
+    /// vowels end in a stress digit, "HH AH0 L OW1".
+    Arpabet,
+    /// The Microsoft SAPI phone set for American English, phones are separated by spaces and
+    /// followed by "1" or "2" for stress, "h ax l ow 1".
+    MicrosoftSapi,
+}
+
+/// IPA symbols, combining marks and suprasegmentals which are recognised in IPA transcriptions.
+/// Other diacritics and modifier letters are accepted through `ipa_modifier`.
+const IPA: &[&str] = &[
+    "p", "b", "t", "d", "ʈ", "ɖ", "c", "ɟ", "k", "ɡ", "g", "q", "ɢ", "ʔ", "m", "ɱ", "n", "ɳ", "ɲ",
+    "ŋ", "ɴ", "ʙ", "r", "ʀ", "ⱱ", "ɾ", "ɽ", "ɸ", "β", "f", "v", "θ", "ð", "s", "z", "ʃ", "ʒ", "ʂ",
+    "ʐ", "ç", "ʝ", "x", "ɣ", "χ", "ʁ", "ħ", "ʕ", "h", "ɦ", "ɬ", "ɮ", "ʋ", "ɹ", "ɻ", "j", "ɰ", "l",
+    "ɭ", "ʎ", "ʟ", "w", "ʍ", "ɥ", "i", "y", "ɨ", "ʉ", "ɯ", "u", "ɪ", "ʏ", "ʊ", "e", "ø", "ɘ", "ɵ",
+    "ɤ", "o", "ə", "ɛ", "œ", "ɜ", "ɞ", "ʌ", "ɔ", "æ", "ɐ", "a", "ɶ", "ɑ", "ɒ", "ɚ", "ɝ", "ˈ", "ˌ",
+    "ː", "ˑ", ".", "ʰ", "ʷ", "ʲ", "\u{303}", "\u{329}", "\u{361}", "ɫ", "ɺ", "ɕ", "ʑ", "ɧ", "ʜ",
+    "ʢ", "ʡ", "ɓ", "ɗ", "ʄ", "ɠ", "ʛ", "ʘ", "ǀ", "ǃ", "ǂ", "ǁ", "ʧ", "ʤ", "ʦ", "ʣ", "ʨ", "ʥ", "ʼ",
+    "˥", "˦", "˧", "˨", "˩", "ꜜ", "ꜛ", "↗", "↘", "|", "‖", "‿",
+];
+
+lazy_static! {
+    /// The spacing modifier letters, U+02B0 to U+02FF, followed by the combining diacritical
+    /// marks, U+0300 to U+036F. Every one of them is two bytes long in UTF-8.
+    static ref IPA_MODIFIERS: String = ('\u{2b0}'..='\u{36f}').collect();
+}
+
+/// A diacritic or modifier letter written after an IPA symbol, such as the non-syllabic mark in
+/// "aɪ̯" or the dental mark in "t̪".
+fn ipa_modifier(c: char) -> Option<&'static str> {
+    let index = (c as usize).checked_sub(0x2b0)? * 2;
+    IPA_MODIFIERS.get(index..index + 2)
+}
+
+const X_SAMPA: &[(&str, &str)] = &[
+    ("p", "p"),
+    ("b", "b"),
+    ("t", "t"),
+    ("d", "d"),
+    ("t`", "ʈ"),
+    ("d`", "ɖ"),
+    ("c", "c"),
+    ("J\\", "ɟ"),
+    ("k", "k"),
+    ("g", "ɡ"),
+    ("q", "q"),
+    ("G\\", "ɢ"),
+    ("?", "ʔ"),
+    ("m", "m"),
+    ("F", "ɱ"),
+    ("n", "n"),
+    ("n`", "ɳ"),
+    ("J", "ɲ"),
+    ("N", "ŋ"),
+    ("N\\", "ɴ"),
+    ("B\\", "ʙ"),
+    ("r", "r"),
+    ("R\\", "ʀ"),
+    ("4", "ɾ"),
+    ("r`", "ɽ"),
+    ("p\\", "ɸ"),
+    ("B", "β"),
+    ("f", "f"),
+    ("v", "v"),
+    ("T", "θ"),
+    ("D", "ð"),
+    ("s", "s"),
+    ("z", "z"),
+    ("S", "ʃ"),
+    ("Z", "ʒ"),
+    ("s`", "ʂ"),
+    ("z`", "ʐ"),
+    ("C", "ç"),
+    ("j\\", "ʝ"),
+    ("x", "x"),
+    ("G", "ɣ"),
+    ("X", "χ"),
+    ("R", "ʁ"),
+    ("X\\", "ħ"),
+    ("?\\", "ʕ"),
+    ("h", "h"),
+    ("h\\", "ɦ"),
+    ("K", "ɬ"),
+    ("K\\", "ɮ"),
+    ("P", "ʋ"),
+    ("v\\", "ʋ"),
+    ("r\\", "ɹ"),
+    ("r\\`", "ɻ"),
+    ("j", "j"),
+    ("M\\", "ɰ"),
+    ("l", "l"),
+    ("l`", "ɭ"),
+    ("L", "ʎ"),
+    ("L\\", "ʟ"),
+    ("w", "w"),
+    ("W", "ʍ"),
+    ("H", "ɥ"),
+    ("i", "i"),
+    ("y", "y"),
+    ("1", "ɨ"),
+    ("}", "ʉ"),
+    ("M", "ɯ"),
+    ("u", "u"),
+    ("I", "ɪ"),
+    ("Y", "ʏ"),
+    ("U", "ʊ"),
+    ("e", "e"),
+    ("2", "ø"),
+    ("@\\", "ɘ"),
+    ("8", "ɵ"),
+    ("7", "ɤ"),
+    ("o", "o"),
+    ("@", "ə"),
+    ("E", "ɛ"),
+    ("9", "œ"),
+    ("3", "ɜ"),
+    ("3\\", "ɞ"),
+    ("V", "ʌ"),
+    ("O", "ɔ"),
+    ("{", "æ"),
+    ("6", "ɐ"),
+    ("a", "a"),
+    ("&", "ɶ"),
+    ("A", "ɑ"),
+    ("Q", "ɒ"),
+    ("@`", "ɚ"),
+    ("3`", "ɝ"),
+    ("\"", "ˈ"),
+    ("%", "ˌ"),
+    (":", "ː"),
+    (":\\", "ˑ"),
+    (".", "."),
+    ("_h", "ʰ"),
+    ("_w", "ʷ"),
+    ("_j", "ʲ"),
+    ("~", "\u{303}"),
+    ("=", "\u{329}"),
+    ("_", "\u{361}"),
+];
+
+/// ARPAbet phones, the vowels take a stress digit. Where several phones have the same IPA the
+/// first is used when converting from IPA.
+const ARPABET: &[(&str, &str)] = &[
+    ("AA", "ɑ"),
+    ("AE", "æ"),
+    ("AH", "ʌ"),
+    ("AO", "ɔ"),
+    ("AW", "aʊ"),
+    ("AX", "ə"),
+    ("AXR", "ɚ"),
+    ("AY", "aɪ"),
+    ("EH", "ɛ"),
+    ("ER", "ɝ"),
+    ("EY", "eɪ"),
+    ("IH", "ɪ"),
+    ("IX", "ɨ"),
+    ("IY", "i"),
+    ("OW", "oʊ"),
+    ("OY", "ɔɪ"),
+    ("UH", "ʊ"),
+    ("UW", "u"),
+    ("UX", "ʉ"),
+    ("B", "b"),
+    ("CH", "tʃ"),
+    ("D", "d"),
+    ("DH", "ð"),
+    ("DX", "ɾ"),
+    ("EL", "l\u{329}"),
+    ("EM", "m\u{329}"),
+    ("EN", "n\u{329}"),
+    ("F", "f"),
+    ("G", "ɡ"),
+    ("HH", "h"),
+    ("JH", "dʒ"),
+    ("K", "k"),
+    ("L", "l"),
+    ("M", "m"),
+    ("N", "n"),
+    ("NG", "ŋ"),
+    ("P", "p"),
+    ("Q", "ʔ"),
+    ("R", "ɹ"),
+    ("S", "s"),
+    ("SH", "ʃ"),
+    ("T", "t"),
+    ("TH", "θ"),
+    ("V", "v"),
+    ("W", "w"),
+    ("WH", "ʍ"),
+    ("Y", "j"),
+    ("Z", "z"),
+    ("ZH", "ʒ"),
+];
+
+const SAPI: &[(&str, &str)] = &[
+    ("aa", "ɑ"),
+    ("ae", "æ"),
+    ("ah", "ʌ"),
+    ("ao", "ɔ"),
+    ("aw", "aʊ"),
+    ("ax", "ə"),
+    ("ay", "aɪ"),
+    ("eh", "ɛ"),
+    ("er", "ɝ"),
+    ("ey", "eɪ"),
+    ("ih", "ɪ"),
+    ("iy", "i"),
+    ("ow", "oʊ"),
+    ("oy", "ɔɪ"),
+    ("uh", "ʊ"),
+    ("uw", "u"),
+    ("b", "b"),
+    ("ch", "tʃ"),
+    ("d", "d"),
+    ("dh", "ð"),
+    ("f", "f"),
+    ("g", "ɡ"),
+    ("h", "h"),
+    ("jh", "dʒ"),
+    ("k", "k"),
+    ("l", "l"),
+    ("m", "m"),
+    ("n", "n"),
+    ("ng", "ŋ"),
+    ("p", "p"),
+    ("r", "ɹ"),
+    ("s", "s"),
+    ("sh", "ʃ"),
+    ("t", "t"),
+    ("th", "θ"),
+    ("v", "v"),
+    ("w", "w"),
+    ("y", "j"),
+    ("z", "z"),
+    ("zh", "ʒ"),
+    ("1", "ˈ"),
+    ("2", "ˌ"),
+    ("-", "."),
+];
+
+/// IPA which ARPAbet and SAPI don't have their own phone for but have a close one, used when
+/// converting from IPA. Length marks are dropped.
+const ENGLISH_APPROXIMATIONS: &[(&str, &str)] = &[
+    ("iː", "i"),
+    ("uː", "u"),
+    ("ɑː", "ɑ"),
+    ("ɔː", "ɔ"),
+    ("ɜː", "ɝ"),
+    ("ɜ", "ɝ"),
+    ("əʊ", "oʊ"),
+    ("o", "oʊ"),
+    ("e", "eɪ"),
+    ("ɒ", "ɑ"),
+    ("a", "æ"),
+    ("r", "ɹ"),
+    ("ɾ", "t"),
+    ("g", "ɡ"),
+    ("ʔ", "t"),
+    ("ː", ""),
+    (".", ""),
+];
+
+const VOWELS: &str = "iyɨʉɯuɪʏʊeøɘɵɤoəɛœɜɞʌɔæɐaɶɑɒɚɝ";
+
+fn is_vowel(ipa: &str) -> bool {
+    ipa.chars().next().is_some_and(|c| VOWELS.contains(c))
+}
+
+/// A symbol read from a transcription.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol<'a> {
+    /// The symbol as it's written.
+    pub text: &'a str,
+    /// Where the symbol is in the transcription in bytes.
+    pub range: Range<usize>,
+    /// The symbol in IPA, `None` if it isn't a symbol of the alphabet. Whitespace is a single
+    /// space and the stress digits of ARPAbet vowels aren't included.
+    pub ipa: Option<&'static str>,
+}
+
+/// Result of converting a transcription to another alphabet.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Converted {
+    /// The transcription in the new alphabet, symbols which couldn't be converted are left out.
+    pub ph: String,
+    /// Symbols which couldn't be converted.
+    pub unmapped: Vec<Unmapped>,
+}
+
+/// A symbol with no equivalent in the alphabet being converted to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Unmapped {
+    /// The symbol, in the source alphabet if it isn't a valid symbol there otherwise in IPA.
+    pub symbol: String,
+    /// Byte offset in the source transcription of the symbol it came from.
+    pub offset: usize,
+}
+
+/// Finds the longest symbol in the table matching the start of the text, the first in the table
+/// is used if several are the same length.
+fn longest_match<'t>(
+    text: &str,
+    symbols: impl Iterator<Item = (&'t str, &'static str)>,
+) -> Option<(&'t str, &'static str)> {
+    let mut longest: Option<(&str, &str)> = None;
+    for (symbol, value) in symbols {
+        if !symbol.is_empty()
+            && text.starts_with(symbol)
+            && longest.is_none_or(|(x, _)| symbol.len() > x.len())
+        {
+            longest = Some((symbol, value));
+        }
+    }
+    longest
+}
+
+impl Alphabet {
+    /// All of the alphabets which can be converted between.
+    pub const ALL: [Alphabet; 4] = [Self::Ipa, Self::XSampa, Self::Arpabet, Self::MicrosoftSapi];
+
+    /// Name of the alphabet used in the alphabet attribute.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ipa => "ipa",
+            Self::XSampa => "x-sampa",
+            Self::Arpabet => "x-arpabet",
+            Self::MicrosoftSapi => "x-microsoft-sapi",
+        }
+    }
+
+    /// Whether symbols are separated by whitespace rather than written one after another.
+    fn space_separated(&self) -> bool {
+        matches!(self, Self::Arpabet | Self::MicrosoftSapi)
+    }
+
+    /// The symbols of the alphabet and their IPA.
+    fn table(&self) -> Box<dyn Iterator<Item = (&'static str, &'static str)>> {
+        match self {
+            // An ASCII g is often written for the IPA ɡ
+            Self::Ipa => Box::new(IPA.iter().map(|x| (*x, if *x == "g" { "ɡ" } else { *x }))),
+            Self::XSampa => Box::new(X_SAMPA.iter().copied()),
+            Self::Arpabet => Box::new(ARPABET.iter().copied()),
+            Self::MicrosoftSapi => Box::new(SAPI.iter().copied()),
+        }
+    }
+
+    /// Looks up a space separated phone, ARPAbet vowels can end with a stress digit.
+    fn phone(&self, text: &str) -> Option<&'static str> {
+        let phone = match self {
+            Self::Arpabet => {
+                let phone = text.trim_end_matches(['0', '1', '2']);
+                if phone.len() + 1 < text.len() {
+                    return None;
+                }
+                phone
+            }
+            _ => text,
+        };
+        let (_, ipa) = self.table().find(|(symbol, _)| *symbol == phone)?;
+        let stressed = phone.len() != text.len();
+        match (self, stressed) {
+            (Self::Arpabet, true) if !is_vowel(ipa) => None,
+            // The CMU dictionary writes schwa as an unstressed AH
+            (Self::Arpabet, true) if text == "AH0" => Some("ə"),
+            (Self::Arpabet, true) if text == "ER0" => Some("ɚ"),
+            _ => Some(ipa),
+        }
+    }
+
+    /// Splits a transcription into its symbols. Whitespace is returned as a symbol for alphabets
+    /// which write symbols one after another, such as IPA, and skipped for those that separate
+    /// them with spaces.
+    pub fn symbols<'a>(&self, ph: &'a str) -> Vec<Symbol<'a>> {
+        let mut symbols = vec![];
+        if self.space_separated() {
+            let mut offset = 0;
+            for part in ph.split_whitespace() {
+                let start = offset + ph[offset..].find(part).unwrap_or_default();
+                offset = start + part.len();
+                symbols.push(Symbol {
+                    text: part,
+                    range: start..offset,
+                    ipa: self.phone(part),
+                });
+            }
+            return symbols;
+        }
+        let mut offset = 0;
+        while offset < ph.len() {
+            let rest = &ph[offset..];
+            let (len, ipa) = match longest_match(rest, self.table()) {
+                Some((symbol, ipa)) => (symbol.len(), Some(ipa)),
+                None => {
+                    let c = rest.chars().next().unwrap();
+                    let ipa = match self {
+                        Self::Ipa => ipa_modifier(c),
+                        _ => None,
+                    };
+                    let ipa = ipa.or_else(|| c.is_whitespace().then_some(" "));
+                    (c.len_utf8(), ipa)
+                }
+            };
+            symbols.push(Symbol {
+                text: &rest[..len],
+                range: offset..offset + len,
+                ipa,
+            });
+            offset += len;
+        }
+        symbols
+    }
+
+    /// The first symbol of the transcription which isn't part of the alphabet, if there is one.
+    pub fn invalid_symbol<'a>(&self, ph: &'a str) -> Option<Symbol<'a>> {
+        self.symbols(ph).into_iter().find(|x| x.ipa.is_none())
+    }
+
+    /// Converts the transcription to IPA, returning each piece of IPA with the offset of the
+    /// source symbol it came from.
+    fn ipa_pieces(&self, ph: &str, unmapped: &mut Vec<Unmapped>) -> Vec<(String, usize)> {
+        let mut pieces: Vec<(String, usize)> = vec![];
+        for symbol in self.symbols(ph) {
+            let Some(ipa) = symbol.ipa else {
+                unmapped.push(Unmapped {
+                    symbol: symbol.text.to_string(),
+                    offset: symbol.range.start,
+                });
+                continue;
+            };
+            let stress = match self {
+                Self::Arpabet if symbol.text.ends_with('1') => Some("ˈ"),
+                Self::Arpabet if symbol.text.ends_with('2') => Some("ˌ"),
+                Self::MicrosoftSapi if ipa == "ˈ" || ipa == "ˌ" => {
+                    // SAPI stress follows the vowel, pieces are moved in front of it below
+                    match pieces.iter().rposition(|(x, _)| is_vowel(x)) {
+                        Some(vowel) => {
+                            pieces.insert(vowel, (ipa.to_string(), symbol.range.start));
+                            Self::move_stress(&mut pieces, vowel);
+                        }
+                        None => unmapped.push(Unmapped {
+                            symbol: symbol.text.to_string(),
+                            offset: symbol.range.start,
+                        }),
+                    }
+                    continue;
+                }
+                _ => None,
+            };
+            if let Some(stress) = stress {
+                pieces.push((stress.to_string(), symbol.range.start));
+                let index = pieces.len() - 1;
+                Self::move_stress(&mut pieces, index);
+            }
+            pieces.push((ipa.to_string(), symbol.range.start));
+        }
+        pieces
+    }
+
+    /// Moves the stress mark at `index`, which is just before its vowel, to the start of the
+    /// syllable. The consonants before it are moved after it leaving the first with the previous
+    /// syllable if there's more than one and a previous syllable.
+    fn move_stress(pieces: &mut Vec<(String, usize)>, index: usize) {
+        let onset_start = pieces[..index]
+            .iter()
+            .rposition(|(x, _)| is_vowel(x) || x == "." || x.trim().is_empty())
+            .map(|x| x + 1);
+        let consonants = index - onset_start.unwrap_or(0);
+        let target = match onset_start {
+            Some(start) if consonants > 1 && is_vowel(&pieces[start - 1].0) => start + 1,
+            Some(start) => start,
+            None => 0,
+        };
+        let stress = pieces.remove(index);
+        pieces.insert(target, stress);
+    }
+
+    /// Converts the IPA pieces into this alphabet.
+    fn write_pieces(&self, pieces: &[(String, usize)], unmapped: &mut Vec<Unmapped>) -> String {
+        let mut ipa = String::new();
+        let mut origins = vec![];
+        for (piece, offset) in pieces {
+            origins.push((ipa.len(), *offset));
+            ipa.push_str(piece);
+        }
+        let origin = |position: usize| {
+            let index = origins.partition_point(|(start, _)| *start <= position);
+            origins[index.saturating_sub(1)].1
+        };
+
+        let mut output: Vec<String> = vec![];
+        let mut stress = None;
+        let mut position = 0;
+        while position < ipa.len() {
+            let rest = &ipa[position..];
+            let table = self.table().map(|(symbol, ipa)| (ipa, symbol));
+            let found = match self {
+                Self::Arpabet | Self::MicrosoftSapi => {
+                    let approximations = ENGLISH_APPROXIMATIONS.iter().filter_map(|(from, to)| {
+                        match self.table().find(|(_, ipa)| ipa == to) {
+                            Some((symbol, _)) => Some((*from, symbol)),
+                            None if to.is_empty() => Some((*from, "")),
+                            None => None,
+                        }
+                    });
+                    let stress_marks = [("ˈ", "ˈ"), ("ˌ", "ˌ")];
+                    longest_match(rest, table.chain(approximations).chain(stress_marks))
+                }
+                _ => longest_match(rest, table),
+            };
+            let Some((matched, symbol)) = found else {
+                let c = rest.chars().next().unwrap();
+                if c.is_whitespace() {
+                    output.push(" ".to_string());
+                } else {
+                    unmapped.push(Unmapped {
+                        symbol: c.to_string(),
+                        offset: origin(position),
+                    });
+                }
+                position += c.len_utf8();
+                continue;
+            };
+            position += matched.len();
+            if self.space_separated() && (matched == "ˈ" || matched == "ˌ") {
+                stress = Some(matched);
+                continue;
+            }
+            match (self, symbol) {
+                (_, "") => {}
+                (Self::Arpabet, _) if self.phone(symbol).is_some_and(is_vowel) => {
+                    let digit = match stress.take() {
+                        Some("ˈ") => "1",
+                        Some(_) => "2",
+                        None => "0",
+                    };
+                    let symbol = match (symbol, digit) {
+                        ("AX", "0") => "AH",
+                        ("AXR", "0") => "ER",
+                        _ => symbol,
+                    };
+                    output.push(format!("{}{}", symbol, digit));
+                }
+                (Self::MicrosoftSapi, _) if self.phone(symbol).is_some_and(is_vowel) => {
+                    output.push(symbol.to_string());
+                    match stress.take() {
+                        Some("ˈ") => output.push("1".to_string()),
+                        Some(_) => output.push("2".to_string()),
+                        None => {}
+                    }
+                }
+                _ => output.push(symbol.to_string()),
+            }
+        }
+
+        if self.space_separated() {
+            output
+                .iter()
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            output.concat()
+        }
+    }
+
+    /// Converts a transcription from this alphabet to the target. Symbols which aren't part of
+    /// this alphabet or which have no equivalent in the target are reported and left out.
+    pub fn convert(&self, ph: &str, target: Alphabet) -> Converted {
+        let mut unmapped = vec![];
+        let pieces = self.ipa_pieces(ph, &mut unmapped);
+        let ph = target.write_pieces(&pieces, &mut unmapped);
+        unmapped.sort_by_key(|x| x.offset);
+        Converted { ph, unmapped }
+    }
+}
+
+impl fmt::Display for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Alphabet {
+    type Err = anyhow::Error;
+
+    /// Reads the name of an alphabet, as well as the registered names this accepts the names
+    /// vendors use such as "sapi" and "arpabet".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ipa" => Ok(Self::Ipa),
+            "x-sampa" | "xsampa" => Ok(Self::XSampa),
+            "x-arpabet" | "arpabet" | "x-cmu" | "cmu" => Ok(Self::Arpabet),
+            "x-microsoft-sapi" | "sapi" | "x-sapi" => Ok(Self::MicrosoftSapi),
+            _ => anyhow::bail!("'{}' isn't a phonetic alphabet we know", s),
+        }
+    }
+}
+
+impl From<Alphabet> for PhonemeAlphabet {
+    fn from(alphabet: Alphabet) -> Self {
+        match alphabet {
+            Alphabet::Ipa => Self::Ipa,
+            alphabet => Self::Other(alphabet.name().to_string()),
+        }
+    }
+}
+
+impl PhonemeAlphabet {
+    /// The alphabet if it's one that can be converted.
+    pub fn known(&self) -> Option<Alphabet> {
+        match self {
+            Self::Ipa => Some(Alphabet::Ipa),
+            Self::Other(name) => name.parse().ok(),
+        }
+    }
+}
+
+/// Result of converting the phoneme elements of a document to another alphabet.
+#[derive(Clone, Debug)]
+pub struct ConvertedPhonemes {
+    /// The rewritten SSML and its synthesisable text.
+    pub output: TransformedSsml,
+    /// Symbols which couldn't be converted and were left out.
+    pub unmapped: Vec<UnmappedSymbol>,
+}
+
+/// A symbol of a phoneme element which couldn't be converted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnmappedSymbol {
+    /// Position of the phoneme element in the source document's text in unicode scalar values.
+    pub position: usize,
+    /// The ph attribute of the element.
+    pub ph: String,
+    /// The symbol which couldn't be converted, the whole ph attribute if its alphabet isn't one
+    /// that can be converted.
+    pub symbol: String,
+    /// Byte offset of the symbol in the ph attribute.
+    pub offset: usize,
+}
+
+impl Ssml {
+    /// Rewrites the ph attribute of every phoneme element in the target alphabet. Phonemes
+    /// without an alphabet are taken to be IPA. Phonemes in an alphabet which can't be converted
+    /// are left as they are and reported along with any symbols which couldn't be converted.
+    pub fn convert_phonemes(&self, target: Alphabet) -> ConvertedPhonemes {
+        let mut position = 0;
+        let mut unmapped = vec![];
+        let mut convert = |attrs: PhonemeAttributes, position: usize| {
+            let alphabet = attrs.alphabet.clone().unwrap_or(PhonemeAlphabet::Ipa);
+            let Some(source) = alphabet.known() else {
+                unmapped.push(UnmappedSymbol {
+                    position,
+                    ph: attrs.ph.clone(),
+                    symbol: attrs.ph.clone(),
+                    offset: 0,
+                });
+                return attrs;
+            };
+            let converted = source.convert(&attrs.ph, target);
+            unmapped.extend(converted.unmapped.into_iter().map(|x| UnmappedSymbol {
+                position,
+                ph: attrs.ph.clone(),
+                symbol: x.symbol,
+                offset: x.offset,
+            }));
+            PhonemeAttributes {
+                ph: converted.ph,
+                alphabet: Some(target.into()),
+            }
+        };
+        // The close tag has the same attributes as the open tag so it's converted the same way
+        let mut open = vec![];
+        let output = self.write_ssml_with_transform(|event| match event {
+            ParserEvent::Text(text) => {
+                position += text.chars().count();
+                Some(ParserEvent::Text(text))
+            }
+            ParserEvent::Open(ParsedElement::Phoneme(attrs)) => {
+                let converted = convert(attrs, position);
+                open.push(converted.clone());
+                Some(ParserEvent::Open(ParsedElement::Phoneme(converted)))
+            }
+            ParserEvent::Close(ParsedElement::Phoneme(attrs)) => Some(ParserEvent::Close(
+                ParsedElement::Phoneme(open.pop().unwrap_or(attrs)),
+            )),
+            ParserEvent::Empty(ParsedElement::Phoneme(attrs)) => Some(ParserEvent::Empty(
+                ParsedElement::Phoneme(convert(attrs, position)),
+            )),
+            event => Some(event),
+        });
+        ConvertedPhonemes { output, unmapped }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_ssml, OffsetUnit};
+
+    fn convert(ph: &str, from: Alphabet, to: Alphabet) -> String {
+        let converted = from.convert(ph, to);
+        assert!(converted.unmapped.is_empty(), "{:?}", converted.unmapped);
+        converted.ph
+    }
+
+    #[test]
+    fn alphabet_names() {
+        for alphabet in Alphabet::ALL {
+            assert_eq!(alphabet.name().parse::<Alphabet>().unwrap(), alphabet);
+            assert_eq!(PhonemeAlphabet::from(alphabet).known(), Some(alphabet));
+        }
+        assert_eq!("sapi".parse::<Alphabet>().unwrap(), Alphabet::MicrosoftSapi);
+        assert_eq!("ARPAbet".parse::<Alphabet>().unwrap(), Alphabet::Arpabet);
+        assert!("x-klingon".parse::<Alphabet>().is_err());
+        assert_eq!(PhonemeAlphabet::Other("ups".to_string()).known(), None);
+    }
+
+    #[test]
+    fn ipa_and_x_sampa() {
+        use Alphabet::*;
+        let words = [
+            ("təˈmɑːtəʊ", "t@\"mA:t@U"),
+            ("ˈθɪŋkɪŋ", "\"TINkIN"),
+            ("ʃɛf ˌdʒuː", "SEf %dZu:"),
+            ("ɻ̩", "r\\`="),
+            ("pʰæt", "p_h{t"),
+        ];
+        for (ipa, x_sampa) in words {
+            assert_eq!(convert(ipa, Ipa, XSampa), x_sampa);
+            assert_eq!(convert(x_sampa, XSampa, Ipa), ipa);
+        }
+    }
+
+    #[test]
+    fn english_phone_sets() {
+        use Alphabet::*;
+        assert_eq!(convert("HH AH0 L OW1", Arpabet, Ipa), "həˈloʊ");
+        assert_eq!(convert("AH0 B AW1 T", Arpabet, Ipa), "əˈbaʊt");
+        assert_eq!(convert("IH0 K S T R IY1 M", Arpabet, Ipa), "ɪkˈstɹim");
+        assert_eq!(convert("həˈloʊ", Ipa, Arpabet), "HH AH0 L OW1");
+        assert_eq!(
+            convert("ˌkɒmpjuːˈteɪʃən", Ipa, Arpabet),
+            "K AA2 M P Y UW0 T EY1 SH AH0 N"
+        );
+        assert_eq!(
+            convert("HH AH0 L OW1", Arpabet, MicrosoftSapi),
+            "h ax l ow 1"
+        );
+        assert_eq!(
+            convert("h ax l ow 1", MicrosoftSapi, Arpabet),
+            "HH AH0 L OW1"
+        );
+        assert_eq!(convert("r ey 1 - d iy ow", MicrosoftSapi, Ipa), "ˈɹeɪ.dioʊ");
+        assert_eq!(convert("\"tS{t", XSampa, Arpabet), "CH AE1 T");
+    }
+
+    #[test]
+    fn unmapped_symbols() {
+        let converted = Alphabet::Ipa.convert("ʀøʒ", Alphabet::Arpabet);
+        assert_eq!(converted.ph, "ZH");
+        assert_eq!(
+            converted.unmapped,
+            [
+                Unmapped {
+                    symbol: "ʀ".to_string(),
+                    offset: 0,
+                },
+                Unmapped {
+                    symbol: "ø".to_string(),
+                    offset: 2,
+                },
+            ]
+        );
+        let converted = Alphabet::Arpabet.convert("HH XX1 T2", Alphabet::Ipa);
+        assert_eq!(converted.ph, "h");
+        let symbols = converted
+            .unmapped
+            .iter()
+            .map(|x| (x.symbol.as_str(), x.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(symbols, [("XX1", 3), ("T2", 7)]);
+
+        let symbols = Alphabet::XSampa.symbols("t@ $");
+        assert_eq!(symbols[3].text, "$");
+        assert_eq!(symbols[3].range, 3..4);
+        assert_eq!(symbols[3].ipa, None);
+        assert_eq!(
+            Alphabet::XSampa.invalid_symbol("t@ $").map(|x| x.range),
+            Some(3..4)
+        );
+        assert_eq!(Alphabet::Ipa.invalid_symbol("həˈloʊ"), None);
+    }
+
+    #[test]
+    fn ipa_diacritics() {
+        for ph in [
+            "aɪ̯", "ɐ̯", "t̪", "n̥", "ɫ", "ʧ", "ʤ", "ʦ", "pʼ", "ma˥˩", "kʰ", "ɛ̃", "ŋ̍", "ɔˤ", "e˞",
+        ] {
+            assert_eq!(Alphabet::Ipa.invalid_symbol(ph), None, "{}", ph);
+        }
+        let symbols = Alphabet::Ipa.symbols("aɪ̯");
+        assert_eq!(symbols[2].text, "\u{32f}");
+        assert_eq!(symbols[2].ipa, Some("\u{32f}"));
+        // Marks outside of the two blocks still aren't IPA
+        assert_eq!(
+            Alphabet::Ipa.invalid_symbol("a\u{20dd}").map(|x| x.range),
+            Some(1..4)
+        );
+        assert!(Alphabet::XSampa.invalid_symbol("t\u{32a}").is_some());
+    }
+
+    #[test]
+    fn convert_document_phonemes() {
+        let ssml = parse_ssml(
+            r#"<speak>You say <phoneme alphabet="ipa" ph="təˈmeɪtoʊ">tomato</phoneme>, I say
+            <phoneme alphabet="x-sampa" ph="t@&quot;mA:t@U">tomato</phoneme>,
+            <phoneme ph="ʀø">r</phoneme> <phoneme alphabet="x-klingon" ph="Qapla">q</phoneme>
+            </speak>"#,
+        )
+        .unwrap();
+        let converted = ssml.convert_phonemes(Alphabet::Arpabet);
+        let written = converted.output.ssml_string;
+        assert!(written
+            .contains(r#"<phoneme ph="T AH0 M EY1 T OW0" alphabet="x-arpabet">tomato</phoneme>"#));
+        assert!(written
+            .contains(r#"<phoneme ph="T AH0 M AA1 T OW0" alphabet="x-arpabet">tomato</phoneme>"#));
+        assert!(written.contains(r#"<phoneme ph="Qapla" alphabet="x-klingon">q</phoneme>"#));
+        assert_eq!(converted.output.synthesisable_text, ssml.get_text());
+        let unmapped = converted
+            .unmapped
+            .iter()
+            .map(|x| (x.symbol.as_str(), x.position))
+            .collect::<Vec<_>>();
+        let text = ssml.get_text();
+        let r = OffsetUnit::Chars.from_byte_offset(text, text.find(" r ").unwrap() + 1);
+        assert_eq!(unmapped, [("ʀ", r), ("ø", r), ("Qapla", r + 2)]);
+    }
+}
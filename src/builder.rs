@@ -0,0 +1,304 @@
+//! Builds SSML documents in code instead of writing out the markup by hand. The builder uses the
+//! same element and attribute types the parser produces and checks each element is allowed inside
+//! its parent, so a document that builds successfully is one the parser would accept.
+//!
+//! ```
+//! use ssml_parser::builder::SsmlBuilder;
+//! use ssml_parser::elements::*;
+//!
+//! let ssml = SsmlBuilder::speak()
+//!     .lang("en-GB")
+//!     .sentence(|s| {
+//!         s.text("Hello")
+//!             .break_time(TimeDesignation::Milliseconds(300.0))
+//!             .prosody(
+//!                 ProsodyAttributes {
+//!                     rate: Some(RateRange::Strength(RateStrength::Slow)),
+//!                     ..Default::default()
+//!                 },
+//!                 |p| p.text("world"),
+//!             )
+//!     })
+//!     .build()
+//!     .unwrap();
+//!
+//! assert_eq!(ssml.get_text(), "Helloworld");
+//! println!("{}", ssml.write_ssml());
+//! ```
+use crate::elements::*;
+use crate::error::{Position, SsmlError};
+use crate::language_tag::LanguageTag;
+use crate::{ParserLogEvent, Ssml};
+use std::collections::BTreeMap;
+
+/// Builder for an SSML document. Elements which contain other content take a function which is
+/// given the builder to add the element's content, once the function returns the element is
+/// closed.
+///
+/// Errors such as placing an element somewhere it isn't allowed are reported by `build`.
+#[derive(Clone, Debug)]
+pub struct SsmlBuilder {
+    /// Attributes of the root speak element
+    speak: SpeakAttributes,
+    /// Text of the document with all tags removed
+    text: String,
+    /// Events for everything inside the speak element
+    event_log: Vec<ParserLogEvent>,
+    /// Elements currently open, the first is always the speak element
+    open_tags: Vec<SsmlElement>,
+    /// First error found while building
+    error: Option<SsmlError>,
+}
+
+impl SsmlBuilder {
+    /// Starts a document with a speak element using SSML version 1.1.
+    pub fn speak() -> Self {
+        Self::speak_with(SpeakAttributes {
+            lang: None,
+            base: None,
+            on_lang_failure: None,
+            version: "1.1".to_string(),
+            start_mark: None,
+            end_mark: None,
+            xml_root_attrs: BTreeMap::new(),
+        })
+    }
+
+    /// Starts a document with a speak element with the given attributes.
+    pub fn speak_with(speak: SpeakAttributes) -> Self {
+        Self {
+            speak,
+            text: String::new(),
+            event_log: vec![],
+            open_tags: vec![SsmlElement::Speak],
+            error: None,
+        }
+    }
+
+    /// Sets the language of the document, this is the `xml:lang` attribute of the speak element.
+    /// Use `SsmlBuilder::language` to change language for part of the document. An invalid
+    /// language tag is reported when the document is built.
+    pub fn lang(mut self, lang: impl AsRef<str>) -> Self {
+        let lang = lang.as_ref();
+        match lang.parse::<LanguageTag>() {
+            Ok(tag) => self.speak.lang = Some(tag),
+            Err(e) if self.error.is_none() => {
+                self.error = Some(SsmlError::InvalidAttributeValue {
+                    element: SsmlElement::Speak.to_string(),
+                    attribute: "xml:lang".to_string(),
+                    value: lang.to_string(),
+                    reason: e.to_string(),
+                    position: Position::default(),
+                });
+            }
+            Err(_) => {}
+        }
+        self
+    }
+
+    /// Adds text to the current element. The text is escaped when the SSML is written out.
+    pub fn text(mut self, text: impl AsRef<str>) -> Self {
+        self.push_text(text.as_ref());
+        self
+    }
+
+    /// Adds an element containing the content added by `content`. This can be used for any
+    /// element including custom ones.
+    pub fn element(mut self, element: ParsedElement, content: impl FnOnce(Self) -> Self) -> Self {
+        let ty = SsmlElement::from(&element);
+        self.check_nesting(&ty);
+        if matches!(ty, SsmlElement::Sentence | SsmlElement::Paragraph)
+            && !(self.text.is_empty() || self.text.ends_with(char::is_whitespace))
+        {
+            // Same as the parser, keep a space between sentences so the words don't run together
+            self.push_text(" ");
+        }
+        self.event_log.push(ParserLogEvent::Open(element.clone()));
+        self.open_tags.push(ty);
+        let mut builder = content(self);
+        builder.open_tags.pop();
+        builder.event_log.push(ParserLogEvent::Close(element));
+        builder
+    }
+
+    /// Adds an element with no content such as `<break/>` or `<mark/>`.
+    pub fn empty(mut self, element: ParsedElement) -> Self {
+        self.check_nesting(&SsmlElement::from(&element));
+        self.event_log.push(ParserLogEvent::Empty(element));
+        self
+    }
+
+    /// Adds a paragraph, `<p>`, containing the content added by `content`.
+    pub fn paragraph(self, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Paragraph(Default::default()), content)
+    }
+
+    /// Adds a sentence, `<s>`, containing the content added by `content`.
+    pub fn sentence(self, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Sentence(Default::default()), content)
+    }
+
+    /// Adds a lang element changing the language of the content added by `content`.
+    pub fn language(self, attrs: LangAttributes, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Lang(attrs), content)
+    }
+
+    /// Adds a voice element changing the voice for the content added by `content`.
+    pub fn voice(self, attrs: VoiceAttributes, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Voice(attrs), content)
+    }
+
+    /// Adds a prosody element changing the prosody of the content added by `content`.
+    pub fn prosody(self, attrs: ProsodyAttributes, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Prosody(attrs), content)
+    }
+
+    /// Adds an emphasis element emphasising the content added by `content`.
+    pub fn emphasis(self, attrs: EmphasisAttributes, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Emphasis(attrs), content)
+    }
+
+    /// Adds an audio element, the content added by `content` is spoken if the audio can't be
+    /// played.
+    pub fn audio(self, attrs: AudioAttributes, content: impl FnOnce(Self) -> Self) -> Self {
+        self.element(ParsedElement::Audio(attrs), content)
+    }
+
+    /// Adds a say-as element around the text.
+    pub fn say_as(self, attrs: SayAsAttributes, text: impl AsRef<str>) -> Self {
+        self.element(ParsedElement::SayAs(attrs), |b| b.text(text))
+    }
+
+    /// Adds a phoneme element giving the pronunciation of the text.
+    pub fn phoneme(self, attrs: PhonemeAttributes, text: impl AsRef<str>) -> Self {
+        self.element(ParsedElement::Phoneme(attrs), |b| b.text(text))
+    }
+
+    /// Adds a sub element so the alias is spoken instead of the text.
+    pub fn sub(self, alias: impl Into<String>, text: impl AsRef<str>) -> Self {
+        let attrs = SubAttributes {
+            alias: alias.into(),
+        };
+        self.element(ParsedElement::Sub(attrs), |b| b.text(text))
+    }
+
+    /// Adds a break with the given duration.
+    pub fn break_time(self, time: TimeDesignation) -> Self {
+        self.empty(ParsedElement::Break(BreakAttributes {
+            strength: None,
+            time: Some(time),
+        }))
+    }
+
+    /// Adds a break with the given strength.
+    pub fn break_strength(self, strength: Strength) -> Self {
+        self.empty(ParsedElement::Break(BreakAttributes {
+            strength: Some(strength),
+            time: None,
+        }))
+    }
+
+    /// Adds a mark with the given name.
+    pub fn mark(self, name: impl Into<String>) -> Self {
+        self.empty(ParsedElement::Mark(MarkAttributes { name: name.into() }))
+    }
+
+    /// Finishes the document, returning the first error found while building it.
+    pub fn build(self) -> Result<Ssml, SsmlError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let speak = ParsedElement::Speak(self.speak);
+        let mut event_log = Vec::with_capacity(self.event_log.len() + 2);
+        event_log.push(ParserLogEvent::Open(speak.clone()));
+        event_log.extend(self.event_log);
+        event_log.push(ParserLogEvent::Close(speak));
+        Ok(Ssml::new(self.text, event_log))
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.event_log
+            .push(ParserLogEvent::Text((start, self.text.len())));
+    }
+
+    fn check_nesting(&mut self, child: &SsmlElement) {
+        let parent = self.open_tags.last().unwrap_or(&SsmlElement::Speak);
+        if self.error.is_none() && !parent.can_contain(child) {
+            self.error = Some(SsmlError::InvalidNesting {
+                parent: parent.to_string(),
+                child: child.to_string(),
+                position: Position::default(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_ssml;
+
+    #[test]
+    fn build_document() {
+        let ssml = SsmlBuilder::speak()
+            .lang("en-GB")
+            .paragraph(|p| {
+                p.sentence(|s| {
+                    s.text("Fish & chips")
+                        .break_strength(Strength::Strong)
+                        .emphasis(
+                            EmphasisAttributes {
+                                level: Some(EmphasisLevel::Strong),
+                            },
+                            |e| e.text("<now>"),
+                        )
+                })
+                .sentence(|s| s.sub("World Wide Web", "WWW").mark("end"))
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(ssml.get_text(), "Fish & chips<now> WWW");
+        let written = ssml.write_ssml();
+        assert!(written.contains("Fish &amp; chips"));
+        assert!(written.contains("&lt;now&gt;"));
+
+        let reparsed = parse_ssml(&written).unwrap();
+        assert_eq!(reparsed.get_text(), ssml.get_text());
+        assert_eq!(reparsed.tags, ssml.tags);
+        match &ssml.tags[0].element {
+            ParsedElement::Speak(attrs) => assert_eq!(attrs.lang.as_ref().unwrap(), "en-GB"),
+            e => panic!("Expected speak element got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn invalid_nesting() {
+        let error = SsmlBuilder::speak()
+            .sentence(|s| s.paragraph(|p| p.text("Hello")))
+            .build()
+            .unwrap_err();
+
+        match error {
+            SsmlError::InvalidNesting { parent, child, .. } => {
+                assert_eq!(parent, "s");
+                assert_eq!(child, "p");
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+
+        assert!(SsmlBuilder::speak()
+            .say_as(
+                SayAsAttributes {
+                    interpret_as: InterpretAs::Characters,
+                    format: None,
+                    detail: None,
+                },
+                "abc",
+            )
+            .build()
+            .is_ok());
+    }
+}
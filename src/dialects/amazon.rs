@@ -0,0 +1,601 @@
+//! The Amazon Polly extensions to SSML. Documentation for these elements can be found
+//! [here](https://docs.aws.amazon.com/polly/latest/dg/supportedtags.html).
+use crate::error::SsmlError;
+use crate::parser::{get_required_attribute, parse_attribute, parse_required_attribute};
+use anyhow::bail;
+use quick_xml::events::BytesStart;
+use quick_xml::reader::Reader;
+use std::fmt::{self, Display};
+use std::io;
+use std::str::FromStr;
+
+/// An element in the `amazon:` namespace.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "element", content = "attributes"))]
+pub enum AmazonElement {
+    /// The `<amazon:breath/>` element, inserts a single breath.
+    #[cfg_attr(feature = "serde", serde(rename = "amazon:breath"))]
+    Breath(BreathAttributes),
+    /// The `<amazon:auto-breaths></amazon:auto-breaths>` element, adds breaths to the contained
+    /// text where they would naturally occur.
+    #[cfg_attr(feature = "serde", serde(rename = "amazon:auto-breaths"))]
+    AutoBreaths(AutoBreathsAttributes),
+    /// The `<amazon:domain></amazon:domain>` element, speaks the contained text in a style suited
+    /// to the domain.
+    #[cfg_attr(feature = "serde", serde(rename = "amazon:domain"))]
+    Domain(DomainAttributes),
+    /// The `<amazon:effect></amazon:effect>` element, applies an effect to the contained text.
+    #[cfg_attr(feature = "serde", serde(rename = "amazon:effect"))]
+    Effect(EffectAttributes),
+}
+
+impl AmazonElement {
+    /// Name of the element including the `amazon:` prefix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Breath(_) => "amazon:breath",
+            Self::AutoBreaths(_) => "amazon:auto-breaths",
+            Self::Domain(_) => "amazon:domain",
+            Self::Effect(_) => "amazon:effect",
+        }
+    }
+
+    /// The XML attribute string used when writing the element back out.
+    pub fn attribute_string(&self) -> String {
+        match self {
+            Self::Breath(attr) => format!("{}", attr),
+            Self::AutoBreaths(attr) => format!("{}", attr),
+            Self::Domain(attr) => format!("{}", attr),
+            Self::Effect(attr) => format!("{}", attr),
+        }
+    }
+}
+
+/// Attributes of the `<amazon:breath/>` element.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct BreathAttributes {
+    /// How long the breath lasts.
+    pub duration: Option<BreathDuration>,
+    /// How loud the breath is.
+    pub volume: Option<BreathVolume>,
+}
+
+impl Display for BreathAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(duration) = self.duration {
+            write!(f, " duration=\"{}\"", duration)?;
+        }
+        if let Some(volume) = self.volume {
+            write!(f, " volume=\"{}\"", volume)?;
+        }
+        Ok(())
+    }
+}
+
+/// Attributes of the `<amazon:auto-breaths>` element.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct AutoBreathsAttributes {
+    /// How long each breath lasts.
+    pub duration: Option<BreathDuration>,
+    /// How loud the breaths are.
+    pub volume: Option<BreathVolume>,
+    /// How often breaths are inserted.
+    pub frequency: Option<BreathFrequency>,
+}
+
+impl Display for AutoBreathsAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(duration) = self.duration {
+            write!(f, " duration=\"{}\"", duration)?;
+        }
+        if let Some(volume) = self.volume {
+            write!(f, " volume=\"{}\"", volume)?;
+        }
+        if let Some(frequency) = self.frequency {
+            write!(f, " frequency=\"{}\"", frequency)?;
+        }
+        Ok(())
+    }
+}
+
+/// Length of a breath.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum BreathDuration {
+    /// The default length
+    Default,
+    /// Extra short (x-short)
+    ExtraShort,
+    /// Short
+    Short,
+    /// Medium
+    Medium,
+    /// Long
+    Long,
+    /// Extra long (x-long)
+    ExtraLong,
+}
+
+impl Display for BreathDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Default => "default",
+                Self::ExtraShort => "x-short",
+                Self::Short => "short",
+                Self::Medium => "medium",
+                Self::Long => "long",
+                Self::ExtraLong => "x-long",
+            }
+        )
+    }
+}
+
+impl FromStr for BreathDuration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "x-short" => Ok(Self::ExtraShort),
+            "short" => Ok(Self::Short),
+            "medium" => Ok(Self::Medium),
+            "long" => Ok(Self::Long),
+            "x-long" => Ok(Self::ExtraLong),
+            e => bail!("Unrecognised breath duration {}", e),
+        }
+    }
+}
+
+/// Loudness of a breath.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum BreathVolume {
+    /// The default volume
+    Default,
+    /// Extra soft (x-soft)
+    ExtraSoft,
+    /// Soft
+    Soft,
+    /// Medium
+    Medium,
+    /// Loud
+    Loud,
+    /// Extra loud (x-loud)
+    ExtraLoud,
+}
+
+impl Display for BreathVolume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Default => "default",
+                Self::ExtraSoft => "x-soft",
+                Self::Soft => "soft",
+                Self::Medium => "medium",
+                Self::Loud => "loud",
+                Self::ExtraLoud => "x-loud",
+            }
+        )
+    }
+}
+
+impl FromStr for BreathVolume {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "x-soft" => Ok(Self::ExtraSoft),
+            "soft" => Ok(Self::Soft),
+            "medium" => Ok(Self::Medium),
+            "loud" => Ok(Self::Loud),
+            "x-loud" => Ok(Self::ExtraLoud),
+            e => bail!("Unrecognised breath volume {}", e),
+        }
+    }
+}
+
+/// How often breaths are added by `<amazon:auto-breaths>`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum BreathFrequency {
+    /// The default frequency
+    Default,
+    /// Extra low (x-low)
+    ExtraLow,
+    /// Low
+    Low,
+    /// Medium
+    Medium,
+    /// High
+    High,
+    /// Extra high (x-high)
+    ExtraHigh,
+}
+
+impl Display for BreathFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Default => "default",
+                Self::ExtraLow => "x-low",
+                Self::Low => "low",
+                Self::Medium => "medium",
+                Self::High => "high",
+                Self::ExtraHigh => "x-high",
+            }
+        )
+    }
+}
+
+impl FromStr for BreathFrequency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "x-low" => Ok(Self::ExtraLow),
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            "x-high" => Ok(Self::ExtraHigh),
+            e => bail!("Unrecognised breath frequency {}", e),
+        }
+    }
+}
+
+/// Attributes of the `<amazon:domain>` element.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct DomainAttributes {
+    /// The speaking style to use, this is a required attribute.
+    pub name: Domain,
+}
+
+impl Display for DomainAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " name=\"{}\"", self.name)
+    }
+}
+
+/// Speaking styles supported by `<amazon:domain>`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum Domain {
+    /// Newscaster speaking style
+    News,
+    /// Conversational speaking style
+    Conversational,
+}
+
+impl Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::News => "news",
+                Self::Conversational => "conversational",
+            }
+        )
+    }
+}
+
+impl FromStr for Domain {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "news" => Ok(Self::News),
+            "conversational" => Ok(Self::Conversational),
+            e => bail!("Unrecognised domain {}", e),
+        }
+    }
+}
+
+/// Attributes of the `<amazon:effect>` element. Polly uses one attribute per element, when
+/// parsing at least one of them has to be present.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct EffectAttributes {
+    /// A named effect such as whispering.
+    pub name: Option<Effect>,
+    /// Changes the phonation of the speech.
+    pub phonation: Option<Phonation>,
+    /// Changes the length of the speaker's vocal tract, making the voice sound larger or smaller.
+    pub vocal_tract_length: Option<VocalTractLength>,
+}
+
+impl Display for EffectAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.name {
+            write!(f, " name=\"{}\"", name)?;
+        }
+        if let Some(phonation) = self.phonation {
+            write!(f, " phonation=\"{}\"", phonation)?;
+        }
+        if let Some(length) = self.vocal_tract_length {
+            write!(f, " vocal-tract-length=\"{}\"", length)?;
+        }
+        Ok(())
+    }
+}
+
+/// Named effects supported by `<amazon:effect>`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum Effect {
+    /// Speak the text as a whisper
+    Whispered,
+    /// Dynamic range compression, makes the speech louder and clearer in noisy environments
+    Drc,
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Whispered => "whispered",
+                Self::Drc => "drc",
+            }
+        )
+    }
+}
+
+impl FromStr for Effect {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whispered" => Ok(Self::Whispered),
+            "drc" => Ok(Self::Drc),
+            e => bail!("Unrecognised effect {}", e),
+        }
+    }
+}
+
+/// Phonation values supported by `<amazon:effect>`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum Phonation {
+    /// Soft phonation
+    Soft,
+}
+
+impl Display for Phonation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Soft => write!(f, "soft"),
+        }
+    }
+}
+
+impl FromStr for Phonation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "soft" => Ok(Self::Soft),
+            e => bail!("Unrecognised phonation {}", e),
+        }
+    }
+}
+
+/// Change in vocal tract length as a percentage.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum VocalTractLength {
+    /// Change relative to the voice's normal length, from -50% to +100%
+    Relative(i32),
+    /// Length as a percentage of the voice's normal length, from 50% to 200%
+    Absolute(u32),
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for VocalTractLength {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &fake::Faker, rng: &mut R) -> Self {
+        use fake::RngExt;
+        if rng.random_bool(0.5) {
+            Self::Relative(rng.random_range(-50..=100))
+        } else {
+            Self::Absolute(rng.random_range(50..=200))
+        }
+    }
+}
+
+impl Display for VocalTractLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Relative(change) => write!(f, "{:+}%", change),
+            Self::Absolute(length) => write!(f, "{}%", length),
+        }
+    }
+}
+
+impl FromStr for VocalTractLength {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(value) = s.strip_suffix('%') else {
+            bail!("Vocal tract length must be a percentage");
+        };
+        if value.starts_with(['+', '-']) {
+            let change: i32 = value.parse()?;
+            if !(-50..=100).contains(&change) {
+                bail!("Vocal tract length change must be between -50% and +100%");
+            }
+            Ok(Self::Relative(change))
+        } else {
+            let length: u32 = value.parse()?;
+            if !(50..=200).contains(&length) {
+                bail!("Vocal tract length must be between 50% and 200%");
+            }
+            Ok(Self::Absolute(length))
+        }
+    }
+}
+
+/// Parses an element in the `amazon:` namespace, returns `None` for elements which aren't part
+/// of the Amazon dialect.
+pub(crate) fn parse_element<R: io::BufRead>(
+    name: &str,
+    elem: &BytesStart,
+    reader: &Reader<R>,
+) -> Result<Option<AmazonElement>, SsmlError> {
+    let element = match name {
+        "amazon:breath" => AmazonElement::Breath(BreathAttributes {
+            duration: parse_attribute(elem, reader, "duration", BreathDuration::from_str)?,
+            volume: parse_attribute(elem, reader, "volume", BreathVolume::from_str)?,
+        }),
+        "amazon:auto-breaths" => AmazonElement::AutoBreaths(AutoBreathsAttributes {
+            duration: parse_attribute(elem, reader, "duration", BreathDuration::from_str)?,
+            volume: parse_attribute(elem, reader, "volume", BreathVolume::from_str)?,
+            frequency: parse_attribute(elem, reader, "frequency", BreathFrequency::from_str)?,
+        }),
+        "amazon:domain" => AmazonElement::Domain(DomainAttributes {
+            name: parse_required_attribute(elem, reader, "name", Domain::from_str)?,
+        }),
+        "amazon:effect" => {
+            let attrs = EffectAttributes {
+                name: parse_attribute(elem, reader, "name", Effect::from_str)?,
+                phonation: parse_attribute(elem, reader, "phonation", Phonation::from_str)?,
+                vocal_tract_length: parse_attribute(
+                    elem,
+                    reader,
+                    "vocal-tract-length",
+                    VocalTractLength::from_str,
+                )?,
+            };
+            if attrs == EffectAttributes::default() {
+                // Report the most commonly used attribute as missing
+                get_required_attribute(elem, reader, "name")?;
+            }
+            AmazonElement::Effect(attrs)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(element))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialects::{Dialect, DialectElement};
+    use crate::elements::ParsedElement;
+    use crate::parser::SsmlParserBuilder;
+    use fake::{Fake, Faker};
+
+    fn parse_amazon(ssml: &str) -> Result<crate::Ssml, SsmlError> {
+        SsmlParserBuilder::default()
+            .dialect(Dialect::Amazon)
+            .build()
+            .unwrap()
+            .parse(ssml)
+    }
+
+    #[test]
+    fn amazon_conversions() {
+        for _ in 0..30 {
+            let element: AmazonElement = Faker.fake();
+            if element == AmazonElement::Effect(EffectAttributes::default()) {
+                continue;
+            }
+            let xml = format!(
+                "<speak><{}{}>Hello</{}></speak>",
+                element.name(),
+                element.attribute_string(),
+                element.name()
+            );
+            println!("{}", xml);
+
+            let ssml = parse_amazon(&xml).unwrap();
+            assert_eq!(
+                ssml.tags[1].element,
+                ParsedElement::Dialect(DialectElement::Amazon(element))
+            );
+        }
+    }
+
+    #[test]
+    fn amazon_tags() {
+        let ssml = r#"<speak>
+            <amazon:domain name="news">Breaking news.</amazon:domain>
+            <amazon:auto-breaths frequency="low">Some long text</amazon:auto-breaths>
+            <amazon:effect name="whispered">A secret<amazon:breath duration="x-long"/></amazon:effect>
+        </speak>"#;
+
+        // Without the dialect everything is a custom tag
+        let ssml_custom = crate::parse_ssml(ssml).unwrap();
+        assert!(ssml_custom
+            .tags()
+            .skip(1)
+            .all(|x| matches!(x.element, ParsedElement::Custom(_))));
+
+        let ssml = parse_amazon(ssml).unwrap();
+        let elements = ssml
+            .tags()
+            .skip(1)
+            .map(|x| x.element.clone())
+            .collect::<Vec<_>>();
+        let expected = [
+            AmazonElement::Domain(DomainAttributes { name: Domain::News }),
+            AmazonElement::AutoBreaths(AutoBreathsAttributes {
+                frequency: Some(BreathFrequency::Low),
+                ..Default::default()
+            }),
+            AmazonElement::Effect(EffectAttributes {
+                name: Some(Effect::Whispered),
+                ..Default::default()
+            }),
+            AmazonElement::Breath(BreathAttributes {
+                duration: Some(BreathDuration::ExtraLong),
+                volume: None,
+            }),
+        ]
+        .map(|x| ParsedElement::Dialect(DialectElement::Amazon(x)));
+        assert_eq!(elements, expected);
+
+        // And it writes back out the same
+        let rewritten = parse_amazon(&ssml.write_ssml()).unwrap();
+        assert_eq!(rewritten.tags, ssml.tags);
+    }
+
+    #[test]
+    fn invalid_amazon_attributes() {
+        let err =
+            parse_amazon(r#"<speak><amazon:effect name="shouted">Hi</amazon:effect></speak>"#)
+                .unwrap_err();
+        assert_eq!(err.element(), Some("amazon:effect"));
+        assert_eq!(err.attribute(), Some("name"));
+
+        let err = parse_amazon(r#"<speak><amazon:domain>Hi</amazon:domain></speak>"#).unwrap_err();
+        assert!(matches!(err, SsmlError::MissingAttribute { .. }));
+
+        let err = parse_amazon(
+            r#"<speak><amazon:effect vocal-tract-length="+150%">Hi</amazon:effect></speak>"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.attribute(), Some("vocal-tract-length"));
+
+        parse_amazon(
+            r#"<speak><amazon:effect vocal-tract-length="-10%">Hi</amazon:effect></speak>"#,
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,504 @@
+//! The Google Cloud Text-to-Speech extensions to SSML. Documentation for these elements can be
+//! found [here](https://cloud.google.com/text-to-speech/docs/ssml).
+//!
+//! Google adds the `<par>`, `<seq>` and `<media>` elements for playing speech and audio in
+//! parallel or in sequence. A `<media>` element contains a single `<speak>` or `<audio>` element,
+//! so with this dialect enabled `<speak>` is allowed inside `<media>`. Google also supports a
+//! fixed list of say-as interpret-as values which the dialect checks say-as elements against.
+use crate::elements::{ParsedElement, Sign, SsmlElement, TimeDesignation};
+use crate::error::{Position, SsmlError};
+use crate::parser::{get_attribute, parse_attribute, parse_decibel};
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use quick_xml::escape::escape;
+use quick_xml::events::BytesStart;
+use quick_xml::reader::Reader;
+use regex::Regex;
+use std::fmt::{self, Display};
+use std::io;
+use std::str::FromStr;
+
+/// A Google timing element.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "element", content = "attributes", rename_all = "lowercase")
+)]
+pub enum GoogleElement {
+    /// The `<par></par>` element, its children are played at the same time.
+    Par,
+    /// The `<seq></seq>` element, its children are played one after the other.
+    Seq,
+    /// The `<media></media>` element, a piece of speech or audio within a `<par>` or `<seq>`.
+    Media(MediaAttributes),
+}
+
+impl GoogleElement {
+    /// Name of the element.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Par => "par",
+            Self::Seq => "seq",
+            Self::Media(_) => "media",
+        }
+    }
+
+    /// The XML attribute string used when writing the element back out.
+    pub fn attribute_string(&self) -> String {
+        match self {
+            Self::Par | Self::Seq => String::new(),
+            Self::Media(attr) => format!("{}", attr),
+        }
+    }
+}
+
+/// Attributes of the `<media>` element.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct MediaAttributes {
+    /// Identifier other media elements can use to time themselves relative to this one.
+    pub id: Option<String>,
+    /// When the media starts, the default is the start of its container.
+    pub begin: Option<MediaTime>,
+    /// When the media ends, the default is when the media finishes playing.
+    pub end: Option<MediaTime>,
+    /// How many times the media is played, the default is once.
+    pub repeat_count: Option<f32>,
+    /// How long the media is repeated for.
+    pub repeat_dur: Option<TimeDesignation>,
+    /// Change in volume in decibels between -40dB and +40dB.
+    pub sound_level: Option<f32>,
+    /// How long the media takes to fade in.
+    pub fade_in_dur: Option<TimeDesignation>,
+    /// How long the media takes to fade out.
+    pub fade_out_dur: Option<TimeDesignation>,
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for MediaAttributes {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::{Fake, RngExt};
+        let mut time = || {
+            if rng.random_bool(0.5) {
+                Some(TimeDesignation::Milliseconds(
+                    rng.random_range(0..10000) as f32
+                ))
+            } else {
+                None
+            }
+        };
+        let repeat_dur = time();
+        let fade_in_dur = time();
+        let fade_out_dur = time();
+        Self {
+            id: if rng.random_bool(0.5) {
+                Some(format!("media_{}", rng.random_range(0..100)))
+            } else {
+                None
+            },
+            begin: f.fake_with_rng(rng),
+            end: f.fake_with_rng(rng),
+            repeat_count: if rng.random_bool(0.5) {
+                Some(rng.random_range(1..10) as f32)
+            } else {
+                None
+            },
+            repeat_dur,
+            sound_level: if rng.random_bool(0.5) {
+                Some(rng.random_range(-40..=40) as f32)
+            } else {
+                None
+            },
+            fade_in_dur,
+            fade_out_dur,
+        }
+    }
+}
+
+impl Display for MediaAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(id) = &self.id {
+            write!(f, " xml:id=\"{}\"", escape(id))?;
+        }
+        if let Some(begin) = &self.begin {
+            write!(f, " begin=\"{}\"", escape(begin.to_string()))?;
+        }
+        if let Some(end) = &self.end {
+            write!(f, " end=\"{}\"", escape(end.to_string()))?;
+        }
+        if let Some(count) = self.repeat_count {
+            write!(f, " repeatCount=\"{}\"", count)?;
+        }
+        if let Some(dur) = self.repeat_dur {
+            write!(f, " repeatDur=\"{}\"", dur)?;
+        }
+        if let Some(level) = self.sound_level {
+            write!(f, " soundLevel=\"{:+}dB\"", level)?;
+        }
+        if let Some(dur) = self.fade_in_dur {
+            write!(f, " fadeInDur=\"{}\"", dur)?;
+        }
+        if let Some(dur) = self.fade_out_dur {
+            write!(f, " fadeOutDur=\"{}\"", dur)?;
+        }
+        Ok(())
+    }
+}
+
+/// A time in a `<media>` element's begin or end attribute. This is either an offset from the
+/// start of the containing element such as "2.5s", or relative to the start or end of another
+/// media element such as "intro.end-0.5s".
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaTime {
+    /// The media element and event the time is relative to, when this isn't set the time is
+    /// relative to the start of the containing element.
+    pub sync: Option<SyncBase>,
+    /// Offset from the sync base.
+    pub offset: Option<(Sign, TimeDesignation)>,
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for MediaTime {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::{Fake, RngExt};
+        let sync: Option<SyncBase> = f.fake_with_rng(rng);
+        let offset = if sync.is_none() || rng.random_bool(0.5) {
+            Some((
+                f.fake_with_rng(rng),
+                TimeDesignation::Milliseconds(rng.random_range(0..10000) as f32),
+            ))
+        } else {
+            None
+        };
+        Self { sync, offset }
+    }
+}
+
+impl Display for MediaTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.sync, &self.offset) {
+            (Some(sync), Some((sign, time))) => write!(f, "{}{}{}", sync, sign, time),
+            (Some(sync), None) => write!(f, "{}", sync),
+            (None, Some((Sign::Plus, time))) => write!(f, "{}", time),
+            (None, Some((Sign::Minus, time))) => write!(f, "-{}", time),
+            (None, None) => write!(f, "0s"),
+        }
+    }
+}
+
+impl FromStr for MediaTime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref TIME_RE: Regex = Regex::new(
+                r"^(?:([\w.-]+)\.(begin|end))?\s*(?:([+-])?\s*((?:\d*\.)?\d+\s*(?:s|ms)))?$"
+            )
+            .unwrap();
+        }
+        let caps = TIME_RE
+            .captures(s.trim())
+            .context("value must be a time or a media id followed by .begin or .end")?;
+        let sync = match (caps.get(1), caps.get(2)) {
+            (Some(id), Some(event)) => Some(SyncBase {
+                id: id.as_str().to_string(),
+                event: event.as_str().parse()?,
+            }),
+            _ => None,
+        };
+        let offset = match caps.get(4) {
+            Some(time) => {
+                let sign = match caps.get(3).map(|x| x.as_str()) {
+                    Some("-") => Sign::Minus,
+                    None if sync.is_some() => bail!("offset from a media element needs a sign"),
+                    _ => Sign::Plus,
+                };
+                Some((sign, time.as_str().parse()?))
+            }
+            None => None,
+        };
+        if sync.is_none() && offset.is_none() {
+            bail!("value must be a time or a media id followed by .begin or .end");
+        }
+        Ok(Self { sync, offset })
+    }
+}
+
+/// The start or end of a media element, used to time media relative to one another.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SyncBase {
+    /// The `xml:id` of the media element.
+    pub id: String,
+    /// Whether the time is relative to the start or end of the media.
+    pub event: SyncEvent,
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for SyncBase {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::{Fake, RngExt};
+        Self {
+            id: format!("media_{}", rng.random_range(0..100)),
+            event: f.fake_with_rng(rng),
+        }
+    }
+}
+
+impl Display for SyncBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.id, self.event)
+    }
+}
+
+/// Events of a media element other media can be timed relative to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum SyncEvent {
+    /// When the media starts
+    Begin,
+    /// When the media ends
+    End,
+}
+
+impl Display for SyncEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Begin => write!(f, "begin"),
+            Self::End => write!(f, "end"),
+        }
+    }
+}
+
+impl FromStr for SyncEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "begin" => Ok(Self::Begin),
+            "end" => Ok(Self::End),
+            e => bail!("Unrecognised media event {}", e),
+        }
+    }
+}
+
+/// The say-as interpret-as values supported by Google.
+pub const INTERPRET_AS_VALUES: &[&str] = &[
+    "currency",
+    "telephone",
+    "verbatim",
+    "spell-out",
+    "date",
+    "characters",
+    "cardinal",
+    "ordinal",
+    "fraction",
+    "expletive",
+    "bleep",
+    "unit",
+    "time",
+];
+
+fn parse_repeat_count(s: &str) -> anyhow::Result<f32> {
+    let count: f32 = s.parse()?;
+    if count <= 0.0 || !count.is_finite() {
+        bail!("Repeat count must be a positive number");
+    }
+    Ok(count)
+}
+
+fn parse_sound_level(s: &str) -> anyhow::Result<f32> {
+    let level = parse_decibel(s)?;
+    if !(-40.0..=40.0).contains(&level) {
+        bail!("Sound level must be between -40dB and +40dB");
+    }
+    Ok(level)
+}
+
+/// Parses a Google timing element, returns `None` for elements which aren't part of the Google
+/// dialect.
+pub(crate) fn parse_element<R: io::BufRead>(
+    name: &str,
+    elem: &BytesStart,
+    reader: &Reader<R>,
+) -> Result<Option<GoogleElement>, SsmlError> {
+    let element = match name {
+        "par" => GoogleElement::Par,
+        "seq" => GoogleElement::Seq,
+        "media" => GoogleElement::Media(MediaAttributes {
+            id: get_attribute(elem, reader, "xml:id")?,
+            begin: parse_attribute(elem, reader, "begin", MediaTime::from_str)?,
+            end: parse_attribute(elem, reader, "end", MediaTime::from_str)?,
+            repeat_count: parse_attribute(elem, reader, "repeatCount", parse_repeat_count)?,
+            repeat_dur: parse_attribute(elem, reader, "repeatDur", TimeDesignation::from_str)?,
+            sound_level: parse_attribute(elem, reader, "soundLevel", parse_sound_level)?,
+            fade_in_dur: parse_attribute(elem, reader, "fadeInDur", TimeDesignation::from_str)?,
+            fade_out_dur: parse_attribute(elem, reader, "fadeOutDur", TimeDesignation::from_str)?,
+        }),
+        _ => return Ok(None),
+    };
+    Ok(Some(element))
+}
+
+/// Nesting rules for the timing elements, returns `None` if neither element is a timing element.
+pub(crate) fn can_contain(parent: &SsmlElement, child: &SsmlElement) -> Option<bool> {
+    let name = |element: &SsmlElement| match element {
+        SsmlElement::Custom(name) => Some(name.clone()),
+        _ => None,
+    };
+    let is_timing =
+        |element: &SsmlElement| matches!(name(element).as_deref(), Some("par" | "seq" | "media"));
+    match name(parent).as_deref() {
+        Some("par" | "seq") => Some(is_timing(child)),
+        Some("media") => Some(matches!(child, SsmlElement::Speak | SsmlElement::Audio)),
+        // Media only makes sense inside a timing container
+        _ if name(child).as_deref() == Some("media") => Some(false),
+        _ => None,
+    }
+}
+
+/// Checks elements from the core specification use values Google supports.
+pub(crate) fn check_element(element: &ParsedElement) -> Result<(), SsmlError> {
+    if let ParsedElement::SayAs(attrs) = element {
+        if !INTERPRET_AS_VALUES.contains(&attrs.interpret_as.as_str()) {
+            return Err(SsmlError::InvalidAttributeValue {
+                element: SsmlElement::SayAs.to_string(),
+                attribute: "interpret-as".to_string(),
+                value: attrs.interpret_as.as_str().to_string(),
+                reason: "not an interpret-as value supported by Google".to_string(),
+                position: Position::default(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialects::{Dialect, DialectElement};
+    use crate::parser::SsmlParserBuilder;
+    use fake::{Fake, Faker};
+
+    fn parse_google(ssml: &str) -> Result<crate::Ssml, SsmlError> {
+        SsmlParserBuilder::default()
+            .dialect(Dialect::Google)
+            .build()
+            .unwrap()
+            .parse(ssml)
+    }
+
+    #[test]
+    fn google_conversions() {
+        for _ in 0..30 {
+            let element: GoogleElement = Faker.fake();
+            let xml = format!(
+                "<speak><par><{}{}></{}></par></speak>",
+                element.name(),
+                element.attribute_string(),
+                element.name()
+            );
+            println!("{}", xml);
+
+            let ssml = parse_google(&xml).unwrap();
+            assert_eq!(
+                ssml.tags[2].element,
+                ParsedElement::Dialect(DialectElement::Google(element))
+            );
+        }
+    }
+
+    #[test]
+    fn media_times() {
+        let time = MediaTime::from_str("intro.end-0.5s").unwrap();
+        assert_eq!(
+            time,
+            MediaTime {
+                sync: Some(SyncBase {
+                    id: "intro".to_string(),
+                    event: SyncEvent::End,
+                }),
+                offset: Some((Sign::Minus, TimeDesignation::Seconds(0.5))),
+            }
+        );
+        assert_eq!(time.to_string(), "intro.end-0.5s");
+
+        let time = MediaTime::from_str("2.5s").unwrap();
+        assert_eq!(time.sync, None);
+        assert_eq!(time.to_string(), "2.5s");
+        assert!(MediaTime::from_str("question.begin").is_ok());
+        assert!(MediaTime::from_str("intro.end 2s").is_err());
+        assert!(MediaTime::from_str("soon").is_err());
+    }
+
+    #[test]
+    fn timing_elements() {
+        let ssml = r#"<speak>
+          <par>
+            <media xml:id="question" begin="0.5s">
+              <speak>Who invented the Internet?</speak>
+            </media>
+            <media xml:id="answer" begin="question.end+2.0s">
+              <speak>The Internet was invented by cats.</speak>
+            </media>
+            <media begin="answer.end-0.2s" soundLevel="-6dB">
+              <audio src="https://actions.google.com/sounds/v1/cartoon/cartoon_boing.ogg"/>
+            </media>
+          </par>
+          <s>That's a <say-as interpret-as="expletive">darn</say-as> lie.</s>
+        </speak>"#;
+
+        // Nested speak elements aren't allowed without the dialect
+        assert!(crate::parse_ssml(ssml).is_err());
+
+        let parsed = parse_google(ssml).unwrap();
+        let question = parsed
+            .root()
+            .descendants()
+            .find(|x| {
+                matches!(
+                    x.element(),
+                    Some(ParsedElement::Dialect(DialectElement::Google(
+                        GoogleElement::Media(MediaAttributes { id: Some(id), .. })
+                    ))) if id == "question"
+                )
+            })
+            .expect("question media missing");
+        assert_eq!(question.text().trim(), "Who invented the Internet?");
+        let speak = question.children().find(|x| x.is_element()).unwrap();
+        assert!(matches!(speak.element(), Some(ParsedElement::Speak(_))));
+
+        let rewritten = parse_google(&parsed.write_ssml()).unwrap();
+        assert_eq!(rewritten.tags, parsed.tags);
+    }
+
+    #[test]
+    fn invalid_google_documents() {
+        // Text has to go in a media element
+        let err = parse_google("<speak><par><s>Hello</s></par></speak>").unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidNesting { .. }));
+
+        let err = parse_google("<speak><media><speak>Hi</speak></media></speak>").unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidNesting { .. }));
+
+        // Self-closing elements follow the same rules
+        let err = parse_google(r#"<speak><media begin="1s"/></speak>"#).unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidNesting { .. }));
+        assert_eq!(err.element(), Some("media"));
+
+        let err = parse_google(r#"<speak><par><break time="1s"/></par></speak>"#).unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidNesting { .. }));
+        assert_eq!(err.element(), Some("break"));
+
+        let err =
+            parse_google(r#"<speak><par><media soundLevel="+50dB"/></par></speak>"#).unwrap_err();
+        assert_eq!(err.attribute(), Some("soundLevel"));
+
+        let err = parse_google(r#"<speak><say-as interpret-as="digits">123</say-as></speak>"#)
+            .unwrap_err();
+        assert_eq!(err.element(), Some("say-as"));
+        assert_eq!(err.attribute(), Some("interpret-as"));
+    }
+}
@@ -0,0 +1,535 @@
+//! The Microsoft Azure extensions to SSML, this is only available with the `azure` feature.
+//! Documentation for these elements can be found
+//! [here](https://learn.microsoft.com/en-us/azure/ai-services/speech-service/speech-synthesis-markup).
+use crate::elements::TimeDesignation;
+use crate::error::SsmlError;
+use crate::parser::{get_required_attribute, parse_attribute, parse_required_attribute};
+use anyhow::bail;
+use quick_xml::escape::escape;
+use quick_xml::events::BytesStart;
+use quick_xml::reader::Reader;
+use std::fmt::{self, Display};
+use std::io;
+use std::str::FromStr;
+
+/// An element in the `mstts:` namespace, or the `<bookmark/>` element which Azure uses instead of
+/// `<mark/>`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "element", content = "attributes"))]
+pub enum MicrosoftElement {
+    /// The `<mstts:express-as></mstts:express-as>` element, speaks the contained text with a
+    /// speaking style or role play.
+    #[cfg_attr(feature = "serde", serde(rename = "mstts:express-as"))]
+    ExpressAs(ExpressAsAttributes),
+    /// The `<mstts:silence/>` element, controls the silence added around the text.
+    #[cfg_attr(feature = "serde", serde(rename = "mstts:silence"))]
+    Silence(SilenceAttributes),
+    /// The `<mstts:backgroundaudio/>` element, plays audio behind the whole document.
+    #[cfg_attr(feature = "serde", serde(rename = "mstts:backgroundaudio"))]
+    BackgroundAudio(BackgroundAudioAttributes),
+    /// The `<mstts:viseme/>` element, requests viseme output alongside the audio.
+    #[cfg_attr(feature = "serde", serde(rename = "mstts:viseme"))]
+    Viseme(VisemeAttributes),
+    /// The `<bookmark/>` element, a named position reported when it's reached in the audio.
+    #[cfg_attr(feature = "serde", serde(rename = "bookmark"))]
+    Bookmark(BookmarkAttributes),
+}
+
+impl MicrosoftElement {
+    /// Name of the element including the `mstts:` prefix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ExpressAs(_) => "mstts:express-as",
+            Self::Silence(_) => "mstts:silence",
+            Self::BackgroundAudio(_) => "mstts:backgroundaudio",
+            Self::Viseme(_) => "mstts:viseme",
+            Self::Bookmark(_) => "bookmark",
+        }
+    }
+
+    /// The XML attribute string used when writing the element back out.
+    pub fn attribute_string(&self) -> String {
+        match self {
+            Self::ExpressAs(attr) => format!("{}", attr),
+            Self::Silence(attr) => format!("{}", attr),
+            Self::BackgroundAudio(attr) => format!("{}", attr),
+            Self::Viseme(attr) => format!("{}", attr),
+            Self::Bookmark(attr) => format!("{}", attr),
+        }
+    }
+}
+
+/// Attributes of the `<mstts:express-as>` element.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ExpressAsAttributes {
+    /// The speaking style such as "cheerful" or "sad". The styles available depend on the voice so
+    /// any non-empty style is accepted.
+    pub style: String,
+    /// Intensity of the speaking style from 0.01 to 2, the default is 1.
+    pub style_degree: Option<f32>,
+    /// An age and gender for the voice to role play.
+    pub role: Option<Role>,
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for ExpressAsAttributes {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::{Fake, RngExt};
+        let style_degree = if rng.random_bool(0.5) {
+            Some(rng.random_range(1..=200) as f32 / 100.0)
+        } else {
+            None
+        };
+        Self {
+            style: (1..20)
+                .fake_with_rng::<String, _>(rng)
+                .replace(char::is_whitespace, "_"),
+            style_degree,
+            role: f.fake_with_rng(rng),
+        }
+    }
+}
+
+impl Display for ExpressAsAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " style=\"{}\"", escape(&self.style))?;
+        if let Some(degree) = self.style_degree {
+            write!(f, " styledegree=\"{}\"", degree)?;
+        }
+        if let Some(role) = self.role {
+            write!(f, " role=\"{}\"", role)?;
+        }
+        Ok(())
+    }
+}
+
+/// Roles the voice can play in `<mstts:express-as>`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum Role {
+    /// Girl
+    Girl,
+    /// Boy
+    Boy,
+    /// Young adult female
+    YoungAdultFemale,
+    /// Young adult male
+    YoungAdultMale,
+    /// Older adult female
+    OlderAdultFemale,
+    /// Older adult male
+    OlderAdultMale,
+    /// Senior female
+    SeniorFemale,
+    /// Senior male
+    SeniorMale,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Girl => "Girl",
+                Self::Boy => "Boy",
+                Self::YoungAdultFemale => "YoungAdultFemale",
+                Self::YoungAdultMale => "YoungAdultMale",
+                Self::OlderAdultFemale => "OlderAdultFemale",
+                Self::OlderAdultMale => "OlderAdultMale",
+                Self::SeniorFemale => "SeniorFemale",
+                Self::SeniorMale => "SeniorMale",
+            }
+        )
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Girl" => Ok(Self::Girl),
+            "Boy" => Ok(Self::Boy),
+            "YoungAdultFemale" => Ok(Self::YoungAdultFemale),
+            "YoungAdultMale" => Ok(Self::YoungAdultMale),
+            "OlderAdultFemale" => Ok(Self::OlderAdultFemale),
+            "OlderAdultMale" => Ok(Self::OlderAdultMale),
+            "SeniorFemale" => Ok(Self::SeniorFemale),
+            "SeniorMale" => Ok(Self::SeniorMale),
+            e => bail!("Unrecognised role {}", e),
+        }
+    }
+}
+
+fn parse_style_degree(s: &str) -> anyhow::Result<f32> {
+    let degree: f32 = s.parse()?;
+    if !(0.01..=2.0).contains(&degree) {
+        bail!("Style degree must be between 0.01 and 2");
+    }
+    Ok(degree)
+}
+
+/// Attributes of the `<mstts:silence/>` element.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SilenceAttributes {
+    /// Where the silence is added.
+    pub ty: SilenceType,
+    /// How long the silence is.
+    pub value: TimeDesignation,
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for SilenceAttributes {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::{Fake, RngExt};
+        Self {
+            ty: f.fake_with_rng(rng),
+            value: TimeDesignation::Milliseconds(rng.random_range(0..5000) as f32),
+        }
+    }
+}
+
+impl Display for SilenceAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " type=\"{}\" value=\"{}\"", self.ty, self.value)
+    }
+}
+
+/// Where `<mstts:silence/>` adds silence. The exact variants replace the silence that would
+/// naturally be there, the others add to it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum SilenceType {
+    /// Silence at the beginning of the text
+    Leading,
+    /// Exact silence at the beginning of the text
+    LeadingExact,
+    /// Silence at the end of the text
+    Tailing,
+    /// Exact silence at the end of the text
+    TailingExact,
+    /// Silence between sentences
+    SentenceBoundary,
+    /// Exact silence between sentences
+    SentenceBoundaryExact,
+    /// Exact silence at commas
+    CommaExact,
+    /// Exact silence at semicolons
+    SemicolonExact,
+    /// Exact silence at enumeration commas (used in Chinese and Japanese)
+    EnumerationCommaExact,
+}
+
+impl Display for SilenceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Leading => "Leading",
+                Self::LeadingExact => "Leading-exact",
+                Self::Tailing => "Tailing",
+                Self::TailingExact => "Tailing-exact",
+                Self::SentenceBoundary => "Sentenceboundary",
+                Self::SentenceBoundaryExact => "Sentenceboundary-exact",
+                Self::CommaExact => "Comma-exact",
+                Self::SemicolonExact => "Semicolon-exact",
+                Self::EnumerationCommaExact => "Enumerationcomma-exact",
+            }
+        )
+    }
+}
+
+impl FromStr for SilenceType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Leading" => Ok(Self::Leading),
+            "Leading-exact" => Ok(Self::LeadingExact),
+            "Tailing" => Ok(Self::Tailing),
+            "Tailing-exact" => Ok(Self::TailingExact),
+            "Sentenceboundary" => Ok(Self::SentenceBoundary),
+            "Sentenceboundary-exact" => Ok(Self::SentenceBoundaryExact),
+            "Comma-exact" => Ok(Self::CommaExact),
+            "Semicolon-exact" => Ok(Self::SemicolonExact),
+            "Enumerationcomma-exact" => Ok(Self::EnumerationCommaExact),
+            e => bail!("Unrecognised silence type {}", e),
+        }
+    }
+}
+
+/// Attributes of the `<mstts:backgroundaudio/>` element.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct BackgroundAudioAttributes {
+    /// Location of the audio file.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::uri"))]
+    pub src: http::Uri,
+    /// Volume of the audio from 0 to 100, the default is 1.
+    pub volume: Option<u8>,
+    /// How long the audio takes to fade in, up to 10 seconds.
+    pub fade_in: Option<TimeDesignation>,
+    /// How long the audio takes to fade out, up to 10 seconds.
+    pub fade_out: Option<TimeDesignation>,
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for BackgroundAudioAttributes {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::{Fake, RngExt};
+        let mut fade = || {
+            if rng.random_bool(0.5) {
+                Some(TimeDesignation::Milliseconds(
+                    rng.random_range(0..=10000) as f32
+                ))
+            } else {
+                None
+            }
+        };
+        let fade_in = fade();
+        let fade_out = fade();
+        Self {
+            src: f.fake_with_rng(rng),
+            volume: if rng.random_bool(0.5) {
+                Some(rng.random_range(0..=100))
+            } else {
+                None
+            },
+            fade_in,
+            fade_out,
+        }
+    }
+}
+
+impl Display for BackgroundAudioAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " src=\"{}\"", escape(self.src.to_string()))?;
+        if let Some(volume) = self.volume {
+            write!(f, " volume=\"{}\"", volume)?;
+        }
+        if let Some(fade_in) = self.fade_in {
+            write!(f, " fadein=\"{}\"", fade_in)?;
+        }
+        if let Some(fade_out) = self.fade_out {
+            write!(f, " fadeout=\"{}\"", fade_out)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_volume(s: &str) -> anyhow::Result<u8> {
+    match s.parse::<u8>() {
+        Ok(volume) if volume <= 100 => Ok(volume),
+        _ => bail!("Volume must be a whole number between 0 and 100"),
+    }
+}
+
+fn parse_fade(s: &str) -> anyhow::Result<TimeDesignation> {
+    let time = TimeDesignation::from_str(s)?;
+    if time.duration().as_secs_f32() > 10.0 {
+        bail!("Fade duration can't be longer than 10 seconds");
+    }
+    Ok(time)
+}
+
+/// Attributes of the `<mstts:viseme/>` element.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct VisemeAttributes {
+    /// The kind of viseme output requested.
+    pub ty: VisemeType,
+}
+
+impl Display for VisemeAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " type=\"{}\"", self.ty)
+    }
+}
+
+/// Kinds of viseme output.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum VisemeType {
+    /// Positions of the lips
+    RedlipsFront,
+    /// Blend shapes for facial expressions
+    FacialExpression,
+}
+
+impl Display for VisemeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::RedlipsFront => "redlips_front",
+                Self::FacialExpression => "FacialExpression",
+            }
+        )
+    }
+}
+
+impl FromStr for VisemeType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redlips_front" => Ok(Self::RedlipsFront),
+            "FacialExpression" => Ok(Self::FacialExpression),
+            e => bail!("Unrecognised viseme type {}", e),
+        }
+    }
+}
+
+/// Attributes of the `<bookmark/>` element.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct BookmarkAttributes {
+    /// Name reported when the bookmark is reached.
+    pub mark: String,
+}
+
+impl Display for BookmarkAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " mark=\"{}\"", escape(&self.mark))
+    }
+}
+
+/// Parses an Azure element, returns `None` for elements which aren't part of the Microsoft
+/// dialect.
+pub(crate) fn parse_element<R: io::BufRead>(
+    name: &str,
+    elem: &BytesStart,
+    reader: &Reader<R>,
+) -> Result<Option<MicrosoftElement>, SsmlError> {
+    let element = match name {
+        "mstts:express-as" => MicrosoftElement::ExpressAs(ExpressAsAttributes {
+            style: parse_required_attribute(elem, reader, "style", |s| match s {
+                "" => bail!("Style can't be empty"),
+                s => Ok(s.to_string()),
+            })?,
+            style_degree: parse_attribute(elem, reader, "styledegree", parse_style_degree)?,
+            role: parse_attribute(elem, reader, "role", Role::from_str)?,
+        }),
+        "mstts:silence" => MicrosoftElement::Silence(SilenceAttributes {
+            ty: parse_required_attribute(elem, reader, "type", SilenceType::from_str)?,
+            value: parse_required_attribute(elem, reader, "value", TimeDesignation::from_str)?,
+        }),
+        "mstts:backgroundaudio" => MicrosoftElement::BackgroundAudio(BackgroundAudioAttributes {
+            src: parse_required_attribute(elem, reader, "src", http::Uri::from_str)?,
+            volume: parse_attribute(elem, reader, "volume", parse_volume)?,
+            fade_in: parse_attribute(elem, reader, "fadein", parse_fade)?,
+            fade_out: parse_attribute(elem, reader, "fadeout", parse_fade)?,
+        }),
+        "mstts:viseme" => MicrosoftElement::Viseme(VisemeAttributes {
+            ty: parse_required_attribute(elem, reader, "type", VisemeType::from_str)?,
+        }),
+        "bookmark" => MicrosoftElement::Bookmark(BookmarkAttributes {
+            mark: get_required_attribute(elem, reader, "mark")?,
+        }),
+        _ => return Ok(None),
+    };
+    Ok(Some(element))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialects::{Dialect, DialectElement};
+    use crate::elements::ParsedElement;
+    use crate::parser::SsmlParserBuilder;
+    use fake::{Fake, Faker};
+
+    fn parse_microsoft(ssml: &str) -> Result<crate::Ssml, SsmlError> {
+        SsmlParserBuilder::default()
+            .dialect(Dialect::Microsoft)
+            .build()
+            .unwrap()
+            .parse(ssml)
+    }
+
+    #[test]
+    fn microsoft_conversions() {
+        for _ in 0..30 {
+            let element: MicrosoftElement = Faker.fake();
+            let xml = format!(
+                "<speak><{}{}/></speak>",
+                element.name(),
+                element.attribute_string(),
+            );
+            println!("{}", xml);
+
+            let ssml = parse_microsoft(&xml).unwrap();
+            assert_eq!(
+                ssml.tags[1].element,
+                ParsedElement::Dialect(DialectElement::Microsoft(element))
+            );
+        }
+    }
+
+    #[test]
+    fn microsoft_tags() {
+        let ssml = r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xmlns:mstts="https://www.w3.org/2001/mstts" xml:lang="en-US">
+            <mstts:backgroundaudio src="https://contoso.com/sample.wav" volume="0.7" fadein="3000ms"/>
+            <voice name="en-US-AriaNeural">
+                <mstts:silence type="Sentenceboundary" value="200ms"/>
+                <mstts:express-as style="cheerful" styledegree="2" role="YoungAdultFemale">
+                    That'd be just amazing!<bookmark mark="flower_1"/>
+                </mstts:express-as>
+            </voice>
+        </speak>"#;
+
+        // Volume has to be a whole number
+        let err = parse_microsoft(ssml).unwrap_err();
+        assert_eq!(err.element(), Some("mstts:backgroundaudio"));
+        assert_eq!(err.attribute(), Some("volume"));
+
+        let ssml = parse_microsoft(&ssml.replace("0.7", "70")).unwrap();
+        let elements = ssml
+            .root()
+            .descendants()
+            .filter_map(|x| match x.element() {
+                Some(ParsedElement::Dialect(DialectElement::Microsoft(e))) => Some(e.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let expected = vec![
+            MicrosoftElement::BackgroundAudio(BackgroundAudioAttributes {
+                src: "https://contoso.com/sample.wav".parse().unwrap(),
+                volume: Some(70),
+                fade_in: Some(TimeDesignation::Milliseconds(3000.0)),
+                fade_out: None,
+            }),
+            MicrosoftElement::Silence(SilenceAttributes {
+                ty: SilenceType::SentenceBoundary,
+                value: TimeDesignation::Milliseconds(200.0),
+            }),
+            MicrosoftElement::ExpressAs(ExpressAsAttributes {
+                style: "cheerful".to_string(),
+                style_degree: Some(2.0),
+                role: Some(Role::YoungAdultFemale),
+            }),
+            MicrosoftElement::Bookmark(BookmarkAttributes {
+                mark: "flower_1".to_string(),
+            }),
+        ];
+        assert_eq!(elements, expected);
+
+        let rewritten = parse_microsoft(&ssml.write_ssml()).unwrap();
+        assert_eq!(rewritten.tags, ssml.tags);
+
+        let err = parse_microsoft(
+            r#"<speak><mstts:express-as style="sad" styledegree="3">Hi</mstts:express-as></speak>"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.attribute(), Some("styledegree"));
+    }
+}
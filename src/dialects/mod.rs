@@ -0,0 +1,142 @@
+//! Extensions to SSML added by TTS vendors. Without a dialect enabled on the parser these
+//! elements are parsed as `ParsedElement::Custom` with their attributes as strings, enabling a
+//! dialect parses its elements into typed attributes and checks the attribute values are ones the
+//! vendor accepts.
+use crate::elements::{ParsedElement, SsmlElement};
+use crate::error::SsmlError;
+use quick_xml::events::BytesStart;
+use quick_xml::reader::Reader;
+use std::fmt;
+use std::io;
+
+pub mod amazon;
+pub mod google;
+#[cfg(feature = "azure")]
+pub mod microsoft;
+
+/// A vendor's extensions to SSML which the parser can understand.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Dialect {
+    /// Amazon Polly, elements are in the `amazon:` namespace.
+    Amazon,
+    /// Google Cloud Text-to-Speech, adds the `<par>`, `<seq>` and `<media>` elements.
+    Google,
+    /// Microsoft Azure, elements are in the `mstts:` namespace along with `<bookmark/>`.
+    #[cfg(feature = "azure")]
+    Microsoft,
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Amazon => write!(f, "amazon"),
+            Self::Google => write!(f, "google"),
+            #[cfg(feature = "azure")]
+            Self::Microsoft => write!(f, "microsoft"),
+        }
+    }
+}
+
+/// An element from one of the vendor dialects.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DialectElement {
+    /// An Amazon Polly element.
+    Amazon(amazon::AmazonElement),
+    /// A Google element.
+    Google(google::GoogleElement),
+    /// A Microsoft Azure element.
+    #[cfg(feature = "azure")]
+    Microsoft(microsoft::MicrosoftElement),
+}
+
+impl DialectElement {
+    /// The dialect the element belongs to.
+    pub fn dialect(&self) -> Dialect {
+        match self {
+            Self::Amazon(_) => Dialect::Amazon,
+            Self::Google(_) => Dialect::Google,
+            #[cfg(feature = "azure")]
+            Self::Microsoft(_) => Dialect::Microsoft,
+        }
+    }
+
+    /// Name of the element including any namespace prefix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Amazon(element) => element.name(),
+            Self::Google(element) => element.name(),
+            #[cfg(feature = "azure")]
+            Self::Microsoft(element) => element.name(),
+        }
+    }
+
+    /// The XML attribute string used when writing the element back out.
+    pub fn attribute_string(&self) -> String {
+        match self {
+            Self::Amazon(element) => element.attribute_string(),
+            Self::Google(element) => element.attribute_string(),
+            #[cfg(feature = "azure")]
+            Self::Microsoft(element) => element.attribute_string(),
+        }
+    }
+}
+
+/// Parses an element from one of the enabled dialects, returns `None` if the element isn't part
+/// of any of them.
+pub(crate) fn parse_element<R: io::BufRead>(
+    name: &str,
+    elem: &BytesStart,
+    reader: &Reader<R>,
+    dialects: &[Dialect],
+) -> Result<Option<DialectElement>, SsmlError> {
+    for dialect in dialects {
+        let element = match dialect {
+            Dialect::Amazon => {
+                amazon::parse_element(name, elem, reader)?.map(DialectElement::Amazon)
+            }
+            Dialect::Google => {
+                google::parse_element(name, elem, reader)?.map(DialectElement::Google)
+            }
+            #[cfg(feature = "azure")]
+            Dialect::Microsoft => {
+                microsoft::parse_element(name, elem, reader)?.map(DialectElement::Microsoft)
+            }
+        };
+        if element.is_some() {
+            return Ok(element);
+        }
+    }
+    Ok(None)
+}
+
+/// Nesting rules from the enabled dialects which replace the ones in the SSML specification,
+/// returns `None` if none of the dialects have a rule for the elements.
+pub(crate) fn can_contain(
+    dialects: &[Dialect],
+    parent: &SsmlElement,
+    child: &SsmlElement,
+) -> Option<bool> {
+    dialects.iter().find_map(|dialect| match dialect {
+        Dialect::Google => google::can_contain(parent, child),
+        _ => None,
+    })
+}
+
+/// Checks elements from the SSML specification only use values the enabled dialects support.
+pub(crate) fn check_element(
+    element: &ParsedElement,
+    dialects: &[Dialect],
+) -> Result<(), SsmlError> {
+    for dialect in dialects {
+        if dialect == &Dialect::Google {
+            google::check_element(element)?;
+        }
+    }
+    Ok(())
+}
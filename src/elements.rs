@@ -9,6 +9,8 @@
 //! notice will be placed on the top level type and not each field for conciseness
 //! but keep in mind the fields will also be taken from the same section of the
 //! standard.
+use crate::dialects::DialectElement;
+use crate::language_tag::{LanguageRange, LanguageTag};
 use anyhow::{bail, Context};
 #[cfg(test)]
 use fake::RngExt;
@@ -93,6 +95,7 @@ impl SsmlElement {
                 | Self::Token
                 | Self::Word
                 | Self::Lang
+                | Self::Lookup
                 | Self::Prosody
                 | Self::Audio
                 | Self::Custom(_)
@@ -114,6 +117,7 @@ impl SsmlElement {
             (Self::Emphasis, a) => a.allowed_in_sentence(), // Emphasis and sentence lists match
             (Self::Token | Self::Word, a) => a.allowed_in_token(),
             (Self::Lang, a) => a.allowed_in_speak(),
+            (Self::Lookup, a) => a == &Self::Paragraph || a.allowed_in_paragraph(),
             (Self::Prosody, a) => a.allowed_in_speak(),
             (Self::Audio, a) => a.allowed_in_speak(),
             (Self::Custom(_), _) => true,
@@ -223,6 +227,11 @@ impl Display for SsmlElement {
 /// Enum representing the parsed element, each element with attributes allowed also contains an
 /// object for it's attributes.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "element", content = "attributes", rename_all = "kebab-case")
+)]
 pub enum ParsedElement {
     /// The `<speak></speak>` element and given attributes.
     Speak(SpeakAttributes),
@@ -235,14 +244,17 @@ pub enum ParsedElement {
     Meta(MetaAttributes),
     /// The `<metadata></metadata>` element.
     Metadata,
-    /// The `<p></p>` element.
-    Paragraph,
-    /// The `<s></s>` element.
-    Sentence,
+    /// The `<p></p>` element and given attributes.
+    #[cfg_attr(feature = "serde", serde(rename = "p"))]
+    Paragraph(TextStructureAttributes),
+    /// The `<s></s>` element and given attributes.
+    #[cfg_attr(feature = "serde", serde(rename = "s"))]
+    Sentence(TextStructureAttributes),
     /// The `<token></token>` element and given attributes.
     Token(TokenAttributes),
     /// The `<word></word>` element and given attributes.
     // `w` element is just an alias for `token`
+    #[cfg_attr(feature = "serde", serde(rename = "w"))]
     Word(TokenAttributes),
     /// The `<say-as></say-as>` element and given attributes.
     SayAs(SayAsAttributes),
@@ -265,10 +277,13 @@ pub enum ParsedElement {
     /// The `<mark/>` element and given attributes.
     Mark(MarkAttributes),
     /// The `<desc></desc>` element and given attributes.
+    #[cfg_attr(feature = "serde", serde(rename = "desc"))]
     Description(String),
     /// Custom elements not defined in the spec, the element name is stored in the given string and
     /// any attributes in the map.
     Custom((String, BTreeMap<String, String>)),
+    /// An element from one of the vendor dialects enabled on the parser.
+    Dialect(DialectElement),
 }
 
 impl ParsedElement {
@@ -281,8 +296,8 @@ impl ParsedElement {
             Lookup(attr) => format!("{}", attr),
             Meta(attr) => format!("{}", attr),
             Metadata => String::new(),
-            Paragraph => String::new(),
-            Sentence => String::new(),
+            Paragraph(attr) => format!("{}", attr),
+            Sentence(attr) => format!("{}", attr),
             Token(attr) => format!("{}", attr),
             Word(attr) => format!("{}", attr),
             SayAs(attr) => format!("{}", attr),
@@ -304,6 +319,7 @@ impl ParsedElement {
                 }
                 attr_str
             }
+            Dialect(element) => element.attribute_string(),
         }
     }
 
@@ -326,8 +342,8 @@ impl From<&ParsedElement> for SsmlElement {
             ParsedElement::Lookup(_) => Self::Lookup,
             ParsedElement::Meta(_) => Self::Meta,
             ParsedElement::Metadata => Self::Metadata,
-            ParsedElement::Paragraph => Self::Paragraph,
-            ParsedElement::Sentence => Self::Sentence,
+            ParsedElement::Paragraph(_) => Self::Paragraph,
+            ParsedElement::Sentence(_) => Self::Sentence,
             ParsedElement::Token(_) => Self::Token,
             ParsedElement::Word(_) => Self::Word,
             ParsedElement::SayAs(_) => Self::SayAs,
@@ -342,6 +358,7 @@ impl From<&ParsedElement> for SsmlElement {
             ParsedElement::Mark(_) => Self::Mark,
             ParsedElement::Description(_) => Self::Description,
             ParsedElement::Custom((s, _)) => Self::Custom(s.to_string()),
+            ParsedElement::Dialect(element) => Self::Custom(element.name().to_string()),
         }
     }
 }
@@ -355,18 +372,24 @@ impl From<&ParsedElement> for SsmlElement {
 /// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
 /// All Rights Reserved._
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct SpeakAttributes {
     /// Lang is an attribute specifying the language of the root document. In the specification
     /// this is a REQUIRED attribute, however in reality most TTS APIs require a different way to
     /// specify the language outside of SSML and treat this as optional. Because of that this
     /// implementation has chosen to be more permissive than the spec.
-    pub lang: Option<String>,
+    pub lang: Option<LanguageTag>,
     /// Base is an OPTIONAL attribute specifying the Base URI of the root document.
     pub base: Option<String>,
     /// On Language Failure is an OPTIONAL attribute specifying the desired behavior upon language speaking failure.
     pub on_lang_failure: Option<OnLanguageFailure>,
     /// The version attribute is a REQUIRED attribute that indicates the version of the specification to be used for the document and MUST have the value "1.1".
     pub version: String,
+    /// Start Mark is an OPTIONAL attribute naming the mark where rendering of the document
+    /// starts.
+    pub start_mark: Option<String>,
+    /// End Mark is an OPTIONAL attribute naming the mark where rendering of the document ends.
+    pub end_mark: Option<String>,
     /// for remaining attributes on root like namespace etc
     pub xml_root_attrs: BTreeMap<String, String>,
 }
@@ -380,6 +403,8 @@ impl fake::Dummy<fake::Faker> for SpeakAttributes {
             base: f.fake_with_rng(rng),
             on_lang_failure: f.fake_with_rng(rng),
             version: "1.1".to_string(),
+            start_mark: f.fake_with_rng(rng),
+            end_mark: f.fake_with_rng(rng),
             xml_root_attrs: f.fake_with_rng(rng),
         }
     }
@@ -389,7 +414,7 @@ impl Display for SpeakAttributes {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, " version=\"{}\"", escape(&self.version))?;
         if let Some(lang) = &self.lang {
-            write!(f, " xml:lang=\"{}\"", escape(lang))?;
+            write!(f, " xml:lang=\"{}\"", lang)?;
         }
         if let Some(base) = &self.base {
             write!(f, " xml:base=\"{}\"", escape(base))?;
@@ -397,6 +422,12 @@ impl Display for SpeakAttributes {
         if let Some(fail) = &self.on_lang_failure {
             write!(f, " onlangfailure=\"{}\"", fail)?;
         }
+        if let Some(mark) = &self.start_mark {
+            write!(f, " startmark=\"{}\"", escape(mark))?;
+        }
+        if let Some(mark) = &self.end_mark {
+            write!(f, " endmark=\"{}\"", escape(mark))?;
+        }
         for (attr_name, attr_value) in self.xml_root_attrs.iter() {
             write!(f, " {}=\"{}\"", attr_name, attr_value)?;
         }
@@ -404,19 +435,48 @@ impl Display for SpeakAttributes {
     }
 }
 
+/// A p element represents a paragraph. An s element represents a sentence. Both can change the
+/// natural language of their content with the xml:lang attribute.
+///
+/// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
+/// All Rights Reserved._
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct TextStructureAttributes {
+    /// Lang is an OPTIONAL attribute specifying the language of the content.
+    pub lang: Option<LanguageTag>,
+    /// On Language Failure is an OPTIONAL attribute specifying the desired behavior upon language speaking failure.
+    pub on_lang_failure: Option<OnLanguageFailure>,
+}
+
+impl Display for TextStructureAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(lang) = &self.lang {
+            write!(f, " xml:lang=\"{}\"", lang)?;
+        }
+        if let Some(fail) = self.on_lang_failure {
+            write!(f, " onlangfailure=\"{}\"", fail)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// The lang element is used to specify the natural language of the content. This element MAY be used when there is a change in the natural language.
 #[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct LangAttributes {
     /// Lang is a REQUIRED attribute specifying the language of the root document.
-    pub lang: String,
+    pub lang: LanguageTag,
     /// On Language Failure is an OPTIONAL attribute specifying the desired behavior upon language speaking failure.
     pub on_lang_failure: Option<OnLanguageFailure>,
 }
 
 impl Display for LangAttributes {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, " xml:lang=\"{}\"", escape(&self.lang))?;
+        write!(f, " xml:lang=\"{}\"", self.lang)?;
         if let Some(fail) = self.on_lang_failure {
             write!(f, " onlangfailure=\"{}\"", fail)?;
         }
@@ -548,8 +608,10 @@ impl FromStr for SsmlElement {
 /// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
 /// All Rights Reserved._
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct LexiconAttributes {
     ///  The lexicon element MUST have a uri attribute specifying a URI that identifies the location of the lexicon document.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::uri"))]
     pub uri: http::Uri,
     /// The lexicon element MUST have an xml:id attribute that assigns a name to the lexicon document. The name MUST be unique to the current SSML document.
     /// The scope of this name is the current SSML document.
@@ -587,7 +649,7 @@ impl fake::Dummy<fake::Faker> for LexiconAttributes {
 
 impl Display for LexiconAttributes {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, " uri=\"{}\"", escape(&self.uri.to_string()))?;
+        write!(f, " uri=\"{}\"", escape(self.uri.to_string()))?;
         write!(f, " xml:id=\"{}\"", escape(&self.xml_id))?;
         if let Some(ty) = &self.ty {
             write!(f, " type=\"{}\"", ty)?;
@@ -690,6 +752,7 @@ impl FromStr for TimeDesignation {
 /// All Rights Reserved._
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct LookupAttributes {
     /// Specifies a name that references a lexicon document as assigned by the xml:id attribute of the lexicon element.
     pub lookup_ref: String,
@@ -724,6 +787,7 @@ impl Display for LookupAttributes {
 /// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
 /// All Rights Reserved._
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct MetaAttributes {
     /// Currently, the only defined name is `seeAlso`. In future other meta names may be added.
     pub name: Option<String>,
@@ -782,6 +846,7 @@ impl Display for MetaAttributes {
 /// All Rights Reserved._
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct TokenAttributes {
     /// `role` is an OPTIONAL defined attribute on the token element. The role
     /// attribute takes as its value one or more white space separated QNames
@@ -824,16 +889,20 @@ impl Display for TokenAttributes {
 /// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
 /// All Rights Reserved._
 #[derive(Debug, Clone, Eq, PartialEq)]
-#[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "crate::serialization::SayAsAttributesDef")
+)]
 pub struct SayAsAttributes {
     /// The interpret-as attribute indicates the content type of the contained text construct.
     /// Specifying the content type helps the synthesis processor to distinguish and interpret
     /// text constructs that may be rendered in different ways depending on what type of
     /// information is intended.
-    pub interpret_as: String,
+    pub interpret_as: InterpretAs,
     /// The optional format attribute can give further hints on the precise formatting of the
     /// contained text for content types that may have ambiguous formats.
-    pub format: Option<String>,
+    pub format: Option<SayAsFormat>,
     /// The detail attribute is an optional attribute that indicates the level of detail to be
     /// read aloud or rendered. Every value of the detail attribute must render all of the
     /// informational content in the contained text; however, specific values for the detail
@@ -845,11 +914,31 @@ pub struct SayAsAttributes {
     pub detail: Option<String>,
 }
 
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for SayAsAttributes {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(f: &fake::Faker, rng: &mut R) -> Self {
+        use fake::Fake;
+        // The format has to make sense for the interpret-as value to be parsed back the same
+        let interpret_as: InterpretAs = f.fake_with_rng(rng);
+        let format = match interpret_as {
+            _ if rng.random_bool(0.3) => None,
+            InterpretAs::Date => Some(SayAsFormat::Date(f.fake_with_rng(rng))),
+            InterpretAs::Time => Some(SayAsFormat::Time(f.fake_with_rng(rng))),
+            _ => Some(SayAsFormat::Other(f.fake_with_rng(rng))),
+        };
+        Self {
+            interpret_as,
+            format,
+            detail: f.fake_with_rng(rng),
+        }
+    }
+}
+
 impl Display for SayAsAttributes {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, " interpret-as=\"{}\"", escape(&self.interpret_as))?;
+        write!(f, " interpret-as=\"{}\"", self.interpret_as)?;
         if let Some(format) = &self.format {
-            write!(f, " format=\"{}\"", escape(format))?;
+            write!(f, " format=\"{}\"", format)?;
         }
         if let Some(detail) = &self.detail {
             write!(f, " detail=\"{}\"", escape(detail))?
@@ -859,6 +948,254 @@ impl Display for SayAsAttributes {
     }
 }
 
+/// The content types for say-as defined in the W3C "SSML 1.0 say-as attribute values" note,
+/// along with the common digits, currency and unit types. Processors support different sets
+/// of values so anything else is kept as `Other`.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum InterpretAs {
+    /// A calendar date, the format says which of day, month and year are given and in what order.
+    Date,
+    /// A time of day, the format says whether hours, minutes and seconds are given and whether
+    /// it uses the 12 or 24 hour clock.
+    Time,
+    /// A telephone number, the format can give the country code.
+    Telephone,
+    /// Text to be spelled out character by character.
+    Characters,
+    /// A cardinal number such as "12" or "1,234.5".
+    Cardinal,
+    /// An ordinal number such as "3" or "3rd" read as "third".
+    Ordinal,
+    /// Digits read out one at a time.
+    Digits,
+    /// An amount of money such as "$45.30".
+    Currency,
+    /// A measurement with a unit such as "10kg".
+    Unit,
+    /// Any other content type.
+    Other(String),
+}
+
+impl InterpretAs {
+    /// The value of the interpret-as attribute.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Date => "date",
+            Self::Time => "time",
+            Self::Telephone => "telephone",
+            Self::Characters => "characters",
+            Self::Cardinal => "cardinal",
+            Self::Ordinal => "ordinal",
+            Self::Digits => "digits",
+            Self::Currency => "currency",
+            Self::Unit => "unit",
+            Self::Other(value) => value,
+        }
+    }
+}
+
+impl Display for InterpretAs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", escape(self.as_str()))
+    }
+}
+
+impl FromStr for InterpretAs {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s {
+            "date" => Self::Date,
+            "time" => Self::Time,
+            "telephone" => Self::Telephone,
+            "characters" => Self::Characters,
+            "cardinal" => Self::Cardinal,
+            "ordinal" => Self::Ordinal,
+            "digits" => Self::Digits,
+            "currency" => Self::Currency,
+            "unit" => Self::Unit,
+            e => Self::Other(e.to_string()),
+        };
+        Ok(s)
+    }
+}
+
+/// The format of a say-as element. Dates and times have a fixed set of formats, the formats of
+/// other content types are kept as they're written.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SayAsFormat {
+    /// Format of a date.
+    Date(DateFormat),
+    /// Format of a time.
+    Time(TimeFormat),
+    /// Format of any other content type, such as the country code of a telephone number.
+    Other(String),
+}
+
+impl SayAsFormat {
+    /// Parses a format for the given content type. Only date and time formats are checked, an
+    /// error is returned if it's not one of their formats.
+    pub fn parse(interpret_as: &InterpretAs, s: &str) -> anyhow::Result<Self> {
+        match interpret_as {
+            InterpretAs::Date => Ok(Self::Date(s.parse()?)),
+            InterpretAs::Time => Ok(Self::Time(s.parse()?)),
+            _ => Ok(Self::Other(s.to_string())),
+        }
+    }
+}
+
+impl Display for SayAsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Date(format) => write!(f, "{}", format),
+            Self::Time(format) => write!(f, "{}", format),
+            Self::Other(format) => write!(f, "{}", escape(format)),
+        }
+    }
+}
+
+/// The fields of a date and the order they're written in, "d" for day, "m" for month and "y"
+/// for year.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum DateFormat {
+    /// Month, day and year.
+    Mdy,
+    /// Day, month and year.
+    Dmy,
+    /// Year, month and day.
+    Ymd,
+    /// Month and day.
+    Md,
+    /// Day and month.
+    Dm,
+    /// Year and month.
+    Ym,
+    /// Month and year.
+    My,
+    /// Day only.
+    D,
+    /// Month only.
+    M,
+    /// Year only.
+    Y,
+}
+
+impl DateFormat {
+    /// The fields in the order they're written, each one of 'd', 'm' or 'y'.
+    pub fn fields(&self) -> &'static str {
+        match self {
+            Self::Mdy => "mdy",
+            Self::Dmy => "dmy",
+            Self::Ymd => "ymd",
+            Self::Md => "md",
+            Self::Dm => "dm",
+            Self::Ym => "ym",
+            Self::My => "my",
+            Self::D => "d",
+            Self::M => "m",
+            Self::Y => "y",
+        }
+    }
+}
+
+impl Display for DateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fields())
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s {
+            "mdy" => Self::Mdy,
+            "dmy" => Self::Dmy,
+            "ymd" => Self::Ymd,
+            "md" => Self::Md,
+            "dm" => Self::Dm,
+            "ym" => Self::Ym,
+            "my" => Self::My,
+            "d" => Self::D,
+            "m" => Self::M,
+            "y" => Self::Y,
+            e => bail!("Unrecognised date format {}", e),
+        };
+        Ok(s)
+    }
+}
+
+/// The fields of a time, "h" for hours, "m" for minutes and "s" for seconds, and whether it's
+/// on the 12 or 24 hour clock.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum TimeFormat {
+    /// Hours, minutes and seconds on the 12 hour clock.
+    Hms12,
+    /// Hours, minutes and seconds on the 24 hour clock.
+    Hms24,
+    /// Hours and minutes on the 12 hour clock.
+    Hm12,
+    /// Hours and minutes on the 24 hour clock.
+    Hm24,
+    /// Hours on the 12 hour clock.
+    H12,
+    /// Hours on the 24 hour clock.
+    H24,
+}
+
+impl TimeFormat {
+    /// Whether the time is on the 12 hour clock.
+    pub fn is_12_hour(&self) -> bool {
+        matches!(self, Self::Hms12 | Self::Hm12 | Self::H12)
+    }
+
+    /// The number of fields in the time, 1 for hours only up to 3 with seconds.
+    pub fn field_count(&self) -> usize {
+        match self {
+            Self::Hms12 | Self::Hms24 => 3,
+            Self::Hm12 | Self::Hm24 => 2,
+            Self::H12 | Self::H24 => 1,
+        }
+    }
+}
+
+impl Display for TimeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Hms12 => "hms12",
+                Self::Hms24 => "hms24",
+                Self::Hm12 => "hm12",
+                Self::Hm24 => "hm24",
+                Self::H12 => "h12",
+                Self::H24 => "h24",
+            }
+        )
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s {
+            "hms12" => Self::Hms12,
+            "hms24" => Self::Hms24,
+            "hm12" => Self::Hm12,
+            "hm24" => Self::Hm24,
+            "h12" => Self::H12,
+            "h24" => Self::H24,
+            e => bail!("Unrecognised time format {}", e),
+        };
+        Ok(s)
+    }
+}
+
 /// The phonemic/phonetic pronunciation alphabet. A pronunciation alphabet in this context refers to a collection
 /// of symbols to represent the sounds of one or more human languages.
 ///
@@ -907,6 +1244,7 @@ impl FromStr for PhonemeAlphabet {
 /// All Rights Reserved._
 #[derive(Debug, Clone, Eq, PartialEq)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct PhonemeAttributes {
     /// The ph attribute is a required attribute that specifies the phoneme/phone
     /// string.
@@ -1153,10 +1491,13 @@ impl fmt::Display for RateStrength {
 /// Sign for relative values (positive or negative).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Sign {
     /// Positive relative change.
+    #[cfg_attr(feature = "serde", serde(rename = "+"))]
     Plus,
     /// Negative relative change.
+    #[cfg_attr(feature = "serde", serde(rename = "-"))]
     Minus,
 }
 
@@ -1201,7 +1542,7 @@ impl FromStr for PitchRange {
                     if value.starts_with('+') || value.starts_with('-') {
                         if value.starts_with('-') {
                             Ok(Self::RelativeChange((
-                                value.strip_suffix("Hz").unwrap().parse::<f32>()? * -1.0,
+                                -value.strip_suffix("Hz").unwrap().parse::<f32>()?,
                                 Sign::Minus,
                                 Unit::Hz,
                             )))
@@ -1221,7 +1562,7 @@ impl FromStr for PitchRange {
                     if value.starts_with('+') || value.starts_with('-') {
                         if value.starts_with('-') {
                             Ok(Self::RelativeChange((
-                                value.strip_suffix('%').unwrap().parse::<f32>()? * -1.0,
+                                -value.strip_suffix('%').unwrap().parse::<f32>()?,
                                 Sign::Minus,
                                 Unit::Percentage,
                             )))
@@ -1239,7 +1580,7 @@ impl FromStr for PitchRange {
                     if value.starts_with('+') || value.starts_with('-') {
                         if value.starts_with('-') {
                             Ok(Self::RelativeChange((
-                                value.strip_suffix("st").unwrap().parse::<f32>()? * -1.0,
+                                -value.strip_suffix("st").unwrap().parse::<f32>()?,
                                 Sign::Minus,
                                 Unit::St,
                             )))
@@ -1567,12 +1908,15 @@ impl fmt::Display for PositiveNumber {
 /// be measured in semitones or Hertz.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Unit {
     /// Hertz
     Hz,
     /// Semi-tone
+    #[cfg_attr(feature = "serde", serde(rename = "st"))]
     St,
     /// Percentage
+    #[cfg_attr(feature = "serde", serde(rename = "%"))]
     Percentage,
 }
 
@@ -1642,6 +1986,7 @@ impl FromStr for EmphasisLevel {
 /// All Rights Reserved._
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct BreakAttributes {
     ///  The strength attribute is an optional attribute having one of the following
     ///  values: "none", "x-weak", "weak", "medium" (default value), "strong", or
@@ -1675,8 +2020,9 @@ impl Display for BreakAttributes {
 
 /// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
 /// All Rights Reserved._
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct ProsodyAttributes {
     /// pitch: the baseline pitch for the contained text. Although the exact meaning of "baseline pitch"
     /// will vary across synthesis processors, increasing/decreasing this value will typically increase/decrease
@@ -1756,11 +2102,11 @@ impl Display for ProsodyAttributes {
 /// marker into an output stream for asynchronous notification. When processing
 /// a mark element, a synthesis processor MUST do one or both of the following:
 ///  - inform the hosting environment with the value of the name attribute and
-///  with information allowing the platform to retrieve the corresponding position
-///  in the rendered output.
+///    with information allowing the platform to retrieve the corresponding position
+///    in the rendered output.
 ///  - when audio output of the SSML document reaches the mark, issue an event that
-///  includes the REQUIRED name attribute of the element. The hosting environment
-///  defines the destination of the event.
+///    includes the REQUIRED name attribute of the element. The hosting environment
+///    defines the destination of the event.
 ///
 /// The mark element does not affect the speech output process.
 ///
@@ -1768,6 +2114,7 @@ impl Display for ProsodyAttributes {
 /// All Rights Reserved._
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct MarkAttributes {
     /// Name of the marker used to refer to it when jumping in the audio.
     pub name: String,
@@ -1783,6 +2130,7 @@ impl Display for MarkAttributes {
 /// All Rights Reserved._
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct EmphasisAttributes {
     /// the optional level attribute indicates the strength of emphasis to be applied. Defined
     /// values are "strong", "moderate", "none" and "reduced". The default level is "moderate".
@@ -1818,6 +2166,7 @@ impl Display for EmphasisAttributes {
 /// All Rights Reserved._
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct SubAttributes {
     /// The string to be spoken instead of the string enclosed in the tag
     pub alias: String,
@@ -1877,16 +2226,29 @@ impl FromStr for Gender {
 #[cfg_attr(test, derive(fake::Dummy))]
 pub struct LanguageAccentPair {
     /// Language the voice is desired to speak.
-    pub lang: String,
+    pub lang: LanguageRange,
     /// Optional accent to apply to the language.
-    pub accent: Option<String>,
+    pub accent: Option<LanguageRange>,
+}
+
+impl LanguageAccentPair {
+    /// Whether a voice speaking the language `lang` with the accent `accent` satisfies this pair.
+    /// Both are matched with extended filtering, ignoring the script and extension subtags of
+    /// the accent.
+    pub fn matches(&self, lang: &LanguageTag, accent: &LanguageTag) -> bool {
+        self.lang.matches(lang)
+            && self.accent.as_ref().is_none_or(|x| {
+                x.without_script_and_extensions()
+                    .matches(&accent.without_script_and_extensions())
+            })
+    }
 }
 
 impl Display for LanguageAccentPair {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", escape(&self.lang))?;
+        write!(f, "{}", self.lang)?;
         if let Some(accent) = &self.accent {
-            write!(f, ":{}", escape(accent))?;
+            write!(f, ":{}", accent)?;
         }
         Ok(())
     }
@@ -1898,29 +2260,120 @@ impl FromStr for LanguageAccentPair {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.is_empty() {
             bail!("Empty language string");
-        } else if s == "und" || s == "zxx" {
+        }
+        let (lang, accent) = match s.split_once(':') {
+            Some((lang, accent)) => (lang, Some(accent)),
+            None => (s, None),
+        };
+        if accent.is_some_and(|x| x.contains(':')) {
+            bail!(
+                "Invalid format 'language:accent' or 'language' expected for '{}'",
+                s
+            );
+        }
+        if lang.eq_ignore_ascii_case("und") || lang.eq_ignore_ascii_case("zxx") {
             bail!("Disallowed language code");
-        } else {
-            let lang_accent = s.split(':').collect::<Vec<_>>();
-            if lang_accent.len() > 2 {
-                bail!(
-                    "Invalid format 'language:accent' or 'language' expected for '{}'",
-                    s
-                );
+        }
+        Ok(LanguageAccentPair {
+            lang: lang.parse()?,
+            accent: accent.map(LanguageRange::from_str).transpose()?,
+        })
+    }
+}
+
+/// One of the voice feature attributes, used by the required and ordering attributes to refer to
+/// them by name.
+///
+/// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
+/// All Rights Reserved._
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum VoiceFeature {
+    /// The name attribute
+    Name,
+    /// The languages attribute
+    Languages,
+    /// The gender attribute
+    Gender,
+    /// The age attribute
+    Age,
+    /// The variant attribute
+    Variant,
+}
+
+impl Display for VoiceFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Name => "name",
+                Self::Languages => "languages",
+                Self::Gender => "gender",
+                Self::Age => "age",
+                Self::Variant => "variant",
             }
-            if lang_accent.len() == 1 {
-                Ok(LanguageAccentPair {
-                    lang: lang_accent[0].to_string(),
-                    accent: None,
-                })
-            } else if lang_accent.len() == 2 {
-                Ok(LanguageAccentPair {
-                    lang: lang_accent[0].to_string(),
-                    accent: Some(lang_accent[1].to_string()),
-                })
-            } else {
-                bail!("Unexpected language accent pair: '{}'", s);
+        )
+    }
+}
+
+impl FromStr for VoiceFeature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "languages" => Ok(Self::Languages),
+            "gender" => Ok(Self::Gender),
+            "age" => Ok(Self::Age),
+            "variant" => Ok(Self::Variant),
+            e => bail!("Unrecognised voice feature {}", e),
+        }
+    }
+}
+
+/// The onvoicefailure attribute describes the desired behavior of the synthesis processor upon
+/// voice selection failure. A conforming synthesis processor must report the voice selection
+/// failure in addition to taking the action below.
+///
+/// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
+/// All Rights Reserved._
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(test, derive(fake::Dummy))]
+pub enum OnVoiceFailure {
+    /// The synthesis processor uses the values of all voice feature attributes to select a voice
+    /// by feature priority, where the starting candidate set is the set of all available voices.
+    #[default]
+    PrioritySelect,
+    /// The voice does not change.
+    KeepExisting,
+    /// The synthesis processor chooses the behavior (either priorityselect or keepexisting).
+    ProcessorChoice,
+}
+
+impl Display for OnVoiceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::PrioritySelect => "priorityselect",
+                Self::KeepExisting => "keepexisting",
+                Self::ProcessorChoice => "processorchoice",
             }
+        )
+    }
+}
+
+impl FromStr for OnVoiceFailure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "priorityselect" => Ok(Self::PrioritySelect),
+            "keepexisting" => Ok(Self::KeepExisting),
+            "processorchoice" => Ok(Self::ProcessorChoice),
+            e => bail!("Unrecognised voice failure value {}", e),
         }
     }
 }
@@ -1930,32 +2383,32 @@ impl FromStr for LanguageAccentPair {
 /// voice and those that control behavior. The voice feature attributes are:
 ///
 /// * **gender**: _optional_ attribute indicating the preferred gender of the voice to speak the
-/// contained text. Enumerated values are: "male", "female", "neutral", or the empty string "".
+///   contained text. Enumerated values are: "male", "female", "neutral", or the empty string "".
 /// * **age**: _optional_ attribute indicating the preferred age in years (since birth) of the
-/// voice to speak the contained text. Acceptable values are of type xsd:nonNegativeInteger
-/// [SCHEMA2 §3.3.20] or the empty string "".
+///   voice to speak the contained text. Acceptable values are of type xsd:nonNegativeInteger
+///   [SCHEMA2 §3.3.20] or the empty string "".
 /// * **variant**: _optional_ attribute indicating a preferred variant of the other voice
-/// characteristics to speak the contained text. (e.g. the second male child voice). Valid values of
-/// variant are of type xsd:positiveInteger [SCHEMA2 §3.3.25] or the empty string "".
+///   characteristics to speak the contained text. (e.g. the second male child voice). Valid values of
+///   variant are of type xsd:positiveInteger [SCHEMA2 §3.3.25] or the empty string "".
 /// * **name**: _optional_ attribute indicating a processor-specific voice name to speak the contained
-/// text. The value may be a space-separated list of names ordered from top preference down or the
-/// empty string "". As a result a name must not contain any white space.
+///   text. The value may be a space-separated list of names ordered from top preference down or the
+///   empty string "". As a result a name must not contain any white space.
 /// * **languages**: _optional_ attribute indicating the list of languages the voice is desired to speak.
-/// The value must be either the empty string "" or a space-separated list of languages, with optional
-/// accent indication per language. Each language/accent pair is of the form "language" or
-/// "language:accent", where both language and accent must be an Extended Language Range
-/// [BCP47, Matching of Language Tags §2.2], except that the values "und" and "zxx" are disallowed.
-/// A voice satisfies the languages feature if, for each language/accent pair in the list,
+///   The value must be either the empty string "" or a space-separated list of languages, with optional
+///   accent indication per language. Each language/accent pair is of the form "language" or
+///   "language:accent", where both language and accent must be an Extended Language Range
+///   [BCP47, Matching of Language Tags §2.2], except that the values "und" and "zxx" are disallowed.
+///   A voice satisfies the languages feature if, for each language/accent pair in the list,
 ///   1. the voice is documented (see Voice descriptions) as reading/speaking a language that
-///   matches the Extended Language Range given by language according to the Extended Filtering
-///   matching algorithm [BCP47, Matching of Language Tags §3.3.2], and
+///      matches the Extended Language Range given by language according to the Extended Filtering
+///      matching algorithm [BCP47, Matching of Language Tags §3.3.2], and
 ///   2. if an accent is given, the voice is documented (see Voice descriptions) as
-///   reading/speaking the language above with an accent that matches the Extended Language Range
-///   given by accent according to the Extended Filtering matching algorithm [BCP47, Matching of
-///   Language Tags §3.3.2], except that the script and extension subtags of the accent must be
-///   ignored by the synthesis processor. It is recommended that authors and voice providers do
-///   not use the script or extension subtags for accents because they are not relevant for
-///   speaking.
+///      reading/speaking the language above with an accent that matches the Extended Language Range
+///      given by accent according to the Extended Filtering matching algorithm [BCP47, Matching of
+///      Language Tags §3.3.2], except that the script and extension subtags of the accent must be
+///      ignored by the synthesis processor. It is recommended that authors and voice providers do
+///      not use the script or extension subtags for accents because they are not relevant for
+///      speaking.
 ///
 /// For example, a languages value of "en:pt fr:ja" can legally be matched by any voice that can
 /// both read English (speaking it with a Portuguese accent) and read French (speaking it with a
@@ -1973,52 +2426,52 @@ impl FromStr for LanguageAccentPair {
 /// The behavior control attributes of voice are:
 ///
 /// * **required**: _optional_ attribute that specifies a set of features by their respective
-/// attribute names. This set of features is used by the voice selection algorithm described below.
-/// Valid values of required are a space-separated list composed of values from the list of feature
-/// names: "name", "languages", "gender", "age", "variant" or the empty string "". The default
-/// value for this attribute is "languages".
+///   attribute names. This set of features is used by the voice selection algorithm described below.
+///   Valid values of required are a space-separated list composed of values from the list of feature
+///   names: "name", "languages", "gender", "age", "variant" or the empty string "". The default
+///   value for this attribute is "languages".
 /// * **ordering**: _optional_ attribute that specifies the priority ordering of features. Valid
-/// values of ordering are a space-separated list composed of values from the list of feature
-/// names: "name", "languages", "gender", "age", "variant" or the empty string "", where features
-/// named earlier in the list have higher priority . The default value for this attribute is
-/// "languages". Features not listed in the ordering list have equal priority to each other but
-/// lower than that of the last feature in the list. Note that if the ordering attribute is set to
-/// the empty string then all features have the same priority.
+///   values of ordering are a space-separated list composed of values from the list of feature
+///   names: "name", "languages", "gender", "age", "variant" or the empty string "", where features
+///   named earlier in the list have higher priority . The default value for this attribute is
+///   "languages". Features not listed in the ordering list have equal priority to each other but
+///   lower than that of the last feature in the list. Note that if the ordering attribute is set to
+///   the empty string then all features have the same priority.
 /// * **onvoicefailure**: _optional_ attribute containing one value from the following enumerated
-/// list describing the desired behavior of the synthesis processor upon voice selection failure.
-/// The default value for this attribute is "priorityselect".
+///   list describing the desired behavior of the synthesis processor upon voice selection failure.
+///   The default value for this attribute is "priorityselect".
 ///     * *priorityselect* - the synthesis processor uses the values of all voice feature attributes
-///     to select a voice by feature priority, where the starting candidate set is the set of all
-///     available voices.
+///       to select a voice by feature priority, where the starting candidate set is the set of all
+///       available voices.
 ///     * *keepexisting* - the voice does not change.
 ///     * *processorchoice* - the synthesis processor chooses the behavior (either priorityselect or
-///     keepexisting).
+///       keepexisting).
 ///
 /// The following voice selection algorithm must be used:
 ///
 /// 1. All available voices are identified for which the values of all voice feature attributes
-/// listed in the required attribute value are matched. When the value of the required attribute is
-/// the empty string "", any and all voices are considered successful matches. If one or more voices
-/// are identified, the selection is considered successful; otherwise there is voice selection
-/// failure.
+///    listed in the required attribute value are matched. When the value of the required attribute is
+///    the empty string "", any and all voices are considered successful matches. If one or more voices
+///    are identified, the selection is considered successful; otherwise there is voice selection
+///    failure.
 /// 2. If a successful selection identifies only one voice, the synthesis processor must use that
-/// voice.
+///    voice.
 /// 3. If a successful selection identifies more than one voice, the remaining features (those not
-/// listed in the required attribute value) are used to choose a voice by feature priority, where
-/// the starting candidate set is the set of all voices identified.
+///    listed in the required attribute value) are used to choose a voice by feature priority, where
+///    the starting candidate set is the set of all voices identified.
 /// 4. If there is voice selection failure, a conforming synthesis processor must report the voice
-/// selection failure in addition to taking the action(s) expressed by the value of the
-/// onvoicefailure attribute.
+///    selection failure in addition to taking the action(s) expressed by the value of the
+///    onvoicefailure attribute.
 /// 5. To choose a voice by feature priority, each feature is taken in turn starting with the
-/// highest priority feature, as controlled by the ordering attribute.
+///    highest priority feature, as controlled by the ordering attribute.
 ///     * If at least one voice matches the value of the current voice feature attribute then all
-///     voices not matching that value are removed from the candidate set. If a single voice remains
-///     in the candidate set the synthesis processor must use it. If more than one voice remains in
-///     the candidate set then the next priority feature is examined for the candidate set.
+///       voices not matching that value are removed from the candidate set. If a single voice remains
+///       in the candidate set the synthesis processor must use it. If more than one voice remains in
+///       the candidate set then the next priority feature is examined for the candidate set.
 ///     * If no voices match the value of the current voice feature attribute then the next priority
-///     feature is examined for the candidate set.
+///       feature is examined for the candidate set.
 /// 6. After examining all feature attributes on the ordering list, if multiple voices remain in
-/// the candidate set, the synthesis processor must use any one of them.
+///    the candidate set, the synthesis processor must use any one of them.
 ///
 /// Although each attribute individually is optional, it is an error if no attributes are specified
 /// when the voice element is used.
@@ -2028,9 +2481,9 @@ impl FromStr for LanguageAccentPair {
 /// following:
 ///
 /// * a list of language tags [BCP47, Tags for Identifying Languages] representing the languages the
-/// voice can read.
+///   voice can read.
 /// * for each language, a language tag [BCP47, Tags for Identifying Languages] representing the
-/// accent the voice uses when reading the language.
+///   accent the voice uses when reading the language.
 ///
 /// Although indication of language (using xml:lang) and selection of voice (using voice) are
 /// independent, there is no requirement that a synthesis processor support every possible
@@ -2063,6 +2516,7 @@ impl FromStr for LanguageAccentPair {
 /// All Rights Reserved._
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct VoiceAttributes {
     /// OPTIONAL attribute indicating the preferred gender of the voice to speak the contained text.
     /// Enumerated values are: "male", "female", "neutral", or the empty string "".
@@ -2081,6 +2535,30 @@ pub struct VoiceAttributes {
     /// with OPTIONAL accent indication per language. Each language/accent pair is of the form "language" or "language:accent",
     /// where both language and accent MUST be an Extended Language Range, except that the values "und" and "zxx" are disallowed.
     pub languages: Vec<LanguageAccentPair>,
+    /// OPTIONAL attribute listing the voice features a voice must match to be selected. `None`
+    /// leaves it at the default, "languages".
+    pub required: Option<Vec<VoiceFeature>>,
+    /// OPTIONAL attribute listing the voice features in order of priority, highest first. `None`
+    /// leaves it at the default, "languages".
+    pub ordering: Option<Vec<VoiceFeature>>,
+    /// OPTIONAL attribute describing what to do when no voice matches the required features.
+    pub on_voice_failure: Option<OnVoiceFailure>,
+}
+
+impl VoiceAttributes {
+    /// The features a voice must match, "languages" unless the required attribute is set.
+    pub fn required_features(&self) -> Vec<VoiceFeature> {
+        self.required
+            .clone()
+            .unwrap_or_else(|| vec![VoiceFeature::Languages])
+    }
+
+    /// The features in order of priority, "languages" unless the ordering attribute is set.
+    pub fn feature_ordering(&self) -> Vec<VoiceFeature> {
+        self.ordering
+            .clone()
+            .unwrap_or_else(|| vec![VoiceFeature::Languages])
+    }
 }
 
 impl Display for VoiceAttributes {
@@ -2095,7 +2573,7 @@ impl Display for VoiceAttributes {
             write!(f, " variant=\"{}\"", variant)?;
         }
         if !self.name.is_empty() {
-            write!(f, " name=\"{}\"", escape(&self.name.join(" ")))?;
+            write!(f, " name=\"{}\"", escape(self.name.join(" ")))?;
         }
         if !self.languages.is_empty() {
             let languages_str = self
@@ -2107,6 +2585,22 @@ impl Display for VoiceAttributes {
 
             write!(f, " languages=\"{}\"", languages_str)?;
         }
+        let features = |features: &[VoiceFeature]| {
+            features
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        if let Some(required) = &self.required {
+            write!(f, " required=\"{}\"", features(required))?;
+        }
+        if let Some(ordering) = &self.ordering {
+            write!(f, " ordering=\"{}\"", features(ordering))?;
+        }
+        if let Some(on_voice_failure) = self.on_voice_failure {
+            write!(f, " onvoicefailure=\"{}\"", on_voice_failure)?;
+        }
 
         Ok(())
     }
@@ -2118,10 +2612,11 @@ impl Display for VoiceAttributes {
 ///
 /// "Speech Synthesis Markup Language (SSML) Version 1.1" _Copyright © 2010 W3C® (MIT, ERCIM, Keio),
 /// All Rights Reserved._
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
 #[cfg_attr(test, derive(fake::Dummy))]
 pub enum FetchHint {
     /// The processor can perform an optimisation where it fetches the audio before it is needed
+    #[default]
     Prefetch,
     /// The audio should only be fetched when needed
     Safe,
@@ -2153,12 +2648,6 @@ impl FromStr for FetchHint {
     }
 }
 
-impl Default for FetchHint {
-    fn default() -> Self {
-        Self::Prefetch
-    }
-}
-
 /// The audio element supports the insertion of recorded audio files and the insertion of other
 /// audio formats in conjunction with synthesized speech output. The audio element may be empty.
 /// If the audio element is not empty then the contents should be the marked-up text to be spoken if the audio document is not available. The alternate content may include text, speech markup, desc elements, or other audio elements. The alternate content may also be used when rendering the document to non-audible output and for accessibility (see the desc element).
@@ -2167,9 +2656,11 @@ impl Default for FetchHint {
 /// All Rights Reserved._
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(test, derive(fake::Dummy))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct AudioAttributes {
     /// The URI of a document with an appropriate media type. If absent, the audio element behaves
     /// as if src were present with a legal URI but the document could not be fetched.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::option_uri"))]
     pub src: Option<http::Uri>,
     /// The timeout for fetches.
     pub fetch_timeout: Option<TimeDesignation>,
@@ -2214,7 +2705,7 @@ impl Display for AudioAttributes {
         write!(f, " soundLevel=\"{}dB\"", self.sound_level)?;
         write!(f, " speed=\"{}%\"", self.speed * 100.0)?;
         if let Some(src) = &self.src {
-            write!(f, " src=\"{}\"", escape(&src.to_string()))?;
+            write!(f, " src=\"{}\"", escape(src.to_string()))?;
         }
         if let Some(timeout) = &self.fetch_timeout {
             write!(f, " fetchtimeout=\"{}\"", timeout)?;
@@ -2264,7 +2755,7 @@ mod tests {
             let xml = format!(
                 "<{} {}></{}>",
                 SsmlElement::Speak,
-                speak.to_string(),
+                speak,
                 SsmlElement::Speak
             );
             println!("{}", xml);
@@ -2273,7 +2764,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Speak);
                 assert_eq!(parsed_element, ParsedElement::Speak(speak));
@@ -2294,7 +2785,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Lang);
                 assert_eq!(parsed_element, ParsedElement::Lang(lang));
@@ -2304,6 +2795,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn text_structure_conversions() {
+        for _ in 0..30 {
+            let attrs: TextStructureAttributes = Faker.fake();
+
+            for element in [SsmlElement::Paragraph, SsmlElement::Sentence] {
+                let xml = format!("<{} {}></{}>", element, attrs, element);
+
+                let mut reader = Reader::from_reader(xml.as_ref());
+                let event = reader.read_event().unwrap();
+                if let Event::Start(bs) = event {
+                    let (ssml_element, parsed_element) =
+                        parse_element(bs, &mut reader, &[]).unwrap();
+
+                    assert_eq!(ssml_element, element);
+                    match parsed_element {
+                        ParsedElement::Paragraph(parsed) | ParsedElement::Sentence(parsed) => {
+                            assert_eq!(parsed, attrs)
+                        }
+                        e => panic!("Unexpected element {:?}", e),
+                    }
+                } else {
+                    panic!("Didn't get expected event");
+                }
+            }
+        }
+    }
+
     #[test]
     fn lookup_conversions() {
         for _ in 0..30 {
@@ -2320,7 +2839,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Lookup);
                 assert_eq!(parsed_element, ParsedElement::Lookup(look));
@@ -2341,7 +2860,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Meta);
                 assert_eq!(parsed_element, ParsedElement::Meta(meta));
@@ -2367,7 +2886,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Token);
                 assert_eq!(parsed_element, ParsedElement::Token(token.clone()));
@@ -2381,7 +2900,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Word);
                 assert_eq!(parsed_element, ParsedElement::Word(token));
@@ -2407,7 +2926,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::SayAs);
                 assert_eq!(parsed_element, ParsedElement::SayAs(say_as));
@@ -2433,7 +2952,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Phoneme);
                 assert_eq!(parsed_element, ParsedElement::Phoneme(attr));
@@ -2454,7 +2973,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Break);
                 assert_eq!(parsed_element, ParsedElement::Break(attr));
@@ -2483,7 +3002,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Prosody);
                 assert_eq!(parsed_element, ParsedElement::Prosody(attr));
@@ -2504,7 +3023,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Mark);
                 assert_eq!(parsed_element, ParsedElement::Mark(attr));
@@ -2530,7 +3049,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Emphasis);
                 assert_eq!(parsed_element, ParsedElement::Emphasis(attr));
@@ -2551,7 +3070,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Sub);
                 assert_eq!(parsed_element, ParsedElement::Sub(attr));
@@ -2577,7 +3096,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Lexicon);
                 assert_eq!(parsed_element, ParsedElement::Lexicon(attr));
@@ -2598,7 +3117,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Voice);
                 assert_eq!(parsed_element, ParsedElement::Voice(attr));
@@ -2619,7 +3138,7 @@ mod tests {
             let event = reader.read_event().unwrap();
             println!("{:?}", event);
             if let Event::Start(bs) = event {
-                let (ssml_element, parsed_element) = parse_element(bs, &mut reader).unwrap();
+                let (ssml_element, parsed_element) = parse_element(bs, &mut reader, &[]).unwrap();
 
                 assert_eq!(ssml_element, SsmlElement::Audio);
                 if let ParsedElement::Audio(parsed) = parsed_element {
@@ -0,0 +1,377 @@
+//! Errors produced when parsing SSML. Each error records where in the source document the problem
+//! was found so it can be reported back to whoever wrote the SSML.
+use std::error::Error;
+use std::fmt;
+
+/// A location within the source SSML document.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Position {
+    /// Offset from the start of the document in bytes.
+    pub byte: usize,
+    /// Line number, starting from 1.
+    pub line: usize,
+    /// Column within the line in unicode scalar values, starting from 1.
+    pub column: usize,
+}
+
+impl Position {
+    /// Works out the line and column of a byte offset within `source`. Offsets past the end of
+    /// the source are clamped to the end and offsets inside a multi-byte character are moved back
+    /// to the start of that character.
+    pub fn from_byte_offset(source: &str, byte: usize) -> Self {
+        let mut byte = byte.min(source.len());
+        while !source.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        let before = &source[..byte];
+        let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+        Self {
+            byte,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} column {}", self.line, self.column)
+    }
+}
+
+/// An error encountered when parsing an SSML document.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum SsmlError {
+    /// A required attribute wasn't present on an element.
+    MissingAttribute {
+        /// Name of the element missing the attribute.
+        element: String,
+        /// Name of the missing attribute.
+        attribute: String,
+        /// Location of the element in the source document.
+        position: Position,
+    },
+    /// An attribute was present but its value couldn't be parsed or isn't allowed.
+    InvalidAttributeValue {
+        /// Name of the element the attribute belongs to.
+        element: String,
+        /// Name of the attribute.
+        attribute: String,
+        /// The value which was rejected.
+        value: String,
+        /// Description of why the value was rejected.
+        reason: String,
+        /// Location of the element in the source document.
+        position: Position,
+    },
+    /// The text inside an element isn't what its attributes say it is, such as a say-as date
+    /// which isn't a real date.
+    InvalidContent {
+        /// Name of the element.
+        element: String,
+        /// The text inside the element.
+        text: String,
+        /// Description of why the text was rejected.
+        reason: String,
+        /// Location of the element in the source document.
+        position: Position,
+    },
+    /// A phoneme's transcription contains a symbol which isn't part of its alphabet.
+    InvalidPhoneme {
+        /// The transcription from the ph attribute.
+        ph: String,
+        /// Name of the alphabet the transcription was checked against.
+        alphabet: String,
+        /// The symbol which isn't in the alphabet.
+        phone: String,
+        /// Byte offset of the symbol in the ph attribute.
+        offset: usize,
+        /// Location of the phoneme element in the source document.
+        position: Position,
+    },
+    /// An element was placed inside one which isn't allowed to contain it.
+    InvalidNesting {
+        /// Name of the containing element.
+        parent: String,
+        /// Name of the element which can't be placed in the parent.
+        child: String,
+        /// Location of the child element in the source document.
+        position: Position,
+    },
+    /// A close tag was found with no matching open tag.
+    UnexpectedCloseTag {
+        /// Name of the element being closed.
+        element: String,
+        /// Location of the close tag in the source document.
+        position: Position,
+    },
+    /// The version attribute on the speak element isn't a version of SSML we support.
+    UnsupportedVersion {
+        /// The version given in the document.
+        version: String,
+        /// Location of the speak element in the source document.
+        position: Position,
+    },
+    /// The document isn't well-formed XML.
+    Xml {
+        /// The error reported by the XML reader.
+        error: quick_xml::Error,
+        /// Location of the malformed XML in the source document.
+        position: Position,
+    },
+    /// An XML entity was referenced which isn't one of the predefined XML entities.
+    UnknownEntity {
+        /// Name of the entity without the surrounding `&` and `;`.
+        entity: String,
+        /// Location of the entity reference in the source document.
+        position: Position,
+    },
+}
+
+impl SsmlError {
+    /// Location in the source document the error relates to.
+    pub fn position(&self) -> Position {
+        match self {
+            Self::MissingAttribute { position, .. }
+            | Self::InvalidAttributeValue { position, .. }
+            | Self::InvalidContent { position, .. }
+            | Self::InvalidPhoneme { position, .. }
+            | Self::InvalidNesting { position, .. }
+            | Self::UnexpectedCloseTag { position, .. }
+            | Self::UnsupportedVersion { position, .. }
+            | Self::Xml { position, .. }
+            | Self::UnknownEntity { position, .. } => *position,
+        }
+    }
+
+    /// Name of the element the error relates to if there is one. For nesting errors this is the
+    /// child element.
+    pub fn element(&self) -> Option<&str> {
+        match self {
+            Self::MissingAttribute { element, .. }
+            | Self::InvalidAttributeValue { element, .. }
+            | Self::InvalidContent { element, .. }
+            | Self::UnexpectedCloseTag { element, .. } => Some(element),
+            Self::InvalidNesting { child, .. } => Some(child),
+            Self::InvalidPhoneme { .. } => Some("phoneme"),
+            Self::UnsupportedVersion { .. } => Some("speak"),
+            Self::Xml { .. } | Self::UnknownEntity { .. } => None,
+        }
+    }
+
+    /// Name of the attribute the error relates to if there is one.
+    pub fn attribute(&self) -> Option<&str> {
+        match self {
+            Self::MissingAttribute { attribute, .. }
+            | Self::InvalidAttributeValue { attribute, .. } => Some(attribute),
+            Self::UnsupportedVersion { .. } => Some("version"),
+            Self::InvalidPhoneme { .. } => Some("ph"),
+            _ => None,
+        }
+    }
+
+    /// Errors are created while looking at a single element without knowledge of where it is in
+    /// the document, the parser then fills in the position afterwards.
+    pub(crate) fn with_position(mut self, new_position: Position) -> Self {
+        match &mut self {
+            Self::MissingAttribute { position, .. }
+            | Self::InvalidAttributeValue { position, .. }
+            | Self::InvalidContent { position, .. }
+            | Self::InvalidPhoneme { position, .. }
+            | Self::InvalidNesting { position, .. }
+            | Self::UnexpectedCloseTag { position, .. }
+            | Self::UnsupportedVersion { position, .. }
+            | Self::Xml { position, .. }
+            | Self::UnknownEntity { position, .. } => *position = new_position,
+        }
+        self
+    }
+}
+
+/// A problem with the document which was repaired by a lenient parse.
+#[derive(Clone, Debug)]
+pub struct ParseWarning {
+    /// The error a strict parse would have failed with.
+    pub error: SsmlError,
+    /// What was done to the document to work around the error.
+    pub repair: Repair,
+}
+
+impl ParseWarning {
+    /// Location in the source document of the repaired problem.
+    pub fn position(&self) -> Position {
+        self.error.position()
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.repair, self.error)
+    }
+}
+
+/// The repairs a lenient parse can make to a document.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Repair {
+    /// The attribute was removed from the element, so it takes the default value.
+    RemovedAttribute,
+    /// The element's open and close tags were removed, any text or elements inside it are kept.
+    RemovedElement,
+    /// The entity reference was removed from the text.
+    RemovedEntity,
+    /// The close tag was ignored.
+    IgnoredCloseTag,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::RemovedAttribute => "removed attribute",
+                Self::RemovedElement => "removed element",
+                Self::RemovedEntity => "removed entity",
+                Self::IgnoredCloseTag => "ignored close tag",
+            }
+        )
+    }
+}
+
+impl fmt::Display for SsmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAttribute {
+                element,
+                attribute,
+                position,
+            } => write!(
+                f,
+                "{} attribute is required with a {} element ({})",
+                attribute, element, position
+            ),
+            Self::InvalidAttributeValue {
+                element,
+                attribute,
+                value,
+                reason,
+                position,
+            } => write!(
+                f,
+                "invalid value '{}' for {} attribute of {} element: {} ({})",
+                value, attribute, element, reason, position
+            ),
+            Self::InvalidContent {
+                element,
+                text,
+                reason,
+                position,
+            } => write!(
+                f,
+                "invalid text '{}' in {} element: {} ({})",
+                text, element, reason, position
+            ),
+            Self::InvalidPhoneme {
+                ph,
+                alphabet,
+                phone,
+                offset,
+                position,
+            } => write!(
+                f,
+                "invalid phone '{}' at offset {} of '{}' for {} alphabet ({})",
+                phone, offset, ph, alphabet, position
+            ),
+            Self::InvalidNesting {
+                parent,
+                child,
+                position,
+            } => write!(
+                f,
+                "{} cannot be placed inside {} ({})",
+                child, parent, position
+            ),
+            Self::UnexpectedCloseTag { element, position } => write!(
+                f,
+                "close tag '{}' presented without open tag ({})",
+                element, position
+            ),
+            Self::UnsupportedVersion { version, position } => {
+                write!(
+                    f,
+                    "unsupported SSML spec version: {} ({})",
+                    version, position
+                )
+            }
+            Self::Xml { error, position } => write!(f, "invalid XML: {} ({})", error, position),
+            Self::UnknownEntity { entity, position } => {
+                write!(f, "unrecognized XML entity: &{}; ({})", entity, position)
+            }
+        }
+    }
+}
+
+impl Error for SsmlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Xml { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<quick_xml::Error> for SsmlError {
+    fn from(error: quick_xml::Error) -> Self {
+        Self::Xml {
+            error,
+            position: Position::default(),
+        }
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for SsmlError {
+    fn from(error: quick_xml::events::attributes::AttrError) -> Self {
+        quick_xml::Error::InvalidAttr(error).into()
+    }
+}
+
+impl From<quick_xml::encoding::EncodingError> for SsmlError {
+    fn from(error: quick_xml::encoding::EncodingError) -> Self {
+        quick_xml::Error::Encoding(error).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_from_offset() {
+        let source = "<speak>\n  <s>héllo</s>\n</speak>";
+
+        assert_eq!(
+            Position::from_byte_offset(source, 0),
+            Position {
+                byte: 0,
+                line: 1,
+                column: 1
+            }
+        );
+        let s_tag = source.find("<s>").unwrap();
+        assert_eq!(
+            Position::from_byte_offset(source, s_tag),
+            Position {
+                byte: s_tag,
+                line: 2,
+                column: 3
+            }
+        );
+        let close = source.find("</s>").unwrap();
+        assert_eq!(Position::from_byte_offset(source, close).column, 11);
+        // Inside the é goes back to the start of the character
+        let e = source.find('é').unwrap();
+        assert_eq!(Position::from_byte_offset(source, e + 1).byte, e);
+        assert_eq!(Position::from_byte_offset(source, 1000).byte, source.len());
+    }
+}
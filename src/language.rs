@@ -0,0 +1,155 @@
+//! Works out the language and voice in effect for each run of text in a document. The language
+//! is set by the xml:lang attribute of `<speak>`, `<lang>`, `<p>` and `<s>` and the innermost one
+//! applies, as does the innermost onlangfailure. Voice elements nest as well and each one can
+//! leave some of its attributes unset, so the whole stack of voice elements around the text is
+//! given to let the caller decide how to combine them.
+use crate::elements::*;
+use crate::language_tag::LanguageTag;
+use crate::Ssml;
+use std::ops::Range;
+
+/// A run of text with the same language and voices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LanguageRun<'a> {
+    /// Range of the text in unicode scalar values, the same unit as `Span`.
+    pub range: Range<usize>,
+    /// The language tag of the text, `None` if the document doesn't say what language it's in.
+    pub lang: Option<&'a LanguageTag>,
+    /// What to do if the selected voice can't speak the language. When no element sets this it's
+    /// left to the processor, `OnLanguageFailure::ProcessorChoice`.
+    pub on_lang_failure: OnLanguageFailure,
+    /// The voice elements around the text, outermost first.
+    pub voices: Vec<&'a VoiceAttributes>,
+}
+
+impl<'a> LanguageRun<'a> {
+    /// The innermost voice element around the text.
+    pub fn voice(&self) -> Option<&'a VoiceAttributes> {
+        self.voices.last().copied()
+    }
+}
+
+impl Ssml {
+    /// Splits the text into runs with the same language, language failure policy and voices.
+    pub fn language_runs(&self) -> Vec<LanguageRun<'_>> {
+        let mut runs: Vec<LanguageRun> = vec![];
+        for node in self.root().descendants().filter(|x| x.is_text()) {
+            let mut elements = node
+                .ancestors()
+                .filter_map(|x| x.element())
+                .collect::<Vec<_>>();
+            elements.reverse();
+
+            let mut run = LanguageRun {
+                range: node.char_range(),
+                lang: None,
+                on_lang_failure: OnLanguageFailure::ProcessorChoice,
+                voices: vec![],
+            };
+            for element in elements {
+                let (lang, on_lang_failure) = match element {
+                    ParsedElement::Speak(attrs) => (attrs.lang.as_ref(), attrs.on_lang_failure),
+                    ParsedElement::Lang(attrs) => (Some(&attrs.lang), attrs.on_lang_failure),
+                    ParsedElement::Paragraph(attrs) | ParsedElement::Sentence(attrs) => {
+                        (attrs.lang.as_ref(), attrs.on_lang_failure)
+                    }
+                    ParsedElement::Voice(attrs) => {
+                        run.voices.push(attrs);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if let Some(lang) = lang {
+                    run.lang = Some(lang);
+                }
+                if let Some(on_lang_failure) = on_lang_failure {
+                    run.on_lang_failure = on_lang_failure;
+                }
+            }
+
+            match runs.last_mut() {
+                Some(last)
+                    if last.range.end == run.range.start
+                        && last.lang == run.lang
+                        && last.on_lang_failure == run.on_lang_failure
+                        && last.voices == run.voices =>
+                {
+                    last.range.end = run.range.end;
+                }
+                _ => runs.push(run),
+            }
+        }
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::char_to_byte_range;
+    use crate::parse_ssml;
+
+    #[test]
+    fn language_and_voice_runs() {
+        let ssml = parse_ssml(
+            r#"<speak version="1.1" xml:lang="en-GB" onlangfailure="ignoretext">
+            Hello <lang xml:lang="fr-FR">bonjour</lang>
+            <p xml:lang="de-DE"><s>Guten Tag</s><s xml:lang="it-IT" onlangfailure="changevoice">ciao</s></p>
+            <voice gender="female" name="Alice Beth"><voice age="30">Nested <break/>voice</voice> outer</voice>
+            </speak>"#,
+        )
+        .unwrap();
+
+        let runs = ssml
+            .language_runs()
+            .into_iter()
+            .map(|run| {
+                let text = &ssml.get_text()[char_to_byte_range(ssml.get_text(), run.range.clone())];
+                (text.trim().to_string(), run)
+            })
+            .filter(|(text, _)| !text.is_empty())
+            .collect::<Vec<_>>();
+
+        let summary = runs
+            .iter()
+            .map(|(text, run)| {
+                (
+                    text.as_str(),
+                    run.lang.unwrap().to_string(),
+                    run.on_lang_failure,
+                    run.voices.len(),
+                )
+            })
+            .collect::<Vec<_>>();
+        use OnLanguageFailure::*;
+        assert_eq!(
+            summary,
+            [
+                ("Hello", "en-GB", IgnoreText, 0),
+                ("bonjour", "fr-FR", IgnoreText, 0),
+                ("Guten Tag", "de-DE", IgnoreText, 0),
+                ("ciao", "it-IT", ChangeVoice, 0),
+                ("Nested voice", "en-GB", IgnoreText, 2),
+                ("outer", "en-GB", IgnoreText, 1),
+            ]
+            .map(|(text, lang, on_lang_failure, voices)| {
+                (text, lang.to_string(), on_lang_failure, voices)
+            })
+        );
+
+        let nested = &runs[4].1;
+        assert_eq!(nested.voices[0].gender, Some(Gender::Female));
+        assert_eq!(nested.voices[0].name, ["Alice", "Beth"]);
+        assert_eq!(nested.voice().unwrap().age, Some(30));
+    }
+
+    #[test]
+    fn no_language() {
+        let ssml = parse_ssml("<speak>Hi</speak>").unwrap();
+        let runs = ssml.language_runs();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].lang, None);
+        assert_eq!(runs[0].on_lang_failure, OnLanguageFailure::ProcessorChoice);
+        assert!(runs[0].voice().is_none());
+    }
+}
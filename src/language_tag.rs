@@ -0,0 +1,621 @@
+//! BCP 47 language tags and the extended language ranges used to match them. SSML uses language
+//! tags for the xml:lang attribute and extended language ranges for the languages attribute of
+//! `<voice>`, which is matched against the languages a voice can speak using the extended
+//! filtering algorithm from RFC 4647.
+//!
+//! Tags are checked against the syntax in RFC 5646 but not against the IANA subtag registry, so a
+//! well formed tag for a language which doesn't exist is still accepted. The one exception is the
+//! primary language subtag, none of four to eight letters have been registered so those are
+//! rejected as they're almost always a language name like "english" rather than a code.
+use anyhow::bail;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Grandfathered tags from the IANA registry which don't follow the current syntax, or only do by
+/// accident, and the tags they should be replaced with if there are any.
+const GRANDFATHERED: &[(&str, Option<&str>)] = &[
+    ("art-lojban", Some("jbo")),
+    ("cel-gaulish", None),
+    ("en-GB-oed", Some("en-GB-oxendict")),
+    ("i-ami", Some("ami")),
+    ("i-bnn", Some("bnn")),
+    ("i-default", None),
+    ("i-enochian", None),
+    ("i-hak", Some("hak")),
+    ("i-klingon", Some("tlh")),
+    ("i-lux", Some("lb")),
+    ("i-mingo", None),
+    ("i-navajo", Some("nv")),
+    ("i-pwn", Some("pwn")),
+    ("i-tao", Some("tao")),
+    ("i-tay", Some("tay")),
+    ("i-tsu", Some("tsu")),
+    ("no-bok", Some("nb")),
+    ("no-nyn", Some("nn")),
+    ("sgn-BE-FR", Some("sfb")),
+    ("sgn-BE-NL", Some("vgt")),
+    ("sgn-CH-DE", Some("sgg")),
+    ("zh-guoyu", Some("cmn")),
+    ("zh-hakka", Some("hak")),
+    ("zh-min", None),
+    ("zh-min-nan", Some("nan")),
+    ("zh-xiang", Some("hsn")),
+];
+
+/// Deprecated primary language subtags and their replacements.
+const DEPRECATED_LANGUAGES: &[(&str, &str)] = &[
+    ("in", "id"),
+    ("iw", "he"),
+    ("ji", "yi"),
+    ("jw", "jv"),
+    ("mo", "ro"),
+];
+
+/// Deprecated region subtags and their replacements.
+const DEPRECATED_REGIONS: &[(&str, &str)] = &[
+    ("BU", "MM"),
+    ("DD", "DE"),
+    ("FX", "FR"),
+    ("TP", "TL"),
+    ("ZR", "CD"),
+];
+
+/// A BCP 47 language tag such as "en-GB" or "zh-Hant-TW". Subtags are stored with the case
+/// recommended by RFC 5646, language in lower case, script in title case and region in upper
+/// case, comparisons between tags are otherwise exact so use `canonicalise` first to treat
+/// deprecated and preferred forms of a tag as equal.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct LanguageTag {
+    /// Boxed to keep the attributes holding a tag small.
+    parts: Box<Parts>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+struct Parts {
+    /// Primary language subtag, empty for private use tags and the whole tag for grandfathered
+    /// ones.
+    language: String,
+    extlangs: Vec<String>,
+    script: Option<String>,
+    region: Option<String>,
+    variants: Vec<String>,
+    extensions: Vec<Extension>,
+    private_use: Vec<String>,
+    grandfathered: bool,
+}
+
+/// An extension to a language tag, a singleton identifying the extension followed by its
+/// subtags. For example "u-co-phonebk" is the Unicode locale extension setting the collation.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Extension {
+    /// Single character identifying the extension.
+    pub singleton: char,
+    /// Subtags of the extension.
+    pub subtags: Vec<String>,
+}
+
+impl Default for Parts {
+    fn default() -> Self {
+        Self {
+            language: "und".to_string(),
+            extlangs: vec![],
+            script: None,
+            region: None,
+            variants: vec![],
+            extensions: vec![],
+            private_use: vec![],
+            grandfathered: false,
+        }
+    }
+}
+
+impl Default for LanguageTag {
+    /// The "und" tag for an undetermined language.
+    fn default() -> Self {
+        Self {
+            parts: Box::default(),
+        }
+    }
+}
+
+impl LanguageTag {
+    /// The primary language subtag, such as "en". Empty for private use tags like "x-whatever".
+    pub fn language(&self) -> &str {
+        &self.parts.language
+    }
+
+    /// Extended language subtags, these follow the primary language when it's a macrolanguage
+    /// like "zh" in "zh-yue".
+    pub fn extlangs(&self) -> &[String] {
+        &self.parts.extlangs
+    }
+
+    /// The script subtag, such as "Latn".
+    pub fn script(&self) -> Option<&str> {
+        self.parts.script.as_deref()
+    }
+
+    /// The region subtag, either a two letter country code such as "GB" or a three digit UN M.49
+    /// area code such as "419".
+    pub fn region(&self) -> Option<&str> {
+        self.parts.region.as_deref()
+    }
+
+    /// Variant subtags, such as "1996" for the 1996 German orthography.
+    pub fn variants(&self) -> &[String] {
+        &self.parts.variants
+    }
+
+    /// Extensions in the order they appear in the tag.
+    pub fn extensions(&self) -> &[Extension] {
+        &self.parts.extensions
+    }
+
+    /// Private use subtags, the ones after "x".
+    pub fn private_use(&self) -> &[String] {
+        &self.parts.private_use
+    }
+
+    /// Whether this is one of the grandfathered tags registered before the current syntax. The
+    /// whole tag is treated as the language subtag.
+    pub fn is_grandfathered(&self) -> bool {
+        self.parts.grandfathered
+    }
+
+    /// Converts the tag to canonical form as described in RFC 5646 §4.5. Grandfathered tags and
+    /// deprecated subtags are replaced where the registry gives a replacement, tags with an
+    /// extended language subtag use it as the primary language ("zh-yue" becomes "yue") and
+    /// extensions are sorted by their singleton.
+    pub fn canonicalise(&self) -> LanguageTag {
+        if self.parts.grandfathered {
+            return GRANDFATHERED
+                .iter()
+                .find(|(tag, _)| tag.eq_ignore_ascii_case(&self.parts.language))
+                .and_then(|(_, preferred)| *preferred)
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_else(|| self.clone());
+        }
+        let mut tag = self.clone();
+        if let Some(extlang) = tag.parts.extlangs.first() {
+            tag.parts.language = extlang.clone();
+            tag.parts.extlangs.clear();
+        }
+        if let Some((_, preferred)) = DEPRECATED_LANGUAGES
+            .iter()
+            .find(|(deprecated, _)| *deprecated == tag.parts.language)
+        {
+            tag.parts.language = preferred.to_string();
+        }
+        if let Some(region) = &tag.parts.region {
+            if let Some((_, preferred)) = DEPRECATED_REGIONS
+                .iter()
+                .find(|(deprecated, _)| deprecated == region)
+            {
+                tag.parts.region = Some(preferred.to_string());
+            }
+        }
+        tag.parts.extensions.sort_by_key(|x| x.singleton);
+        tag
+    }
+
+    /// The tag with the script, extension and private use subtags removed. SSML ignores these
+    /// when matching the accent of a voice.
+    pub(crate) fn without_script_and_extensions(&self) -> LanguageTag {
+        let mut tag = self.clone();
+        tag.parts.script = None;
+        tag.parts.extensions.clear();
+        tag.parts.private_use.clear();
+        tag
+    }
+
+    fn subtags(&self) -> Vec<String> {
+        let mut subtags = vec![];
+        if !self.parts.language.is_empty() {
+            subtags.push(self.parts.language.clone());
+        }
+        subtags.extend(self.parts.extlangs.iter().cloned());
+        subtags.extend(self.parts.script.iter().cloned());
+        subtags.extend(self.parts.region.iter().cloned());
+        subtags.extend(self.parts.variants.iter().cloned());
+        for extension in &self.parts.extensions {
+            subtags.push(extension.singleton.to_string());
+            subtags.extend(extension.subtags.iter().cloned());
+        }
+        if !self.parts.private_use.is_empty() {
+            subtags.push("x".to_string());
+            subtags.extend(self.parts.private_use.iter().cloned());
+        }
+        subtags
+    }
+}
+
+impl Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.parts.grandfathered {
+            let tag = GRANDFATHERED
+                .iter()
+                .find(|(tag, _)| tag.eq_ignore_ascii_case(&self.parts.language))
+                .map(|(tag, _)| *tag)
+                .unwrap_or(&self.parts.language);
+            return write!(f, "{}", tag);
+        }
+        write!(f, "{}", self.subtags().join("-"))
+    }
+}
+
+/// Compares with a tag written as a string, ignoring case as tags are case insensitive.
+impl PartialEq<str> for LanguageTag {
+    fn eq(&self, other: &str) -> bool {
+        self.to_string().eq_ignore_ascii_case(other)
+    }
+}
+
+impl PartialEq<&str> for LanguageTag {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+fn is_alpha(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_digit(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_variant(s: &str) -> bool {
+    (5..=8).contains(&s.len()) || (s.len() == 4 && s.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+fn title_case(s: &str) -> String {
+    let s = s.to_ascii_lowercase();
+    s[..1].to_ascii_uppercase() + &s[1..]
+}
+
+impl FromStr for LanguageTag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            bail!("Empty language tag");
+        }
+        if s.contains('_') {
+            bail!(
+                "Invalid language tag '{}', subtags are separated by '-' not '_'",
+                s
+            );
+        }
+        if let Some((tag, _)) = GRANDFATHERED
+            .iter()
+            .find(|(tag, _)| tag.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self {
+                parts: Box::new(Parts {
+                    language: tag.to_ascii_lowercase(),
+                    grandfathered: true,
+                    ..Default::default()
+                }),
+            });
+        }
+
+        let subtags = s.split('-').collect::<Vec<_>>();
+        if let Some(subtag) = subtags
+            .iter()
+            .find(|x| x.is_empty() || x.len() > 8 || !x.chars().all(|c| c.is_ascii_alphanumeric()))
+        {
+            bail!("Invalid subtag '{}' in language tag '{}'", subtag, s);
+        }
+        let mut subtags = subtags.into_iter().peekable();
+        let mut tag = LanguageTag {
+            parts: Box::new(Parts {
+                language: String::new(),
+                ..Default::default()
+            }),
+        };
+
+        let first = subtags.peek().copied().unwrap_or_default();
+        if !first.eq_ignore_ascii_case("x") {
+            subtags.next();
+            if !is_alpha(first) {
+                bail!("Invalid language subtag '{}' in '{}'", first, s);
+            } else if first.len() > 3 {
+                bail!(
+                    "'{}' isn't a language code, use a two or three letter code like 'en'",
+                    first
+                );
+            } else if first.len() < 2 {
+                bail!("Language tag '{}' must start with a language subtag", s);
+            }
+            tag.parts.language = first.to_ascii_lowercase();
+
+            while let Some(subtag) = subtags.next_if(|x| x.len() == 3 && is_alpha(x)) {
+                if tag.parts.extlangs.len() == 3 {
+                    bail!("Too many extended language subtags in '{}'", s);
+                }
+                tag.parts.extlangs.push(subtag.to_ascii_lowercase());
+            }
+            if let Some(script) = subtags.next_if(|x| x.len() == 4 && is_alpha(x)) {
+                tag.parts.script = Some(title_case(script));
+            }
+            if let Some(region) =
+                subtags.next_if(|x| (x.len() == 2 && is_alpha(x)) || (x.len() == 3 && is_digit(x)))
+            {
+                tag.parts.region = Some(region.to_ascii_uppercase());
+            }
+            while let Some(variant) = subtags.next_if(|x| is_variant(x)) {
+                let variant = variant.to_ascii_lowercase();
+                if tag.parts.variants.contains(&variant) {
+                    bail!("Duplicate variant '{}' in language tag '{}'", variant, s);
+                }
+                tag.parts.variants.push(variant);
+            }
+            while let Some(singleton) =
+                subtags.next_if(|x| x.len() == 1 && !x.eq_ignore_ascii_case("x"))
+            {
+                let singleton = singleton.to_ascii_lowercase().chars().next().unwrap();
+                if tag
+                    .parts
+                    .extensions
+                    .iter()
+                    .any(|x| x.singleton == singleton)
+                {
+                    bail!(
+                        "Duplicate extension '{}' in language tag '{}'",
+                        singleton,
+                        s
+                    );
+                }
+                let mut extension = Extension {
+                    singleton,
+                    subtags: vec![],
+                };
+                while let Some(subtag) = subtags.next_if(|x| x.len() > 1) {
+                    extension.subtags.push(subtag.to_ascii_lowercase());
+                }
+                if extension.subtags.is_empty() {
+                    bail!("Empty extension '{}' in language tag '{}'", singleton, s);
+                }
+                tag.parts.extensions.push(extension);
+            }
+        }
+        if subtags.next_if(|x| x.eq_ignore_ascii_case("x")).is_some() {
+            tag.parts.private_use = subtags.by_ref().map(|x| x.to_ascii_lowercase()).collect();
+            if tag.parts.private_use.is_empty() {
+                bail!("Empty private use section in language tag '{}'", s);
+            }
+        }
+        if let Some(subtag) = subtags.next() {
+            bail!("Unexpected subtag '{}' in language tag '{}'", subtag, s);
+        }
+        Ok(tag)
+    }
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for LanguageTag {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &fake::Faker, rng: &mut R) -> Self {
+        use fake::RngExt;
+        let tags = [
+            "en",
+            "en-GB",
+            "zh-Hant-TW",
+            "sr-Latn-RS",
+            "de-DE-1996",
+            "es-419",
+            "zh-yue-HK",
+            "de-CH-u-co-phonebk",
+            "en-US-x-twain",
+            "i-klingon",
+        ];
+        tags[rng.random_range(0..tags.len())].parse().unwrap()
+    }
+}
+
+/// An extended language range from RFC 4647 such as "en", "de-*-DE" or "*-GB", where "*" matches
+/// any sequence of subtags.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct LanguageRange {
+    subtags: Vec<String>,
+}
+
+impl LanguageRange {
+    /// Whether the tag matches the range using extended filtering, RFC 4647 §3.3.2. Every subtag
+    /// in the range must appear in the tag in order, other subtags may come between them as long
+    /// as they aren't a singleton introducing an extension.
+    pub fn matches(&self, tag: &LanguageTag) -> bool {
+        let tag = match tag.parts.grandfathered {
+            true => tag.to_string().split('-').map(|x| x.to_string()).collect(),
+            false => tag.subtags(),
+        };
+        let range = &self.subtags;
+        if range[0] != "*" && !range[0].eq_ignore_ascii_case(&tag[0]) {
+            return false;
+        }
+        let (mut r, mut t) = (1, 1);
+        while r < range.len() {
+            if range[r] == "*" {
+                r += 1;
+            } else if t >= tag.len() {
+                return false;
+            } else if range[r].eq_ignore_ascii_case(&tag[t]) {
+                r += 1;
+                t += 1;
+            } else if tag[t].len() == 1 {
+                return false;
+            } else {
+                t += 1;
+            }
+        }
+        true
+    }
+
+    /// The range with anything that looks like a script or extension removed, for matching
+    /// against a tag from `LanguageTag::without_script_and_extensions`.
+    pub(crate) fn without_script_and_extensions(&self) -> LanguageRange {
+        let subtags = self
+            .subtags
+            .iter()
+            .enumerate()
+            .take_while(|(_, subtag)| subtag.len() != 1 || *subtag == "*")
+            .filter(|(i, subtag)| *i == 0 || subtag.len() != 4 || !is_alpha(subtag))
+            .map(|(_, subtag)| subtag.clone())
+            .collect();
+        LanguageRange { subtags }
+    }
+}
+
+impl Display for LanguageRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.subtags.join("-"))
+    }
+}
+
+impl FromStr for LanguageRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            bail!("Empty language range");
+        }
+        if s.contains('_') {
+            bail!(
+                "Invalid language range '{}', subtags are separated by '-' not '_'",
+                s
+            );
+        }
+        let subtags = s.split('-').map(|x| x.to_string()).collect::<Vec<_>>();
+        for (i, subtag) in subtags.iter().enumerate() {
+            let valid = subtag == "*"
+                || (!subtag.is_empty()
+                    && subtag.len() <= 8
+                    && subtag.chars().all(|c| {
+                        if i == 0 {
+                            c.is_ascii_alphabetic()
+                        } else {
+                            c.is_ascii_alphanumeric()
+                        }
+                    }));
+            if !valid {
+                bail!("Invalid subtag '{}' in language range '{}'", subtag, s);
+            }
+        }
+        Ok(LanguageRange { subtags })
+    }
+}
+
+#[cfg(test)]
+impl fake::Dummy<fake::Faker> for LanguageRange {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &fake::Faker, rng: &mut R) -> Self {
+        use fake::RngExt;
+        let ranges = ["en", "en-GB", "*-GB", "de-*-DE", "zh-Hant", "pt-BR", "fr"];
+        ranges[rng.random_range(0..ranges.len())].parse().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(s: &str) -> LanguageTag {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_tags() {
+        let zh = tag("ZH-hant-tw");
+        assert_eq!(zh.to_string(), "zh-Hant-TW");
+        assert_eq!(zh, "zh-hant-tw");
+        assert_eq!(zh.language(), "zh");
+        assert_eq!(zh.script(), Some("Hant"));
+        assert_eq!(zh.region(), Some("TW"));
+
+        let full = tag("sl-rozaj-biske-1994-a-foo-bar-u-nu-thai-x-private");
+        assert_eq!(full.variants(), ["rozaj", "biske", "1994"]);
+        assert_eq!(full.extensions().len(), 2);
+        assert_eq!(full.extensions()[1].singleton, 'u');
+        assert_eq!(full.extensions()[1].subtags, ["nu", "thai"]);
+        assert_eq!(full.private_use(), ["private"]);
+        assert_eq!(
+            full.to_string(),
+            "sl-rozaj-biske-1994-a-foo-bar-u-nu-thai-x-private"
+        );
+
+        assert_eq!(tag("es-419").region(), Some("419"));
+        assert_eq!(tag("zh-yue-HK").extlangs(), ["yue"]);
+        assert_eq!(tag("x-whatever").to_string(), "x-whatever");
+        assert_eq!(tag("x-whatever").language(), "");
+        assert!(tag("I-KLINGON").is_grandfathered());
+        assert_eq!(tag("I-KLINGON").to_string(), "i-klingon");
+
+        for invalid in [
+            "",
+            "en_US",
+            "english",
+            "e",
+            "en-",
+            "en--GB",
+            "1en",
+            "en-GB-a",
+            "de-DE-1901-1901",
+            "en-a-foo-a-bar",
+            "en-x",
+            "en-GB-Latn",
+            "en-abcdefghi",
+        ] {
+            assert!(invalid.parse::<LanguageTag>().is_err(), "{}", invalid);
+        }
+        let error = "en_US".parse::<LanguageTag>().unwrap_err().to_string();
+        assert!(error.contains("'-' not '_'"));
+    }
+
+    #[test]
+    fn canonicalise() {
+        let canonical = |s: &str| tag(s).canonicalise().to_string();
+        assert_eq!(canonical("iw-IL"), "he-IL");
+        assert_eq!(canonical("zh-yue-HK"), "yue-HK");
+        assert_eq!(canonical("de-DD"), "de-DE");
+        assert_eq!(canonical("en-u-nu-thai-a-foo"), "en-a-foo-u-nu-thai");
+        assert_eq!(canonical("i-klingon"), "tlh");
+        assert_eq!(canonical("i-default"), "i-default");
+        assert_eq!(canonical("en-GB"), "en-GB");
+    }
+
+    #[test]
+    fn extended_filtering() {
+        let range = |s: &str| s.parse::<LanguageRange>().unwrap();
+        for tag in [
+            "de-DE",
+            "de-de",
+            "de-Latn-DE",
+            "de-Latf-DE",
+            "de-DE-x-goethe",
+            "de-Latn-DE-1996",
+            "de-Deva-DE",
+        ] {
+            let tag = tag.parse().unwrap();
+            assert!(range("de-*-DE").matches(&tag), "{}", tag);
+            assert!(range("*-DE").matches(&tag), "{}", tag);
+            assert!(range("*").matches(&tag), "{}", tag);
+        }
+        for tag in ["de", "de-x-DE", "de-Deva", "fr-DE"] {
+            let tag = tag.parse().unwrap();
+            assert!(!range("de-*-DE").matches(&tag), "{}", tag);
+        }
+        assert!(range("en").matches(&tag("en-US")));
+        assert!(!range("en-US").matches(&tag("en")));
+
+        let accent = range("pt-Latn-BR-u-co-phonebk").without_script_and_extensions();
+        assert_eq!(accent.to_string(), "pt-BR");
+        assert_eq!(
+            tag("pt-Latn-BR-u-co-phonebk-x-private")
+                .without_script_and_extensions()
+                .to_string(),
+            "pt-BR"
+        );
+
+        for invalid in ["", "en_US", "1-GB", "en--GB", "en-abcdefghi"] {
+            assert!(invalid.parse::<LanguageRange>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Applies the pronunciation lexicons a document refers to, for engines which don't support PLS.
+//! Lexicons are declared with the lexicon element and used for the words inside lookup elements
+//! which refer to them by xml:id. Nested lookup elements are searched innermost first. Each word
+//! with an entry in a lexicon is wrapped in a phoneme or sub element with its pronunciation, and
+//! the role of a token element around a word picks which lexeme is used.
+//!
+//! Fetching the lexicons is left to a `LexiconFetcher`, `FileFetcher` and `MemoryFetcher` are
+//! provided and callers can implement it for HTTP or any other way they store lexicons.
+use crate::elements::*;
+use crate::pls::{parse_lexicon, Lexicon, Pronunciation};
+use crate::{ParserEvent, Ssml};
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Gets the PLS documents lexicon elements refer to.
+pub trait LexiconFetcher {
+    /// Fetches the document at the URI. Fetchers which can take a long time should give up once
+    /// the timeout from the lexicon's fetchtimeout attribute has passed.
+    fn fetch(&self, uri: &http::Uri, timeout: Option<Duration>) -> anyhow::Result<String>;
+}
+
+/// Reads lexicons from files. URIs without a scheme are paths relative to the root directory,
+/// URIs with a scheme such as "http" aren't supported. Lexicon URIs come from the document so
+/// paths with `..` or other components which could leave the root directory are rejected.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileFetcher {
+    root: PathBuf,
+}
+
+impl FileFetcher {
+    /// Creates a fetcher reading files from the root directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl LexiconFetcher for FileFetcher {
+    fn fetch(&self, uri: &http::Uri, _timeout: Option<Duration>) -> anyhow::Result<String> {
+        if let Some(scheme) = uri.scheme_str() {
+            bail!("{} URIs can't be read from the filesystem", scheme);
+        }
+        // A relative path like "lexicon.pls" is parsed as an authority
+        let path = match uri.authority() {
+            Some(authority) => format!("{}{}", authority, uri.path()),
+            None => uri.path().to_string(),
+        };
+        let path = Path::new(path.trim_start_matches('/'));
+        if !path.components().all(|x| matches!(x, Component::Normal(_))) {
+            bail!("{} is outside of the lexicon directory", uri);
+        }
+        let path = self.root.join(path);
+        std::fs::read_to_string(&path).with_context(|| format!("couldn't read {}", path.display()))
+    }
+}
+
+/// Holds lexicons in memory, useful for tests or lexicons that have already been fetched.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryFetcher {
+    documents: HashMap<String, String>,
+}
+
+impl MemoryFetcher {
+    /// Adds a PLS document which is returned for the given URI.
+    pub fn insert(&mut self, uri: impl Into<String>, pls: impl Into<String>) {
+        self.documents.insert(uri.into(), pls.into());
+    }
+}
+
+impl LexiconFetcher for MemoryFetcher {
+    fn fetch(&self, uri: &http::Uri, _timeout: Option<Duration>) -> anyhow::Result<String> {
+        match self.documents.get(&uri.to_string()) {
+            Some(pls) => Ok(pls.clone()),
+            None => bail!("there's no lexicon at {}", uri),
+        }
+    }
+}
+
+/// Result of applying the lexicons of a document.
+#[derive(Clone, Debug)]
+pub struct AppliedLexicons {
+    /// The document with words from the lexicons wrapped in phoneme and sub elements.
+    pub output: Ssml,
+    /// Lexicons which couldn't be used, the words in their lookup elements are left alone.
+    pub failures: Vec<LexiconFailure>,
+}
+
+/// A lexicon which couldn't be used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexiconFailure {
+    /// The xml:id the lexicon is referred to by.
+    pub id: String,
+    /// Why it couldn't be used.
+    pub reason: String,
+}
+
+/// Fetches and parses the lexicon, honouring its type and fetch timeout.
+fn load(attrs: &LexiconAttributes, fetcher: &impl LexiconFetcher) -> anyhow::Result<Lexicon> {
+    if let Some(ty) = &attrs.ty {
+        if !ty
+            .essence()
+            .to_string()
+            .eq_ignore_ascii_case("application/pls+xml")
+        {
+            bail!("lexicons of type {} aren't supported", ty);
+        }
+    }
+    let timeout = attrs.fetch_timeout.map(|x| x.duration());
+    let start = Instant::now();
+    let pls = fetcher.fetch(&attrs.uri, timeout)?;
+    if let Some(timeout) = timeout {
+        if start.elapsed() > timeout {
+            bail!("fetching {} took longer than {:?}", attrs.uri, timeout);
+        }
+    }
+    Ok(parse_lexicon(&pls)?)
+}
+
+/// Rewrites the text of a document using the lexicons of the lookup elements it's inside.
+struct Applier<'a> {
+    lexicons: &'a HashMap<String, Lexicon>,
+    /// Lexicons of the open lookup elements, `None` for ones which couldn't be loaded
+    lookups: Vec<Option<&'a Lexicon>>,
+    /// Depth of elements whose text already says how it's pronounced
+    verbatim: usize,
+    /// The role and text of the token element being read
+    token: Option<(Option<String>, String)>,
+    output: Vec<ParserEvent>,
+}
+
+impl<'a> Applier<'a> {
+    fn active(&self) -> impl Iterator<Item = &'a Lexicon> + '_ {
+        self.lookups.iter().rev().flatten().copied()
+    }
+
+    fn pronunciation(&self, grapheme: &str, role: Option<&str>) -> Option<&'a Pronunciation> {
+        match role {
+            Some(roles) => roles.split_whitespace().find_map(|role| {
+                self.active()
+                    .find_map(|x| x.pronunciation(grapheme, Some(role)))
+            }),
+            None => self.active().find_map(|x| x.pronunciation(grapheme, None)),
+        }
+    }
+
+    fn push_pronounced(&mut self, text: &str, pronunciation: &Pronunciation) {
+        let element = match pronunciation {
+            Pronunciation::Phoneme { ph, alphabet, .. } => {
+                ParsedElement::Phoneme(PhonemeAttributes {
+                    ph: ph.clone(),
+                    alphabet: Some(alphabet.clone()),
+                })
+            }
+            Pronunciation::Alias { alias, .. } => ParsedElement::Sub(SubAttributes {
+                alias: alias.clone(),
+            }),
+        };
+        self.output.push(ParserEvent::Open(element.clone()));
+        self.output.push(ParserEvent::Text(text.to_string()));
+        self.output.push(ParserEvent::Close(element));
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.output.push(ParserEvent::Text(text.to_string()));
+        }
+    }
+
+    /// Wraps the words of the text which are in a lexicon, longer graphemes are matched before
+    /// the words in them.
+    fn rewrite_words(&mut self, text: &str) {
+        let max_words = self
+            .active()
+            .flat_map(|x| x.lexemes())
+            .flat_map(|x| &x.graphemes)
+            .map(|x| x.split_whitespace().count())
+            .max()
+            .unwrap_or(1);
+        let words = text.unicode_word_indices().collect::<Vec<_>>();
+        let mut written = 0;
+        let mut i = 0;
+        while i < words.len() {
+            let start = words[i].0;
+            let found = (i..words.len().min(i + max_words)).rev().find_map(|last| {
+                let (end_start, end_word) = words[last];
+                let end = end_start + end_word.len();
+                self.pronunciation(&text[start..end], None)
+                    .map(|x| (last, end, x))
+            });
+            match found {
+                Some((last, end, pronunciation)) => {
+                    self.push_text(&text[written..start]);
+                    self.push_pronounced(&text[start..end], pronunciation);
+                    written = end;
+                    i = last + 1;
+                }
+                None => i += 1,
+            }
+        }
+        self.push_text(&text[written..]);
+    }
+
+    /// Writes out the text of a token element, the whole token is looked up as one grapheme.
+    fn finish_token(&mut self) {
+        if let Some((role, text)) = self.token.take() {
+            let grapheme = text.trim();
+            match self.pronunciation(grapheme, role.as_deref()) {
+                Some(pronunciation) if !grapheme.is_empty() => {
+                    let start = text.len() - text.trim_start().len();
+                    self.push_text(&text[..start]);
+                    self.push_pronounced(grapheme, pronunciation);
+                    self.push_text(&text[start + grapheme.len()..]);
+                }
+                _ => self.push_text(&text),
+            }
+        }
+    }
+
+    fn apply(&mut self, event: ParserEvent) {
+        let rewriting = self.verbatim == 0 && self.active().next().is_some();
+        match event {
+            ParserEvent::Text(text) => match &mut self.token {
+                Some((_, token)) => token.push_str(&text),
+                None if rewriting => self.rewrite_words(&text),
+                None => self.push_text(&text),
+            },
+            ParserEvent::Open(element) => {
+                // Tokens are only looked up as a whole if they just contain text
+                self.finish_token();
+                match &element {
+                    ParsedElement::Lookup(attrs) => {
+                        self.lookups.push(self.lexicons.get(&attrs.lookup_ref));
+                    }
+                    ParsedElement::Phoneme(_) | ParsedElement::Sub(_) | ParsedElement::SayAs(_) => {
+                        self.verbatim += 1;
+                    }
+                    ParsedElement::Token(attrs) | ParsedElement::Word(attrs) if rewriting => {
+                        self.token = Some((attrs.role.clone(), String::new()));
+                    }
+                    _ => {}
+                }
+                self.output.push(ParserEvent::Open(element));
+            }
+            ParserEvent::Close(element) => {
+                self.finish_token();
+                match &element {
+                    ParsedElement::Lookup(_) => {
+                        self.lookups.pop();
+                    }
+                    ParsedElement::Phoneme(_) | ParsedElement::Sub(_) | ParsedElement::SayAs(_) => {
+                        self.verbatim -= 1;
+                    }
+                    _ => {}
+                }
+                self.output.push(ParserEvent::Close(element));
+            }
+            ParserEvent::Empty(element) => {
+                self.finish_token();
+                self.output.push(ParserEvent::Empty(element));
+            }
+        }
+    }
+}
+
+impl Ssml {
+    /// Fetches the lexicons used by lookup elements and wraps the words inside each lookup which
+    /// are in its lexicon in a phoneme or sub element. Lexicons which can't be fetched or parsed,
+    /// and lookups of lexicons which aren't declared, are reported and their words left alone.
+    pub fn apply_lexicons(&self, fetcher: &impl LexiconFetcher) -> AppliedLexicons {
+        let mut declared = HashMap::new();
+        let mut referenced = vec![];
+        for node in self.root().descendants() {
+            match node.element() {
+                Some(ParsedElement::Lexicon(attrs)) => {
+                    declared.entry(attrs.xml_id.as_str()).or_insert(attrs);
+                }
+                Some(ParsedElement::Lookup(attrs)) if !referenced.contains(&&attrs.lookup_ref) => {
+                    referenced.push(&attrs.lookup_ref);
+                }
+                _ => {}
+            }
+        }
+
+        let mut lexicons = HashMap::new();
+        let mut failures = vec![];
+        for id in referenced {
+            let loaded = match declared.get(id.as_str()) {
+                Some(attrs) => load(attrs, fetcher),
+                None => Err(anyhow::anyhow!("there's no lexicon element with this id")),
+            };
+            match loaded {
+                Ok(lexicon) => {
+                    lexicons.insert(id.clone(), lexicon);
+                }
+                Err(e) => failures.push(LexiconFailure {
+                    id: id.clone(),
+                    reason: format!("{:#}", e),
+                }),
+            }
+        }
+
+        let mut applier = Applier {
+            lexicons: &lexicons,
+            lookups: vec![],
+            verbatim: 0,
+            token: None,
+            output: vec![],
+        };
+        for event in self.event_iter() {
+            applier.apply(event);
+        }
+        AppliedLexicons {
+            output: Ssml::from_events(applier.output),
+            failures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_ssml;
+
+    const LEXICON: &str = r#"<lexicon version="1.0" alphabet="ipa" xml:lang="en-GB"
+        xmlns="http://www.w3.org/2005/01/pronunciation-lexicon">
+      <lexeme><grapheme>tomato</grapheme><phoneme>təˈmɑːtəʊ</phoneme></lexeme>
+      <lexeme><grapheme>W3C</grapheme><alias>World Wide Web Consortium</alias></lexeme>
+      <lexeme><grapheme>New York</grapheme><alias>the Big Apple</alias></lexeme>
+      <lexeme role="claws:VVD"><grapheme>read</grapheme><phoneme>rɛd</phoneme></lexeme>
+      <lexeme><grapheme>read</grapheme><phoneme>riːd</phoneme></lexeme>
+    </lexicon>"#;
+
+    const OTHER: &str = r#"<lexicon version="1.0" alphabet="x-sampa" xml:lang="en-GB">
+      <lexeme><grapheme>tomato</grapheme><phoneme>t@"meIt@U</phoneme></lexeme>
+    </lexicon>"#;
+
+    fn fetcher() -> MemoryFetcher {
+        let mut fetcher = MemoryFetcher::default();
+        fetcher.insert("http://example.com/main.pls", LEXICON);
+        fetcher.insert("http://example.com/us.pls", OTHER);
+        fetcher
+    }
+
+    #[test]
+    fn apply_lexicons() {
+        let ssml = parse_ssml(
+            r#"<speak version="1.1">
+            <lexicon uri="http://example.com/main.pls" xml:id="main"/>
+            <lexicon uri="http://example.com/us.pls" xml:id="us" type="application/pls+xml"/>
+            A tomato. <lookup ref="main">The W3C likes tomato, I <w role="claws:VVD">read</w> in
+            New York. <phoneme ph="x">tomato</phoneme>
+            <lookup ref="us">tomato <sub alias="a">W3C</sub></lookup></lookup>
+            </speak>"#,
+        )
+        .unwrap();
+        let applied = ssml.apply_lexicons(&fetcher());
+        assert!(applied.failures.is_empty(), "{:?}", applied.failures);
+        assert_eq!(applied.output.get_text(), ssml.get_text());
+        let written = applied.output.write_ssml();
+        for expected in [
+            "A tomato. <lookup ref=\"main\">The <sub alias=\"World Wide Web Consortium\">W3C</sub>",
+            "likes <phoneme ph=\"təˈmɑːtəʊ\" alphabet=\"ipa\">tomato</phoneme>, I",
+            "<w role=\"claws:VVD\"><phoneme ph=\"rɛd\" alphabet=\"ipa\">read</phoneme></w>",
+            "<sub alias=\"the Big Apple\">New York</sub>.",
+            "<phoneme ph=\"x\">tomato</phoneme>",
+            "<lookup ref=\"us\"><phoneme ph=\"t@&quot;meIt@U\" alphabet=\"x-sampa\">tomato</phoneme>",
+            "<sub alias=\"a\">W3C</sub></lookup>",
+        ] {
+            assert!(written.contains(expected), "{} not in {}", expected, written);
+        }
+    }
+
+    #[test]
+    fn lexicon_failures() {
+        let ssml = parse_ssml(
+            r#"<speak version="1.1">
+            <lexicon uri="http://example.com/missing.pls" xml:id="missing"/>
+            <lexicon uri="http://example.com/main.pls" xml:id="text" type="text/plain"/>
+            <lexicon uri="http://example.com/main.pls" xml:id="main" fetchtimeout="10s"/>
+            <lookup ref="missing">tomato</lookup> <lookup ref="text">tomato</lookup>
+            <lookup ref="undeclared">tomato</lookup> <lookup ref="main">tomato</lookup>
+            </speak>"#,
+        )
+        .unwrap();
+        let applied = ssml.apply_lexicons(&fetcher());
+        let failed = applied
+            .failures
+            .iter()
+            .map(|x| x.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(failed, ["missing", "text", "undeclared"]);
+        assert_eq!(applied.output.write_ssml().matches("<phoneme").count(), 1);
+    }
+
+    #[test]
+    fn file_fetcher() {
+        let dir = std::env::temp_dir().join(format!("ssml-lexicons-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lexicons")).unwrap();
+        std::fs::write(dir.join("lexicons/main.pls"), LEXICON).unwrap();
+        std::fs::write(dir.join("top.pls"), OTHER).unwrap();
+        let fetcher = FileFetcher::new(&dir);
+        let fetch = |uri: &str| fetcher.fetch(&uri.parse().unwrap(), None);
+        assert_eq!(fetch("/lexicons/main.pls").unwrap(), LEXICON);
+        assert_eq!(fetch("top.pls").unwrap(), OTHER);
+        assert!(fetch("missing.pls").is_err());
+        assert!(fetch("http://example.com/top.pls").is_err());
+
+        // Documents can't read files outside of the root
+        std::fs::write(dir.join("secret.txt"), "hunter2").unwrap();
+        let fetcher = FileFetcher::new(dir.join("lexicons"));
+        let fetch = |uri: &str| fetcher.fetch(&uri.parse().unwrap(), None);
+        assert_eq!(fetch("main.pls").unwrap(), LEXICON);
+        for uri in [
+            "/../secret.txt",
+            "/./../secret.txt",
+            "/lexicons/../../secret.txt",
+            "/./main.pls",
+        ] {
+            let err = fetch(uri).unwrap_err().to_string();
+            assert!(
+                err.contains("outside of the lexicon directory"),
+                "{}: {}",
+                uri,
+                err
+            );
+            assert!(!err.contains("hunter2"));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
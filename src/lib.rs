@@ -1,14 +1,42 @@
 #![doc = include_str!("../README.md")]
-use crate::{elements::SsmlElement, parser::Span};
+use crate::{elements::SsmlElement, parser::Span, tree::DocumentTree};
 use elements::ParsedElement;
 use std::fmt;
 use std::ops::FnMut;
 
 // Public re-export
+pub use crate::alphabet::Alphabet;
+pub use crate::builder::SsmlBuilder;
+pub use crate::error::{ParseWarning, Position, Repair, SsmlError};
+pub use crate::language_tag::{LanguageRange, LanguageTag};
+pub use crate::offset::OffsetUnit;
 pub use crate::parser::parse_ssml;
+pub use crate::source_map::SourceMap;
+pub use crate::tree::Node;
 
+pub mod alphabet;
+pub mod builder;
+pub mod dialects;
 pub mod elements;
+pub mod error;
+pub mod language;
+pub mod language_tag;
+pub mod lexicon;
+pub mod marks;
+pub mod normalise;
+pub mod offset;
 pub mod parser;
+pub mod pls;
+pub mod profile;
+pub mod prosody;
+pub mod say_as;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod source_map;
+pub mod timeline;
+pub mod transpile;
+pub mod tree;
+pub mod voice;
 
 /// Holds parsed SSML string with the text minus tags and the tag information
 #[derive(Clone, Debug)]
@@ -19,11 +47,18 @@ pub struct Ssml {
     pub(crate) tags: Vec<Span>,
     /// Simple parse tree to represent the XML document structure
     pub(crate) event_log: ParserLog,
+    /// Element tree built from the event log
+    pub(crate) tree: DocumentTree,
+    /// Problems repaired during a lenient parse
+    pub(crate) warnings: Vec<ParseWarning>,
+    /// Where the text came from in the source, only set for parsed documents
+    pub(crate) source_map: Option<SourceMap>,
 }
 
 /// After applying a transformation to SSML writes out the new SSML string and also the
 /// text to be processed by a speech synthesiser. Assumes all text in custom tags is synthesisable.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct TransformedSsml {
     /// Generated SSML String after transformation
     pub ssml_string: String,
@@ -36,8 +71,13 @@ type ParserLog = Vec<ParserLogEvent>;
 
 /// Represents the XML document structure
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "kebab-case")
+)]
 pub(crate) enum ParserLogEvent {
-    /// Text within tags with the start and end character indices
+    /// Text within tags with the start and end byte offsets in the text
     Text((usize, usize)),
     /// An XML open tag
     Open(ParsedElement),
@@ -51,6 +91,11 @@ pub(crate) enum ParserLogEvent {
 /// transform of the tree without worrying about ownership issues so will take an owned copy of
 /// substrings of the tag-less text.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "kebab-case")
+)]
 pub enum ParserEvent {
     /// Some text within a pair of XML tags
     Text(String),
@@ -94,19 +139,58 @@ impl fmt::Display for ParserEvent {
 }
 
 impl Ssml {
+    /// Creates the document from the text and the events describing where the elements are in
+    /// the text. Every byte of the text must be covered by a text event.
+    pub(crate) fn new(text: String, event_log: ParserLog) -> Self {
+        let tree = DocumentTree::new(&text, &event_log);
+        Self {
+            tags: tree.spans(),
+            text,
+            event_log,
+            tree,
+            warnings: vec![],
+            source_map: None,
+        }
+    }
+
+    /// Creates a document from a stream of events such as those from `event_iter`. The text is
+    /// kept as it is and the events aren't checked, so every open tag must have a close tag.
+    pub(crate) fn from_events(events: impl IntoIterator<Item = ParserEvent>) -> Self {
+        let mut text = String::new();
+        let mut event_log = vec![];
+        for event in events {
+            event_log.push(match event {
+                ParserEvent::Text(t) => {
+                    let start = text.len();
+                    text.push_str(&t);
+                    ParserLogEvent::Text((start, text.len()))
+                }
+                ParserEvent::Open(element) => ParserLogEvent::Open(element),
+                ParserEvent::Close(element) => ParserLogEvent::Close(element),
+                ParserEvent::Empty(element) => ParserLogEvent::Empty(element),
+            });
+        }
+        Self::new(text, event_log)
+    }
+
     /// Gets a version of the text with all the SSML tags stripped
     pub fn get_text(&self) -> &str {
         &self.text
     }
 
+    /// Problems with the source document which were repaired when parsing it. This is always
+    /// empty unless the parser was built with lenient parsing enabled.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
     /// From a given span with start/end characters return the text within that span.
     ///
     /// # Panics
     ///
     /// Will panic if span exceeds the bounds of the text.
     pub fn get_text_from_span(&self, span: &Span) -> &str {
-        assert!(span.end <= self.text.len() && span.end >= span.start);
-        &self.text[span.start..span.end]
+        &self.text[self.span_range(span, OffsetUnit::Bytes)]
     }
 
     /// Get an iterator over the SSML tags - traversed depth first.
@@ -114,6 +198,12 @@ impl Ssml {
         self.tags.iter()
     }
 
+    /// The root of the element tree. Its children are the top level elements and text in the
+    /// document.
+    pub fn root(&self) -> Node<'_> {
+        Node::root(self)
+    }
+
     /// Write out the SSML text again - mainly used for testing correctness of implementation.
     pub fn write_ssml(&self) -> String {
         let mut ssml_str = String::new();
@@ -245,13 +335,13 @@ mod tests {
     #[test]
     fn basic_ssml_writing() {
         let ssml = r#"
-        <speak version="1.0" xml:lang="string" foo="&amp;" xmlns="http://www.w3.org/2001/10/synthesis" xmlns:mstts="https://www.w3.org/2001/mstts">
+        <speak version="1.0" xml:lang="en-US" foo="&amp;" xmlns="http://www.w3.org/2001/10/synthesis" xmlns:mstts="https://www.w3.org/2001/mstts">
             <mstts:backgroundaudio fadein="string" fadeout="string" src="string" volume="string"/>
             <voice name="string">
                 <bookmark mark="string"/>
                 <break strength="medium" time="5s"/>
                 <emphasis level="reduced"/>
-                <lang xml:lang="string"/>
+                <lang xml:lang="fr-FR"/>
                 <lexicon uri="string" xml:id="some_id"/>
                 <math xmlns="http://www.w3.org/1998/Math/MathML"/>
                 <mstts:express-as role="string" style="string" styledegree="value"/>
@@ -300,7 +390,7 @@ mod tests {
         println!("Rewritten:");
         println!("{}", rewritten_trimmed);
 
-        assert!(&ssml == &rewritten_trimmed);
+        assert!(ssml == rewritten_trimmed);
     }
 
     #[test]
@@ -311,7 +401,7 @@ mod tests {
             <voice name="string">
                 <break strength="medium" time="5s"/>
                 <emphasis level="reduced"/>
-                <lang xml:lang="string"/>
+                <lang xml:lang="fr-FR"/>
                 <lexicon uri="string" xml:id="some_id"/>
                 <mstts:express-as role="string" style="string" styledegree="value"/>
                 <p>Some speech! &amp; With correct escaping on text, hopefully. </p>
@@ -0,0 +1,294 @@
+//! Marks placed in the text with `<mark/>`, or Azure's `<bookmark/>`, and where they fall in the
+//! text. Synthesisers report when they reach each mark so this lets captions and other things
+//! following the audio be lined up with the text.
+#[cfg(feature = "azure")]
+use crate::dialects::{microsoft::MicrosoftElement, DialectElement};
+use crate::elements::ParsedElement;
+use crate::{OffsetUnit, ParserEvent, Ssml};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A mark in the document and where it is in the text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mark<'a> {
+    /// Name of the mark.
+    pub name: &'a str,
+    /// Offset of the mark in the text in unicode scalar values.
+    pub offset: usize,
+    /// Index of the first word starting at or after the mark, for a mark after the last word this
+    /// is the number of words in the text.
+    pub word: usize,
+    /// The sentence containing the mark in unicode scalar values. This is the closest `<s>`
+    /// element around the mark, or the sentence found from unicode sentence boundaries if the
+    /// mark isn't in one.
+    pub sentence: Range<usize>,
+}
+
+/// The name of the element if it's a mark.
+pub(crate) fn mark_name(element: &ParsedElement) -> Option<&str> {
+    match element {
+        ParsedElement::Mark(attrs) => Some(&attrs.name),
+        #[cfg(feature = "azure")]
+        ParsedElement::Dialect(DialectElement::Microsoft(MicrosoftElement::Bookmark(attrs))) => {
+            Some(&attrs.mark)
+        }
+        _ => None,
+    }
+}
+
+impl Ssml {
+    /// Every mark in the document in document order.
+    pub fn marks(&self) -> Vec<Mark<'_>> {
+        let text = self.get_text();
+        let words = text
+            .unicode_word_indices()
+            .map(|(start, _)| start)
+            .collect::<Vec<_>>();
+        // Sentence boundaries include the whitespace after the sentence, which isn't wanted
+        let sentences = text
+            .split_sentence_bound_indices()
+            .map(|(start, sentence)| start..start + sentence.trim_end().len())
+            .collect::<Vec<_>>();
+        let chars = |byte| OffsetUnit::Chars.from_byte_offset(text, byte);
+
+        self.root()
+            .descendants()
+            .filter_map(|node| {
+                let name = node.element().and_then(mark_name)?;
+                let byte = node.byte_range().start;
+                let sentence = node
+                    .ancestors()
+                    .find(|x| matches!(x.element(), Some(ParsedElement::Sentence(_))))
+                    .map(|x| x.char_range())
+                    .unwrap_or_else(|| {
+                        let index = sentences.partition_point(|x| x.start <= byte);
+                        match index.checked_sub(1).map(|i| &sentences[i]) {
+                            Some(x) => chars(x.start)..chars(x.end.max(byte)),
+                            None => chars(byte)..chars(byte),
+                        }
+                    });
+                Some(Mark {
+                    name,
+                    offset: chars(byte),
+                    word: words.partition_point(|x| *x < byte),
+                    sentence,
+                })
+            })
+            .collect()
+    }
+
+    /// The part of the text to render in unicode scalar values. This starts at the mark named by
+    /// the startmark attribute of the speak element and ends at the mark named by endmark, either
+    /// end is the end of the text if the attribute isn't given or there's no mark with that name.
+    pub fn rendered_range(&self) -> Range<usize> {
+        let len = self.get_text().chars().count();
+        let Some(ParsedElement::Speak(speak)) = self.root().children().find_map(|x| x.element())
+        else {
+            return 0..len;
+        };
+        let marks = self.marks();
+        let find = |name: &Option<String>| {
+            let name = name.as_deref()?;
+            marks.iter().find(|x| x.name == name).map(|x| x.offset)
+        };
+        let start = find(&speak.start_mark).unwrap_or(0);
+        let end = find(&speak.end_mark).unwrap_or(len);
+        start..end.max(start)
+    }
+
+    /// Creates a document with just the content between two marks, such as to carry on speaking
+    /// a document from the last mark reached. The elements around the start mark are opened
+    /// again at the start of the new document and any still open at the end mark are closed.
+    /// Without a start mark the document starts from the beginning and without an end mark it
+    /// goes to the end. The marks themselves aren't included, but lexicon and meta elements
+    /// in the speak element before the start are kept so words are still pronounced the same.
+    ///
+    /// Returns `None` if there's no mark with one of the names or the end mark is before the
+    /// start mark.
+    pub fn slice_between_marks(&self, start: Option<&str>, end: Option<&str>) -> Option<Ssml> {
+        let source = self.event_iter().collect::<Vec<_>>();
+        let find = |name: &str| {
+            source.iter().position(|x| match x {
+                ParserEvent::Open(e) | ParserEvent::Empty(e) => mark_name(e) == Some(name),
+                _ => false,
+            })
+        };
+        let first = match start {
+            // A mark written with open and close tags ends at its close tag
+            Some(name) => match find(name)? {
+                index if matches!(source[index], ParserEvent::Open(_)) => index + 2,
+                index => index + 1,
+            },
+            None => 0,
+        };
+        let end = match end {
+            Some(name) => find(name)?,
+            None => source.len(),
+        };
+        if end < first {
+            return None;
+        }
+
+        let mut open = vec![];
+        let mut kept = vec![];
+        for event in source[..first].iter().cloned() {
+            match event {
+                ParserEvent::Open(e) => open.push(e),
+                ParserEvent::Close(_) => {
+                    open.pop();
+                }
+                ParserEvent::Empty(e @ (ParsedElement::Lexicon(_) | ParsedElement::Meta(_)))
+                    if open.len() == 1 =>
+                {
+                    kept.push(ParserEvent::Empty(e));
+                }
+                _ => {}
+            }
+        }
+        // Kept elements go straight inside the speak element
+        let mut events = open
+            .iter()
+            .cloned()
+            .map(ParserEvent::Open)
+            .collect::<Vec<_>>();
+        events.splice(events.len().min(1)..events.len().min(1), kept);
+        for event in source[first..end].iter().cloned() {
+            match &event {
+                ParserEvent::Open(e) => open.push(e.clone()),
+                ParserEvent::Close(_) => {
+                    open.pop();
+                }
+                _ => {}
+            }
+            events.push(event);
+        }
+        events.extend(open.into_iter().rev().map(ParserEvent::Close));
+
+        // The marks named on the speak element aren't in the new document
+        let events = events.into_iter().map(|event| {
+            let strip = |e| match e {
+                ParsedElement::Speak(mut attrs) => {
+                    attrs.start_mark = None;
+                    attrs.end_mark = None;
+                    ParsedElement::Speak(attrs)
+                }
+                e => e,
+            };
+            match event {
+                ParserEvent::Open(e) => ParserEvent::Open(strip(e)),
+                ParserEvent::Close(e) => ParserEvent::Close(strip(e)),
+                event => event,
+            }
+        });
+        Some(Ssml::from_events(events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offset::char_to_byte_range;
+    use crate::parse_ssml;
+
+    #[test]
+    fn mark_positions() {
+        let ssml = parse_ssml(
+            r#"<speak><mark name="start"/>Hello there. How <mark name="mid"/>are you? <s>Fine <mark name="s"/>thanks</s><mark name="end"/></speak>"#,
+        )
+        .unwrap();
+        let text = ssml.get_text();
+        let len = text.chars().count();
+        let text_at = |range| &text[char_to_byte_range(text, range)];
+        let marks = ssml.marks();
+        let names = marks.iter().map(|x| x.name).collect::<Vec<_>>();
+        assert_eq!(names, ["start", "mid", "s", "end"]);
+
+        assert_eq!(marks[0].offset, 0);
+        assert_eq!(marks[0].word, 0);
+        assert_eq!(text_at(marks[0].sentence.clone()), "Hello there.");
+
+        assert_eq!(text_at(marks[1].offset..len), "are you? Fine thanks");
+        assert_eq!(marks[1].word, 3);
+        assert_eq!(text_at(marks[1].sentence.clone()), "How are you?");
+
+        assert_eq!(marks[2].word, 6);
+        assert_eq!(text_at(marks[2].sentence.clone()), "Fine thanks");
+
+        assert_eq!(marks[3].offset, len);
+        assert_eq!(marks[3].word, 7);
+    }
+
+    #[test]
+    fn marks_in_chars() {
+        let ssml = parse_ssml(r#"<speak>Café <mark name="a"/>crème</speak>"#).unwrap();
+        let marks = ssml.marks();
+        assert_eq!(marks[0].offset, 5);
+        assert_eq!(marks[0].sentence, 0..10);
+        assert_eq!(ssml.rendered_range(), 0..10);
+    }
+
+    #[test]
+    fn rendered_range() {
+        let ssml = parse_ssml(
+            r#"<speak startmark="a" endmark="b">Skip this. <mark name="a"/>Say this. <mark name="b"/>Not this.</speak>"#,
+        )
+        .unwrap();
+        let text = ssml.get_text();
+        assert_eq!(
+            &text[char_to_byte_range(text, ssml.rendered_range())],
+            "Say this. "
+        );
+        assert!(ssml.write_ssml().contains(r#"startmark="a" endmark="b""#));
+    }
+
+    #[test]
+    fn slice_between_marks() {
+        let ssml = parse_ssml(
+            r#"<speak xml:lang="en-GB" startmark="a"><lexicon uri="lex.pls" xml:id="lex"/><p><s>One. <mark name="a"/>Two <emphasis>three <mark name="b"/>four</emphasis></s><s>Five</s></p></speak>"#,
+        )
+        .unwrap();
+
+        let sliced = ssml.slice_between_marks(Some("a"), Some("b")).unwrap();
+        assert_eq!(sliced.get_text(), "Two three ");
+        assert_eq!(
+            sliced.write_ssml(),
+            r#"<speak version="1.1" xml:lang="en-GB"><lexicon uri="lex.pls" xml:id="lex"/><p><s>Two <emphasis>three </emphasis></s></p></speak>"#
+        );
+
+        let sliced = ssml.slice_between_marks(Some("b"), None).unwrap();
+        assert_eq!(
+            sliced.write_ssml(),
+            r#"<speak version="1.1" xml:lang="en-GB"><lexicon uri="lex.pls" xml:id="lex"/><p><s><emphasis>four</emphasis></s> <s>Five</s></p></speak>"#
+        );
+        // The result is a document in its own right
+        assert_eq!(
+            parse_ssml(&sliced.write_ssml()).unwrap().get_text(),
+            "four Five"
+        );
+
+        let sliced = ssml.slice_between_marks(None, Some("a")).unwrap();
+        assert_eq!(sliced.get_text(), "One. ");
+        assert_eq!(sliced.marks(), []);
+
+        assert!(ssml.slice_between_marks(Some("b"), Some("a")).is_none());
+        assert!(ssml.slice_between_marks(Some("missing"), None).is_none());
+    }
+
+    #[cfg(feature = "azure")]
+    #[test]
+    fn azure_bookmarks() {
+        use crate::dialects::Dialect;
+        use crate::parser::SsmlParserBuilder;
+
+        let parser = SsmlParserBuilder::default()
+            .dialect(Dialect::Microsoft)
+            .build()
+            .unwrap();
+        let ssml = parser
+            .parse(r#"<speak>One <bookmark mark="b"/>two</speak>"#)
+            .unwrap();
+        let marks = ssml.marks();
+        assert_eq!(marks.len(), 1);
+        assert_eq!(marks[0].name, "b");
+        assert_eq!(marks[0].word, 1);
+    }
+}
@@ -0,0 +1,595 @@
+//! Expands the content of say-as elements into the words a speech synthesiser should read, so
+//! documents can be sent to engines which don't support say-as. How things are read depends on the
+//! language so this is done by a `Normaliser`, with `English` provided. `Ssml::expand_say_as`
+//! replaces each say-as element with its expanded text.
+use crate::elements::*;
+use crate::say_as::*;
+use crate::{ParserEvent, Ssml, TransformedSsml};
+use anyhow::bail;
+
+/// Reads out the content types of say-as in one language. Implementors give the reading of each
+/// content type and `normalise` parses the text of an element and picks the right one.
+pub trait Normaliser {
+    /// Reads a cardinal number, "42" is "forty-two".
+    fn cardinal(&self, number: &Number) -> String;
+
+    /// Reads an ordinal number, 42 is "forty-second".
+    fn ordinal(&self, number: u64) -> String;
+
+    /// Spells out the text one character at a time.
+    fn characters(&self, text: &str) -> String;
+
+    /// Reads each digit on its own, "123" is "one two three".
+    fn digits(&self, text: &str) -> String;
+
+    /// Reads a date, only the fields which are set should be read.
+    fn date(&self, date: &Date) -> String;
+
+    /// Reads a time of day.
+    fn time(&self, time: &Time) -> String;
+
+    /// Reads a telephone number as written, including its punctuation.
+    fn telephone(&self, text: &str) -> String;
+
+    /// Reads an amount of money, failing if the currency isn't known.
+    fn currency(&self, currency: &Currency) -> anyhow::Result<String>;
+
+    /// Reads content types without a method of their own such as units. By default these aren't
+    /// supported.
+    fn other(&self, interpret_as: &InterpretAs, text: &str) -> anyhow::Result<String> {
+        bail!("'{}' can't be read as {}", text, interpret_as)
+    }
+
+    /// Reads the text of a say-as element with the given attributes. Whitespace around the text
+    /// is kept so the expanded words don't run into the text around the element.
+    fn normalise(&self, attrs: &SayAsAttributes, text: &str) -> anyhow::Result<String> {
+        let content = text.trim();
+        let words = match &attrs.interpret_as {
+            InterpretAs::Date => self.date(&attrs.date(content)?),
+            InterpretAs::Time => self.time(&attrs.time(content)?),
+            InterpretAs::Cardinal => self.cardinal(&parse_number(content)?),
+            InterpretAs::Ordinal => self.ordinal(parse_ordinal(content)?),
+            InterpretAs::Characters => self.characters(content),
+            InterpretAs::Digits | InterpretAs::Telephone => {
+                attrs.check_text(content)?;
+                match attrs.interpret_as {
+                    InterpretAs::Digits => self.digits(content),
+                    _ => self.telephone(content),
+                }
+            }
+            InterpretAs::Currency => self.currency(&parse_currency(content)?)?,
+            other => self.other(other, content)?,
+        };
+        let leading = &text[..text.len() - text.trim_start().len()];
+        let trailing = &text[text.trim_end().len()..];
+        Ok(format!("{}{}{}", leading, words, trailing))
+    }
+}
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const SCALES: [&str; 7] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Names of a currency's unit and its hundredth, singular then plural.
+struct CurrencyNames {
+    major: (&'static str, &'static str),
+    minor: Option<(&'static str, &'static str)>,
+}
+
+fn currency_names(currency: &str) -> Option<CurrencyNames> {
+    let dollars = CurrencyNames {
+        major: ("dollar", "dollars"),
+        minor: Some(("cent", "cents")),
+    };
+    let names = match currency {
+        "$" | "US$" | "USD" | "A$" | "AUD" | "C$" | "CAD" | "NZ$" | "NZD" => dollars,
+        "£" | "GBP" => CurrencyNames {
+            major: ("pound", "pounds"),
+            minor: Some(("penny", "pence")),
+        },
+        "€" | "EUR" => CurrencyNames {
+            major: ("euro", "euros"),
+            minor: Some(("cent", "cents")),
+        },
+        "¥" | "JPY" => CurrencyNames {
+            major: ("yen", "yen"),
+            minor: None,
+        },
+        "₹" | "INR" => CurrencyNames {
+            major: ("rupee", "rupees"),
+            minor: Some(("paisa", "paise")),
+        },
+        _ => return None,
+    };
+    Some(names)
+}
+
+/// Reads say-as content in British English, "one hundred and five", "the first of March".
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct English;
+
+impl English {
+    fn below_hundred(number: u64) -> String {
+        match number {
+            0..=19 => ONES[number as usize].to_string(),
+            _ if number.is_multiple_of(10) => TENS[number as usize / 10].to_string(),
+            _ => format!(
+                "{}-{}",
+                TENS[number as usize / 10],
+                ONES[number as usize % 10]
+            ),
+        }
+    }
+
+    fn below_thousand(number: u64) -> String {
+        let mut words = vec![];
+        if number >= 100 {
+            words.push(format!("{} hundred", ONES[number as usize / 100]));
+        }
+        let rest = number % 100;
+        if rest > 0 {
+            if number >= 100 {
+                words.push("and".to_string());
+            }
+            words.push(Self::below_hundred(rest));
+        }
+        words.join(" ")
+    }
+
+    fn whole_number(number: u64) -> String {
+        if number == 0 {
+            return ONES[0].to_string();
+        }
+        let mut groups = vec![];
+        let mut rest = number;
+        while rest > 0 {
+            groups.push(rest % 1000);
+            rest /= 1000;
+        }
+        let mut words = vec![];
+        for (scale, group) in groups.iter().enumerate().rev() {
+            if *group == 0 {
+                continue;
+            }
+            if scale == 0 && *group < 100 && number >= 1000 {
+                words.push("and".to_string());
+            }
+            words.push(Self::below_thousand(*group));
+            if scale > 0 {
+                words.push(SCALES[scale].to_string());
+            }
+        }
+        words.join(" ")
+    }
+
+    fn digit_words(text: &str) -> Vec<&'static str> {
+        text.chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|x| ONES[x as usize])
+            .collect()
+    }
+
+    fn year(year: u32) -> String {
+        let (century, rest) = (year as u64 / 100, year as u64 % 100);
+        match year {
+            0..=999 | 10000.. => Self::whole_number(year as u64),
+            _ if century.is_multiple_of(10) && rest < 10 => Self::whole_number(year as u64),
+            _ if rest == 0 => format!("{} hundred", Self::below_hundred(century)),
+            _ if rest < 10 => format!(
+                "{} oh {}",
+                Self::below_hundred(century),
+                ONES[rest as usize]
+            ),
+            _ => format!(
+                "{} {}",
+                Self::below_hundred(century),
+                Self::below_hundred(rest)
+            ),
+        }
+    }
+}
+
+impl Normaliser for English {
+    fn cardinal(&self, number: &Number) -> String {
+        let mut words = vec![];
+        if number.negative {
+            words.push("minus".to_string());
+        }
+        words.push(Self::whole_number(number.integer));
+        if let Some(fraction) = &number.fraction {
+            words.push("point".to_string());
+            words.extend(Self::digit_words(fraction).into_iter().map(String::from));
+        }
+        words.join(" ")
+    }
+
+    fn ordinal(&self, number: u64) -> String {
+        let cardinal = Self::whole_number(number);
+        let split = cardinal.rfind([' ', '-']).map(|x| x + 1).unwrap_or(0);
+        let (start, last) = cardinal.split_at(split);
+        let last = match last {
+            "one" => "first".to_string(),
+            "two" => "second".to_string(),
+            "three" => "third".to_string(),
+            "five" => "fifth".to_string(),
+            "eight" => "eighth".to_string(),
+            "nine" => "ninth".to_string(),
+            "twelve" => "twelfth".to_string(),
+            _ => match last.strip_suffix('y') {
+                Some(tens) => format!("{}ieth", tens),
+                None => format!("{}th", last),
+            },
+        };
+        format!("{}{}", start, last)
+    }
+
+    fn characters(&self, text: &str) -> String {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .map(|c| match c.to_digit(10) {
+                Some(digit) => ONES[digit as usize].to_string(),
+                None => c.to_uppercase().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn digits(&self, text: &str) -> String {
+        Self::digit_words(text).join(" ")
+    }
+
+    fn date(&self, date: &Date) -> String {
+        // Dates can be made by hand so the month may be out of range, it's left out if it is
+        let month = date
+            .month
+            .and_then(|x| MONTHS.get((x as usize).wrapping_sub(1)));
+        let mut words = vec![];
+        if let Some(day) = date.day {
+            words.push(format!("the {}", self.ordinal(day as u64)));
+            if let Some(month) = month {
+                words.push(format!("of {}", month));
+            }
+        } else if let Some(month) = month {
+            words.push(month.to_string());
+        }
+        if let Some(year) = date.year {
+            words.push(Self::year(year));
+        }
+        words.join(" ")
+    }
+
+    fn time(&self, time: &Time) -> String {
+        let twenty_four_hour = time.meridiem.is_none() && (time.hour == 0 || time.hour > 12);
+        let mut words = vec![];
+        if twenty_four_hour && (1..10).contains(&time.hour) {
+            words.push(format!("oh {}", ONES[time.hour as usize]));
+        } else {
+            words.push(Self::below_hundred(time.hour as u64));
+        }
+        match time.minute.unwrap_or_default() {
+            0 if twenty_four_hour => words.push("hundred".to_string()),
+            0 if time.meridiem.is_none() => words.push("o'clock".to_string()),
+            0 => {}
+            minute @ 1..=9 => words.push(format!("oh {}", ONES[minute as usize])),
+            minute => words.push(Self::below_hundred(minute as u64)),
+        }
+        match time.meridiem {
+            Some(Meridiem::Am) => words.push("a m".to_string()),
+            Some(Meridiem::Pm) => words.push("p m".to_string()),
+            None => {}
+        }
+        match time.second {
+            Some(0) | None => {}
+            Some(1) => words.push("and one second".to_string()),
+            Some(second) => words.push(format!(
+                "and {} seconds",
+                Self::below_hundred(second as u64)
+            )),
+        }
+        words.join(" ")
+    }
+
+    fn telephone(&self, text: &str) -> String {
+        let mut groups = vec![];
+        if text.starts_with('+') {
+            groups.push("plus".to_string());
+        }
+        for group in text.split(|c: char| !c.is_ascii_digit()) {
+            if !group.is_empty() {
+                groups.push(self.digits(group));
+            }
+        }
+        groups.join(", ")
+    }
+
+    fn currency(&self, currency: &Currency) -> anyhow::Result<String> {
+        let amount = &currency.amount;
+        let Some(code) = &currency.currency else {
+            return Ok(self.cardinal(amount));
+        };
+        let Some(names) = currency_names(code) else {
+            bail!("the currency '{}' isn't known", code);
+        };
+        let sign = if amount.negative { "minus " } else { "" };
+        let plural = |number: u64, (one, many): (&str, &str)| {
+            let name = if number == 1 { one } else { many };
+            format!("{} {}", Self::whole_number(number), name)
+        };
+        let minor = match (&amount.fraction, names.minor) {
+            (Some(fraction), Some(minor)) if fraction.len() == 2 => {
+                Some((fraction.parse::<u64>()?, minor))
+            }
+            (None, _) => None,
+            // Amounts which can't be split into the currency's units are read as a decimal
+            (Some(_), _) => {
+                let number = Number {
+                    negative: false,
+                    ..amount.clone()
+                };
+                return Ok(format!(
+                    "{}{} {}",
+                    sign,
+                    self.cardinal(&number),
+                    names.major.1
+                ));
+            }
+        };
+        let words = match minor {
+            Some((0, _)) | None => plural(amount.integer, names.major),
+            Some((cents, minor)) if amount.integer == 0 => plural(cents, minor),
+            Some((cents, minor)) => format!(
+                "{} and {}",
+                plural(amount.integer, names.major),
+                plural(cents, minor)
+            ),
+        };
+        Ok(format!("{}{}", sign, words))
+    }
+}
+
+/// Result of expanding the say-as elements in a document.
+#[derive(Clone, Debug)]
+pub struct Expanded {
+    /// The document with say-as elements replaced by their expanded text.
+    pub output: TransformedSsml,
+    /// Say-as elements which couldn't be expanded. Their text is kept as it was written.
+    pub unexpanded: Vec<Unexpanded>,
+}
+
+/// A say-as element which the normaliser couldn't read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unexpanded {
+    /// Position of the element in the source document's text in unicode scalar values.
+    pub position: usize,
+    /// The text of the element.
+    pub text: String,
+    /// Why the text couldn't be read.
+    pub reason: String,
+}
+
+impl Ssml {
+    /// Replaces every say-as element with the words the normaliser reads its text as. Elements
+    /// which can't be read are still removed, keeping their text, and reported in the result.
+    pub fn expand_say_as(&self, normaliser: &impl Normaliser) -> Expanded {
+        let mut position = 0;
+        let mut say_as: Option<(SayAsAttributes, usize, String)> = None;
+        let mut unexpanded = vec![];
+        let output = self.write_ssml_with_transform(|event| match event {
+            ParserEvent::Open(ParsedElement::SayAs(attrs)) => {
+                say_as = Some((attrs, position, String::new()));
+                None
+            }
+            ParserEvent::Text(text) => {
+                position += text.chars().count();
+                match &mut say_as {
+                    Some((_, _, content)) => {
+                        content.push_str(&text);
+                        None
+                    }
+                    None => Some(ParserEvent::Text(text)),
+                }
+            }
+            ParserEvent::Close(ParsedElement::SayAs(_)) => {
+                let (attrs, start, text) = say_as.take()?;
+                match normaliser.normalise(&attrs, &text) {
+                    Ok(words) => Some(ParserEvent::Text(words)),
+                    Err(e) => {
+                        unexpanded.push(Unexpanded {
+                            position: start,
+                            text: text.clone(),
+                            reason: e.to_string(),
+                        });
+                        Some(ParserEvent::Text(text))
+                    }
+                }
+            }
+            event => Some(event),
+        });
+        Expanded { output, unexpanded }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::char_to_byte_range;
+    use crate::parse_ssml;
+
+    fn number(text: &str) -> String {
+        English.cardinal(&parse_number(text).unwrap())
+    }
+
+    #[test]
+    fn english_numbers() {
+        assert_eq!(number("0"), "zero");
+        assert_eq!(number("13"), "thirteen");
+        assert_eq!(number("40"), "forty");
+        assert_eq!(number("105"), "one hundred and five");
+        assert_eq!(number("1,005"), "one thousand and five");
+        assert_eq!(
+            number("-2,340,019.05"),
+            "minus two million three hundred and forty thousand and nineteen point zero five"
+        );
+        assert_eq!(
+            number("18446744073709551615").split(' ').next(),
+            Some("eighteen")
+        );
+
+        assert_eq!(English.ordinal(1), "first");
+        assert_eq!(English.ordinal(12), "twelfth");
+        assert_eq!(English.ordinal(20), "twentieth");
+        assert_eq!(English.ordinal(23), "twenty-third");
+        assert_eq!(English.ordinal(111), "one hundred and eleventh");
+        assert_eq!(English.ordinal(1000), "one thousandth");
+
+        assert_eq!(English.digits("0123 4"), "zero one two three four");
+        assert_eq!(English.characters("ab-1"), "A B one");
+        assert_eq!(
+            English.telephone("+44 (0)20 7946"),
+            "plus, four four, zero, two zero, seven nine four six"
+        );
+    }
+
+    #[test]
+    fn english_dates_and_times() {
+        let date = |year, month, day| Date { year, month, day };
+        assert_eq!(
+            English.date(&date(Some(2024), Some(3), Some(1))),
+            "the first of March twenty twenty-four"
+        );
+        assert_eq!(
+            English.date(&date(None, Some(12), Some(31))),
+            "the thirty-first of December"
+        );
+        assert_eq!(
+            English.date(&date(Some(1905), Some(7), None)),
+            "July nineteen oh five"
+        );
+        assert_eq!(
+            English.date(&date(Some(2005), None, None)),
+            "two thousand and five"
+        );
+        assert_eq!(
+            English.date(&date(Some(1900), None, None)),
+            "nineteen hundred"
+        );
+        assert_eq!(
+            English.date(&date(Some(2024), Some(13), Some(2))),
+            "the second twenty twenty-four"
+        );
+        assert_eq!(English.date(&date(None, Some(0), None)), "");
+
+        let time = |hour, minute, second, meridiem| Time {
+            hour,
+            minute,
+            second,
+            meridiem,
+        };
+        assert_eq!(
+            English.time(&time(9, Some(5), None, Some(Meridiem::Pm))),
+            "nine oh five p m"
+        );
+        assert_eq!(English.time(&time(9, Some(0), None, None)), "nine o'clock");
+        assert_eq!(
+            English.time(&time(7, None, None, Some(Meridiem::Am))),
+            "seven a m"
+        );
+        assert_eq!(
+            English.time(&time(14, Some(0), None, None)),
+            "fourteen hundred"
+        );
+        assert_eq!(
+            English.time(&time(0, Some(30), Some(1), None)),
+            "zero thirty and one second"
+        );
+    }
+
+    #[test]
+    fn english_currency() {
+        let currency = |text| English.currency(&parse_currency(text).unwrap());
+        assert_eq!(
+            currency("$45.30").unwrap(),
+            "forty-five dollars and thirty cents"
+        );
+        assert_eq!(currency("£1").unwrap(), "one pound");
+        assert_eq!(currency("0.01 GBP").unwrap(), "one penny");
+        assert_eq!(currency("-€2.00").unwrap(), "minus two euros");
+        assert_eq!(currency("¥1.5").unwrap(), "one point five yen");
+        assert_eq!(currency("12").unwrap(), "twelve");
+        assert!(currency("XYZ 12").is_err());
+    }
+
+    #[test]
+    fn expand_say_as() {
+        let ssml = parse_ssml(
+            r#"<speak>It's <say-as interpret-as="date" format="dmy">31/12/2024</say-as> at
+            <say-as interpret-as="time" format="hm12"> 9:05pm </say-as>, call
+            <say-as interpret-as="telephone">555-0100</say-as> or pay
+            <say-as interpret-as="unit">10kg</say-as>.</speak>"#,
+        )
+        .unwrap();
+        let expanded = ssml.expand_say_as(&English);
+        assert_eq!(
+            expanded.output.synthesisable_text,
+            "It's the thirty-first of December twenty twenty-four at nine oh five p m , call five five \
+             five, zero one zero zero or pay 10kg."
+        );
+        assert!(!expanded.output.ssml_string.contains("say-as"));
+        assert_eq!(expanded.unexpanded.len(), 1);
+        let unexpanded = &expanded.unexpanded[0];
+        assert_eq!(unexpanded.text, "10kg");
+        let range = unexpanded.position..unexpanded.position + 4;
+        assert_eq!(
+            &ssml.get_text()[char_to_byte_range(ssml.get_text(), range)],
+            "10kg"
+        );
+    }
+}
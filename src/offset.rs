@@ -0,0 +1,214 @@
+//! Offsets into the text of a document. Spans store their start and end in unicode scalar values
+//! (Rust `char`s), this module converts those to the other units text is commonly measured in:
+//! UTF-8 bytes for slicing Rust strings, extended grapheme clusters for what a reader sees as a
+//! single character, and UTF-16 code units for JavaScript and other UTF-16 based platforms.
+use crate::parser::Span;
+use crate::Ssml;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A unit offsets into the document text can be measured in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OffsetUnit {
+    /// UTF-8 bytes, offsets in this unit can be used to slice the text.
+    Bytes,
+    /// Unicode scalar values, the unit used by `Span`.
+    Chars,
+    /// Extended grapheme clusters as defined in Unicode Standard Annex #29. An offset inside a
+    /// cluster is rounded down to the start of the cluster.
+    Graphemes,
+    /// UTF-16 code units, the unit used for string indices in JavaScript.
+    Utf16,
+}
+
+impl OffsetUnit {
+    /// Converts a byte offset within `text` to this unit.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the offset isn't on a character boundary of the text.
+    pub fn from_byte_offset(self, text: &str, byte: usize) -> usize {
+        let before = &text[..byte];
+        match self {
+            Self::Bytes => byte,
+            Self::Chars => before.chars().count(),
+            Self::Graphemes => text
+                .grapheme_indices(true)
+                .take_while(|(start, grapheme)| start + grapheme.len() <= byte)
+                .count(),
+            Self::Utf16 => before.encode_utf16().count(),
+        }
+    }
+
+    /// Converts an offset in this unit within `text` to a byte offset. Offsets past the end of
+    /// the text are clamped to the end and offsets inside a character, such as between the two
+    /// halves of a UTF-16 surrogate pair, are moved back to the start of that character.
+    pub fn to_byte_offset(self, text: &str, offset: usize) -> usize {
+        match self {
+            Self::Bytes => {
+                let mut byte = offset.min(text.len());
+                while !text.is_char_boundary(byte) {
+                    byte -= 1;
+                }
+                byte
+            }
+            Self::Chars => char_to_byte_range(text, offset..offset).start,
+            Self::Graphemes => text
+                .grapheme_indices(true)
+                .nth(offset)
+                .map(|(byte, _)| byte)
+                .unwrap_or(text.len()),
+            Self::Utf16 => {
+                let mut units = 0;
+                for (byte, c) in text.char_indices() {
+                    units += c.len_utf16();
+                    if units > offset {
+                        return byte;
+                    }
+                }
+                text.len()
+            }
+        }
+    }
+}
+
+/// Converts a range of char offsets within `text` to byte offsets. Offsets past the end of the
+/// text are clamped to the end.
+pub(crate) fn char_to_byte_range(text: &str, chars: Range<usize>) -> Range<usize> {
+    let mut indices = text
+        .char_indices()
+        .map(|(byte, _)| byte)
+        .chain(std::iter::once(text.len()));
+    let start = indices.nth(chars.start).unwrap_or(text.len());
+    let end = match chars.end.checked_sub(chars.start + 1) {
+        Some(n) => indices.nth(n).unwrap_or(text.len()),
+        None => start,
+    };
+    start..end
+}
+
+impl Ssml {
+    /// The range of the document text covered by a span, measured in the given unit.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if span exceeds the bounds of the text.
+    pub fn span_range(&self, span: &Span, unit: OffsetUnit) -> Range<usize> {
+        assert!(span.end <= self.text.chars().count() && span.end >= span.start);
+        match unit {
+            OffsetUnit::Chars => span.start..span.end,
+            unit => {
+                let bytes = char_to_byte_range(&self.text, span.start..span.end);
+                unit.from_byte_offset(&self.text, bytes.start)
+                    ..unit.from_byte_offset(&self.text, bytes.end)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_ssml;
+    use crate::ParsedElement;
+
+    #[test]
+    fn offset_units() {
+        // Each of these is one grapheme but made up of several chars or UTF-16 code units.
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let flag = "🇯🇵";
+        let accent = "e\u{301}";
+        let text = format!("a{}{}{}b", family, flag, accent);
+
+        let end = text.len();
+        assert_eq!(OffsetUnit::Bytes.from_byte_offset(&text, end), end);
+        assert_eq!(OffsetUnit::Chars.from_byte_offset(&text, end), 11);
+        assert_eq!(OffsetUnit::Graphemes.from_byte_offset(&text, end), 5);
+        assert_eq!(OffsetUnit::Utf16.from_byte_offset(&text, end), 16);
+
+        // Half way through the flag rounds down to the start of the flag
+        let flag_start = 1 + family.len();
+        assert_eq!(
+            OffsetUnit::Graphemes.from_byte_offset(&text, flag_start + 4),
+            2
+        );
+
+        // Converting back to bytes gives the offset we started at or the start of the grapheme
+        for unit in [
+            OffsetUnit::Bytes,
+            OffsetUnit::Chars,
+            OffsetUnit::Graphemes,
+            OffsetUnit::Utf16,
+        ] {
+            for (byte, _) in text.char_indices() {
+                let offset = unit.from_byte_offset(&text, byte);
+                let expected = match unit {
+                    OffsetUnit::Graphemes => text.grapheme_indices(true).nth(offset).unwrap().0,
+                    _ => byte,
+                };
+                assert_eq!(unit.to_byte_offset(&text, offset), expected);
+            }
+            assert_eq!(unit.to_byte_offset(&text, 100), end);
+        }
+        // Between the two halves of the surrogate pair for the first flag character
+        assert_eq!(OffsetUnit::Utf16.to_byte_offset(&text, 10), flag_start);
+
+        assert_eq!(char_to_byte_range(&text, 1..6), 1..flag_start);
+        assert_eq!(char_to_byte_range(&text, 3..3), 8..8);
+        assert_eq!(char_to_byte_range(&text, 11..11), end..end);
+    }
+
+    #[test]
+    fn non_ascii_spans() {
+        let ssml = parse_ssml(
+            r#"<speak>東京は<emphasis>晴れ</emphasis>です。<s>مرحبا <mark name="m"/>بالعالم</s> <prosody rate="slow">🇬🇧 👋🏽</prosody></speak>"#,
+        )
+        .unwrap();
+
+        let text_of = |element: &dyn Fn(&ParsedElement) -> bool| {
+            let span = ssml.tags().find(|x| element(&x.element)).unwrap();
+            (
+                ssml.get_text_from_span(span),
+                [
+                    OffsetUnit::Bytes,
+                    OffsetUnit::Chars,
+                    OffsetUnit::Graphemes,
+                    OffsetUnit::Utf16,
+                ]
+                .map(|unit| ssml.span_range(span, unit)),
+            )
+        };
+
+        let (text, ranges) = text_of(&|x| matches!(x, ParsedElement::Emphasis(_)));
+        assert_eq!(text, "晴れ");
+        assert_eq!(ranges, [9..15, 3..5, 3..5, 3..5]);
+
+        let (text, ranges) = text_of(&|x| matches!(x, ParsedElement::Sentence(_)));
+        assert_eq!(text, "مرحبا بالعالم");
+        assert_eq!(ranges, [25..50, 9..22, 9..22, 9..22]);
+
+        let (text, ranges) = text_of(&|x| matches!(x, ParsedElement::Mark(_)));
+        assert_eq!(text, "");
+        assert_eq!(ranges, [36..36, 15..15, 15..15, 15..15]);
+
+        let (text, ranges) = text_of(&|x| matches!(x, ParsedElement::Prosody(_)));
+        assert_eq!(text, "🇬🇧 👋🏽");
+        assert_eq!(ranges, [51..68, 23..28, 23..26, 23..32]);
+
+        // Nodes in the tree give the same ranges as their spans
+        for node in ssml.root().descendants().filter(|x| x.is_element()) {
+            let span = node.span().unwrap();
+            assert_eq!(node.text(), ssml.get_text_from_span(span));
+            for unit in [
+                OffsetUnit::Bytes,
+                OffsetUnit::Chars,
+                OffsetUnit::Graphemes,
+                OffsetUnit::Utf16,
+            ] {
+                assert_eq!(node.range(unit), ssml.span_range(span, unit));
+            }
+        }
+    }
+}
@@ -1,32 +1,40 @@
 //! Handles parsing SSML input and returning our `Ssml` structure, contains a simple parse function
 //! that sets up the parser with the default options and hides it as well as a parser type a user
 //! can construct themselves to have more control over parsing.
+use crate::dialects::{self, Dialect};
 use crate::elements::*;
+use crate::error::{ParseWarning, Position, Repair, SsmlError};
 use crate::*;
-use anyhow::{bail, Context, Result};
+use anyhow::Context;
 use derive_builder::Builder;
 use lazy_static::lazy_static;
 use mediatype::MediaTypeBuf;
+use quick_xml::encoding::EncodingError;
 use quick_xml::escape::resolve_xml_entity;
 use quick_xml::events::{BytesRef, BytesStart, BytesText, Event};
+use quick_xml::name::QName;
 use quick_xml::reader::Reader;
 use quick_xml::XmlVersion;
 use regex::Regex;
 use std::cmp::{Ord, Ordering};
 use std::collections::BTreeMap;
+use std::fmt;
 use std::io;
 use std::num::NonZeroUsize;
 use std::str::from_utf8;
 use std::str::FromStr;
 
+type Result<T> = std::result::Result<T, SsmlError>;
+
 /// Shows a region of the cleaned transcript which an SSML element applies to.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Span {
     /// This is the index of span's start (inclusive) in terms of unicode scalar values - not bytes
-    /// or graphemes
+    /// or graphemes. Use `Ssml::span_range` to get it in other units.
     pub start: usize,
     /// This is the of span's end (exclusive) in terms of unicode scalar values - not bytes
-    /// or graphemes
+    /// or graphemes. Use `Ssml::span_range` to get it in other units.
     pub end: usize,
     /// The element contained within this span
     pub element: ParsedElement,
@@ -43,6 +51,8 @@ impl Span {
     /// with the same start and end. However break has to be an empty tag. This function will
     /// return false. Whereas `<s/><s/>` will return true as a sentence can contain other tags. In
     /// future as a sentence cannot contain a sentence this may return false.
+    ///
+    /// To find out which elements really contain one another walk the tree from `Ssml::root`.
     pub fn maybe_contains(&self, other: &Self) -> bool {
         self.element.can_contain(&other.element)
             && (self.start <= other.start && self.end >= other.end)
@@ -75,6 +85,27 @@ pub struct SsmlParser {
     /// attribute.
     #[builder(default = "false")]
     expand_sub: bool,
+    /// If true try to repair problems in the document instead of failing. Attributes with invalid
+    /// values are removed, as are elements missing required attributes or placed inside elements
+    /// which can't contain them (keeping anything inside them). Every repair is recorded as a
+    /// warning which can be read with `Ssml::warnings`.
+    #[builder(default = "false")]
+    lenient: bool,
+    /// Vendor dialects to parse into typed elements, elements from other dialects are parsed as
+    /// custom elements.
+    #[builder(default, setter(each(name = "dialect")))]
+    dialects: Vec<Dialect>,
+    /// If true check the text of say-as elements matches their interpret-as and format
+    /// attributes, so `<say-as interpret-as="date" format="dmy">31/02/2024</say-as>` is an
+    /// error. A lenient parse removes the say-as element instead, keeping its text.
+    #[builder(default = "false")]
+    validate_say_as: bool,
+    /// If true check the ph attribute of phoneme elements only uses symbols from the declared
+    /// alphabet, so `<phoneme alphabet="ipa" ph="h@loU">` is an error. Phonemes without an
+    /// alphabet or with one we don't know aren't checked. A lenient parse removes the phoneme
+    /// element instead, keeping its text.
+    #[builder(default = "false")]
+    validate_phonemes: bool,
 }
 
 /// We're attaching no meaning to repeated whitespace, but things like space at end
@@ -106,20 +137,22 @@ fn push_text(text: &str, text_buffer: &mut String) {
     }
 }
 
-fn push_text_event(e: BytesText, text_buffer: &mut String) -> Result<()> {
+pub(crate) fn push_text_event(e: BytesText, text_buffer: &mut String) -> Result<()> {
     let text = e.xml10_content()?;
     push_text(&text, text_buffer);
     Ok(())
 }
 
-fn push_general_ref(e: BytesRef, text_buffer: &mut String) -> Result<()> {
+pub(crate) fn push_general_ref(e: BytesRef, text_buffer: &mut String) -> Result<()> {
     if let Some(ch) = e.resolve_char_ref()? {
         let mut text = [0; 4];
         push_text(ch.encode_utf8(&mut text), text_buffer);
     } else {
         let entity = e.decode()?;
-        let text = resolve_xml_entity(&entity)
-            .with_context(|| format!("Unrecognized XML entity: &{};", entity))?;
+        let text = resolve_xml_entity(&entity).ok_or_else(|| SsmlError::UnknownEntity {
+            entity: entity.to_string(),
+            position: Position::default(),
+        })?;
         push_text(text, text_buffer);
     }
     Ok(())
@@ -143,36 +176,57 @@ impl SsmlParser {
         }
     }
 
+    /// Checks the nesting rules of the enabled dialects before falling back to the rules in the
+    /// SSML specification.
+    fn can_contain(&self, parent: &SsmlElement, child: &SsmlElement) -> bool {
+        dialects::can_contain(&self.dialects, parent, child)
+            .unwrap_or_else(|| parent.can_contain(child))
+    }
+
     /// Parse the given SSML string
     pub fn parse(&self, ssml: &str) -> Result<Ssml> {
         let mut reader = Reader::from_str(ssml);
         reader.config_mut().check_end_names = true;
         let mut has_started = false;
+        let mut speak_start = 0;
         let mut text_buffer = String::new();
-        let mut open_tags = vec![];
-        let mut tags = vec![];
+        let mut open_tags: Vec<OpenTag> = vec![];
         let mut event_log = vec![];
+        let mut warnings = vec![];
+        let mut source_map = SourceMap::new(ssml);
 
         loop {
-            match reader.read_event()? {
-                Event::Start(e) if e.local_name().as_ref() == b"speak" => {
-                    if !has_started {
-                        text_buffer.clear();
-                    } else {
-                        bail!("Speak element cannot be placed inside a Speak");
-                    }
+            // Every event starts where the previous one finished so this is where we report any
+            // errors for the event.
+            let event_start = reader.buffer_position() as usize;
+            let at = |e: SsmlError| e.with_position(Position::from_byte_offset(ssml, event_start));
+            let event = reader.read_event().map_err(|e| {
+                SsmlError::from(e).with_position(Position::from_byte_offset(
+                    ssml,
+                    reader.error_position() as usize,
+                ))
+            })?;
+            match event {
+                Event::Start(e) if e.local_name().as_ref() == b"speak" && !has_started => {
+                    // Anything before the speak element isn't part of the document text. Any
+                    // speak elements inside this one are checked like any other element.
+                    text_buffer.clear();
+                    source_map.clear();
+                    event_log.retain(|x| !matches!(x, ParserLogEvent::Text(_)));
                     has_started = true;
+                    speak_start = event_start;
 
-                    let element = parse_speak(e, &reader)?;
-                    event_log.push(ParserLogEvent::Open(element.clone()));
-
-                    let span = Span {
-                        start: text_buffer.chars().count(),
-                        end: text_buffer.chars().count(),
+                    let element =
+                        self.parse_attributes(e, &mut warnings, at, |e| parse_speak(e, &reader))?;
+                    if let Some(element) = &element {
+                        event_log.push(ParserLogEvent::Open(element.clone()));
+                    }
+                    open_tags.push(OpenTag {
+                        ty: SsmlElement::Speak,
                         element,
-                    };
-
-                    open_tags.push((SsmlElement::Speak, tags.len(), span));
+                        start: event_start,
+                        text_start: 0,
+                    });
                 }
                 Event::Start(e) => {
                     // TODO implement ordering constraints:
@@ -185,36 +239,49 @@ impl SsmlParser {
                             && matches!(e.local_name().as_ref(), b"s" | b"p")
                         {
                             // Need to add in a space as they're using tags instead
+                            let text_start = text_buffer.len();
                             text_buffer.push(' ');
+                            event_log.push(ParserLogEvent::Text((text_start, text_buffer.len())));
+                            source_map.push(
+                                text_start..text_buffer.len(),
+                                event_start..reader.buffer_position() as usize,
+                            );
                         }
-                        let (ty, element) = parse_element(e, &mut reader)?;
-                        if ty == SsmlElement::Sub && self.expand_sub {
-                            if let ParsedElement::Sub(attrs) = &element {
-                                let text_start = text_buffer.len();
-                                text_buffer.push(' ');
-                                text_buffer.push_str(&attrs.alias);
-                                text_buffer.push(' ');
-                                let text_end = text_buffer.len();
-                                event_log.push(ParserLogEvent::Text((text_start, text_end)));
-                            } else {
-                                unreachable!("Sub element wasn't returned for sub type");
-                            }
-                        } else {
-                            event_log.push(ParserLogEvent::Open(element.clone()));
-                            match open_tags.last().map(|x| &x.0) {
-                                Some(open_type) if !open_type.can_contain(&ty) => {
-                                    bail!("{:?} cannot be placed inside {:?}", ty, open_type)
+                        let ty =
+                            SsmlElement::from_str(&element_name(&e.name()).map_err(at)?).unwrap();
+                        let element = self.parse_attributes(e, &mut warnings, at, |e| {
+                            parse_element(e, &mut reader, &self.dialects)
+                                .and_then(|(_, element)| self.check_phoneme(element))
+                        })?;
+                        let element =
+                            self.check_nesting(element, &ty, &open_tags, &mut warnings, at)?;
+                        if let Some(element) = &element {
+                            if ty == SsmlElement::Sub && self.expand_sub {
+                                if let ParsedElement::Sub(attrs) = element {
+                                    let text_start = text_buffer.len();
+                                    text_buffer.push(' ');
+                                    text_buffer.push_str(&attrs.alias);
+                                    text_buffer.push(' ');
+                                    let text_end = text_buffer.len();
+                                    event_log.push(ParserLogEvent::Text((text_start, text_end)));
+                                    source_map.push(
+                                        text_start..text_end,
+                                        event_start..reader.buffer_position() as usize,
+                                    );
+                                } else {
+                                    unreachable!("Sub element wasn't returned for sub type");
                                 }
-                                _ => {}
+                            } else {
+                                event_log.push(ParserLogEvent::Open(element.clone()));
                             }
                         }
-                        let new_span = Span {
-                            start: text_buffer.chars().count(),
-                            end: text_buffer.chars().count(),
-                            element,
-                        };
 
-                        open_tags.push((ty, tags.len(), new_span));
+                        open_tags.push(OpenTag {
+                            ty,
+                            element,
+                            start: event_start,
+                            text_start: text_buffer.len(),
+                        });
                     }
                 }
                 Event::Comment(_)
@@ -224,68 +291,395 @@ impl SsmlParser {
                 | Event::DocType(_) => continue,
                 Event::Eof => break,
                 Event::Text(e) => {
-                    let elem = open_tags.last().map(|x| &x.0);
+                    let elem = open_tags.last().map(|x| &x.ty);
                     if self.text_should_enter_buffer(elem) {
                         let text_start = text_buffer.len();
-                        push_text_event(e, &mut text_buffer)?;
+                        push_text_event(e, &mut text_buffer).map_err(at)?;
                         let text_end = text_buffer.len();
                         event_log.push(ParserLogEvent::Text((text_start, text_end)));
+                        source_map.push_text(
+                            &text_buffer[text_start..text_end],
+                            text_start,
+                            event_start..reader.buffer_position() as usize,
+                        );
                     }
                 }
                 Event::GeneralRef(e) => {
-                    let elem = open_tags.last().map(|x| &x.0);
+                    let elem = open_tags.last().map(|x| &x.ty);
                     if self.text_should_enter_buffer(elem) {
                         let text_start = text_buffer.len();
-                        push_general_ref(e, &mut text_buffer)?;
+                        if let Err(e) = push_general_ref(e, &mut text_buffer) {
+                            self.repair(at(e), Repair::RemovedEntity, &mut warnings)?;
+                            continue;
+                        }
                         let text_end = text_buffer.len();
                         event_log.push(ParserLogEvent::Text((text_start, text_end)));
+                        source_map.push(
+                            text_start..text_end,
+                            event_start..reader.buffer_position() as usize,
+                        );
                     }
                 }
                 Event::End(e) => {
-                    let name = e.name();
-                    let name = from_utf8(name.as_ref())?;
+                    let name = element_name(&e.name()).map_err(at)?;
                     if open_tags.is_empty() {
-                        bail!(
-                            "Invalid SSML close tag '{}' presented without open tag.",
-                            name
-                        );
+                        let error = at(SsmlError::UnexpectedCloseTag {
+                            element: name,
+                            position: Position::default(),
+                        });
+                        self.repair(error, Repair::IgnoredCloseTag, &mut warnings)?;
+                        continue;
                     }
-                    let ssml_elem = SsmlElement::from_str(name).unwrap();
-                    if ssml_elem != open_tags[open_tags.len() - 1].0 {
+                    let ssml_elem = SsmlElement::from_str(&name).unwrap();
+                    if ssml_elem != open_tags[open_tags.len() - 1].ty {
                         // We have a close tag without an open!
                     } else {
                         // Okay time to close and remove tag
-                        let (_, pos, mut span) = open_tags.remove(open_tags.len() - 1);
+                        let open_tag = open_tags.remove(open_tags.len() - 1);
+                        // Removed elements have nothing to close
+                        let Some(element) = open_tag.element else {
+                            continue;
+                        };
+                        if let (true, ParsedElement::SayAs(attrs)) =
+                            (self.validate_say_as, &element)
+                        {
+                            let text = &text_buffer[open_tag.text_start..];
+                            if let Err(e) = attrs.check_text(text) {
+                                let error = SsmlError::InvalidContent {
+                                    element: name,
+                                    text: text.trim().to_string(),
+                                    reason: e.to_string(),
+                                    position: Position::from_byte_offset(ssml, open_tag.start),
+                                };
+                                self.repair(error, Repair::RemovedElement, &mut warnings)?;
+                                // Say-as can only contain text so its open event is the last one
+                                if let Some(index) = event_log
+                                    .iter()
+                                    .rposition(|x| matches!(x, ParserLogEvent::Open(_)))
+                                {
+                                    event_log.remove(index);
+                                }
+                                continue;
+                            }
+                        }
                         if !(ssml_elem == SsmlElement::Sub && self.expand_sub) {
-                            event_log.push(ParserLogEvent::Close(span.element.clone()));
-                            span.end = text_buffer.chars().count();
-                            tags.insert(pos, span);
-                            if !(ssml_elem == SsmlElement::Speak && open_tags.is_empty()) {
-                            } else {
+                            event_log.push(ParserLogEvent::Close(element));
+                            if ssml_elem == SsmlElement::Speak && open_tags.is_empty() {
                                 break;
                             }
                         }
                     }
                 }
                 Event::Empty(e) => {
-                    let (_, element) = parse_element(e, &mut reader)?;
-                    let span = Span {
-                        start: text_buffer.chars().count(),
-                        end: text_buffer.chars().count(),
-                        element,
-                    };
-                    event_log.push(ParserLogEvent::Empty(span.element.clone()));
-                    tags.push(span);
+                    let ty = SsmlElement::from_str(&element_name(&e.name()).map_err(at)?).unwrap();
+                    let element = self.parse_attributes(e, &mut warnings, at, |e| {
+                        parse_element(e, &mut reader, &self.dialects)
+                            .and_then(|(_, element)| self.check_phoneme(element))
+                    })?;
+                    let element =
+                        self.check_nesting(element, &ty, &open_tags, &mut warnings, at)?;
+                    if let Some(element) = element {
+                        event_log.push(ParserLogEvent::Empty(element));
+                    }
                 }
             }
         }
-        tags.sort();
-        Ok(Ssml {
-            text: text_buffer,
-            tags,
-            event_log,
-        })
+        let position = Position::from_byte_offset(ssml, speak_start);
+        self.check_speak_marks(&mut event_log, position, &mut warnings)?;
+        let mut ssml = Ssml::new(text_buffer, event_log);
+        ssml.warnings = warnings;
+        ssml.source_map = Some(source_map);
+        Ok(ssml)
+    }
+
+    /// In lenient mode record the repair made for an error and carry on, otherwise fail with the
+    /// error.
+    fn repair(
+        &self,
+        error: SsmlError,
+        repair: Repair,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<()> {
+        if self.lenient {
+            warnings.push(ParseWarning { error, repair });
+            Ok(())
+        } else {
+            Err(error)
+        }
     }
+
+    /// Checks the closest parent of an element which wasn't removed can contain it. When lenient
+    /// an element which can't be there is removed and `None` is returned.
+    fn check_nesting(
+        &self,
+        element: Option<ParsedElement>,
+        ty: &SsmlElement,
+        open_tags: &[OpenTag],
+        warnings: &mut Vec<ParseWarning>,
+        at: impl Fn(SsmlError) -> SsmlError,
+    ) -> Result<Option<ParsedElement>> {
+        let parent = open_tags.iter().rev().find(|x| x.element.is_some());
+        match (element, parent) {
+            (Some(_), Some(parent)) if !self.can_contain(&parent.ty, ty) => {
+                let error = at(SsmlError::InvalidNesting {
+                    parent: parent.ty.to_string(),
+                    child: ty.to_string(),
+                    position: Position::default(),
+                });
+                self.repair(error, Repair::RemovedElement, warnings)?;
+                Ok(None)
+            }
+            (element, _) => Ok(element),
+        }
+    }
+
+    /// Checks the marks named by the startmark and endmark attributes of the speak element are in
+    /// the document with the start before the end. A lenient parse removes the attribute.
+    fn check_speak_marks(
+        &self,
+        event_log: &mut ParserLog,
+        position: Position,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<()> {
+        let Some(ParserLogEvent::Open(ParsedElement::Speak(speak))) = event_log.first() else {
+            return Ok(());
+        };
+        let marks = event_log
+            .iter()
+            .filter_map(|x| match x {
+                ParserLogEvent::Open(e) | ParserLogEvent::Empty(e) => marks::mark_name(e),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let find = |name: &str| marks.iter().position(|x| *x == name);
+        let error = |attribute: &str, value: &str, reason: &str| SsmlError::InvalidAttributeValue {
+            element: SsmlElement::Speak.to_string(),
+            attribute: attribute.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+            position,
+        };
+        let missing = "there's no mark with this name";
+        let mut errors = vec![];
+        let start = speak.start_mark.as_deref().and_then(|name| {
+            let start = find(name);
+            if start.is_none() {
+                errors.push(error("startmark", name, missing));
+            }
+            start
+        });
+        if let Some(name) = speak.end_mark.as_deref() {
+            match (find(name), start) {
+                (None, _) => errors.push(error("endmark", name, missing)),
+                (Some(end), Some(start)) if end < start => errors.push(error(
+                    "endmark",
+                    name,
+                    "the mark comes before the startmark",
+                )),
+                _ => {}
+            }
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let mut speak = speak.clone();
+        for error in errors {
+            match error.attribute() {
+                Some("startmark") => speak.start_mark = None,
+                _ => speak.end_mark = None,
+            }
+            self.repair(error, Repair::RemovedAttribute, warnings)?;
+        }
+        // The speak element is the first open tag and its close tag is the last close tag
+        event_log[0] = ParserLogEvent::Open(ParsedElement::Speak(speak.clone()));
+        if let Some(close) = event_log
+            .iter_mut()
+            .rev()
+            .find(|x| matches!(x, ParserLogEvent::Close(_)))
+        {
+            *close = ParserLogEvent::Close(ParsedElement::Speak(speak));
+        }
+        Ok(())
+    }
+
+    /// Checks every symbol in a phoneme's transcription is in its alphabet when validating
+    /// phonemes, the first symbol which isn't is reported as an error.
+    fn check_phoneme(&self, element: ParsedElement) -> Result<ParsedElement> {
+        if let (true, ParsedElement::Phoneme(attrs)) = (self.validate_phonemes, &element) {
+            if let Some(alphabet) = attrs.alphabet.as_ref().and_then(|x| x.known()) {
+                if let Some(symbol) = alphabet.invalid_symbol(&attrs.ph) {
+                    return Err(SsmlError::InvalidPhoneme {
+                        ph: attrs.ph.clone(),
+                        alphabet: alphabet.to_string(),
+                        phone: symbol.text.to_string(),
+                        offset: symbol.range.start,
+                        position: Position::default(),
+                    });
+                }
+            }
+        }
+        Ok(element)
+    }
+
+    /// Runs the parse function on the element. When lenient any attribute with an invalid value
+    /// is removed before trying again, and if a required attribute is missing `None` is returned
+    /// so the element can be removed.
+    fn parse_attributes<F>(
+        &self,
+        elem: BytesStart,
+        warnings: &mut Vec<ParseWarning>,
+        at: impl Fn(SsmlError) -> SsmlError,
+        mut parse: F,
+    ) -> Result<Option<ParsedElement>>
+    where
+        F: FnMut(BytesStart) -> Result<ParsedElement>,
+    {
+        let mut elem = elem;
+        loop {
+            let error = match parse(elem.clone()) {
+                Ok(element) => return Ok(Some(element)),
+                Err(e) => at(e),
+            };
+            match &error {
+                SsmlError::InvalidAttributeValue { .. } | SsmlError::UnsupportedVersion { .. }
+                    if self.lenient =>
+                {
+                    let attribute = error.attribute().unwrap_or_default();
+                    let new_elem = without_attribute(&elem, attribute).map_err(&at)?;
+                    if new_elem.attributes().count() == elem.attributes().count() {
+                        return Err(error);
+                    }
+                    elem = new_elem;
+                    self.repair(error, Repair::RemovedAttribute, warnings)?;
+                }
+                SsmlError::MissingAttribute { .. } | SsmlError::InvalidPhoneme { .. } => {
+                    self.repair(error, Repair::RemovedElement, warnings)?;
+                    return Ok(None);
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+/// An element which has been opened and not yet closed.
+struct OpenTag {
+    /// Type of the element
+    ty: SsmlElement,
+    /// The parsed element, this is none when the element was removed by a lenient parse.
+    element: Option<ParsedElement>,
+    /// Byte offset of the open tag in the source.
+    start: usize,
+    /// Byte offset in the text where the element's text starts.
+    text_start: usize,
+}
+
+/// Copies an element leaving out the named attribute.
+fn without_attribute(elem: &BytesStart, name: &str) -> Result<BytesStart<'static>> {
+    let mut new_elem = BytesStart::new(element_name(&elem.name())?);
+    for attr in elem.attributes() {
+        let attr = attr?;
+        if attr.key.as_ref() != name.as_bytes() {
+            new_elem.push_attribute(attr);
+        }
+    }
+    Ok(new_elem)
+}
+
+/// Turns the raw name of an element into a string.
+pub(crate) fn element_name(name: &QName) -> Result<String> {
+    let name = from_utf8(name.as_ref()).map_err(EncodingError::from)?;
+    Ok(name.to_string())
+}
+
+/// Gets the decoded and normalised value of an attribute if it's present on the element.
+pub(crate) fn get_attribute<R: io::BufRead>(
+    elem: &BytesStart,
+    reader: &Reader<R>,
+    name: &str,
+) -> Result<Option<String>> {
+    match elem.try_get_attribute(name)? {
+        Some(attr) => Ok(Some(
+            attr.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
+                .to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Gets the decoded and normalised value of an attribute, the attribute not being present is an
+/// error.
+pub(crate) fn get_required_attribute<R: io::BufRead>(
+    elem: &BytesStart,
+    reader: &Reader<R>,
+    name: &str,
+) -> Result<String> {
+    match get_attribute(elem, reader, name)? {
+        Some(value) => Ok(value),
+        None => Err(SsmlError::MissingAttribute {
+            element: element_name(&elem.name())?,
+            attribute: name.to_string(),
+            position: Position::default(),
+        }),
+    }
+}
+
+/// Creates an error for an attribute with a value which can't be used.
+pub(crate) fn invalid_attribute(
+    elem: &BytesStart,
+    name: &str,
+    value: &str,
+    reason: impl fmt::Display,
+) -> SsmlError {
+    match element_name(&elem.name()) {
+        Ok(element) => SsmlError::InvalidAttributeValue {
+            element,
+            attribute: name.to_string(),
+            value: value.to_string(),
+            reason: format!("{:#}", reason),
+            position: Position::default(),
+        },
+        Err(e) => e,
+    }
+}
+
+/// Gets an attribute if it's present on the element and converts it with the provided function.
+/// If the conversion fails the attribute is reported as having an invalid value.
+pub(crate) fn parse_attribute<R, T, E, F>(
+    elem: &BytesStart,
+    reader: &Reader<R>,
+    name: &str,
+    parse: F,
+) -> Result<Option<T>>
+where
+    R: io::BufRead,
+    E: fmt::Display,
+    F: FnOnce(&str) -> std::result::Result<T, E>,
+{
+    match get_attribute(elem, reader, name)? {
+        Some(value) => match parse(&value) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(invalid_attribute(elem, name, &value, e)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Gets an attribute which must be present on the element and converts it with the provided
+/// function.
+pub(crate) fn parse_required_attribute<R, T, E, F>(
+    elem: &BytesStart,
+    reader: &Reader<R>,
+    name: &str,
+    parse: F,
+) -> Result<T>
+where
+    R: io::BufRead,
+    E: fmt::Display,
+    F: FnOnce(&str) -> std::result::Result<T, E>,
+{
+    let value = get_required_attribute(elem, reader, name)?;
+    parse(&value).map_err(|e| invalid_attribute(elem, name, &value, e))
 }
 
 /// Parse an SSML element, this returns an `SsmlElement` as a tag to represent the SSML and the
@@ -293,10 +687,10 @@ impl SsmlParser {
 pub(crate) fn parse_element(
     elem: BytesStart,
     reader: &mut Reader<&[u8]>,
+    dialects: &[Dialect],
 ) -> Result<(SsmlElement, ParsedElement)> {
-    let name = elem.name();
-    let name = from_utf8(name.as_ref())?;
-    let elem_type = SsmlElement::from_str(name).unwrap();
+    let name = element_name(&elem.name())?;
+    let elem_type = SsmlElement::from_str(&name).unwrap();
 
     let res = match elem_type {
         SsmlElement::Speak => parse_speak(elem, reader)?,
@@ -304,8 +698,8 @@ pub(crate) fn parse_element(
         SsmlElement::Lookup => parse_lookup(elem, reader)?,
         SsmlElement::Meta => parse_meta(elem, reader)?,
         SsmlElement::Metadata => ParsedElement::Metadata,
-        SsmlElement::Paragraph => ParsedElement::Paragraph,
-        SsmlElement::Sentence => ParsedElement::Sentence,
+        SsmlElement::Paragraph => ParsedElement::Paragraph(parse_text_structure(elem, reader)?),
+        SsmlElement::Sentence => ParsedElement::Sentence(parse_text_structure(elem, reader)?),
         SsmlElement::Token => parse_token(elem, reader)?,
         SsmlElement::Word => parse_word(elem, reader)?,
         SsmlElement::SayAs => parse_say_as(elem, reader)?,
@@ -325,74 +719,66 @@ pub(crate) fn parse_element(
             ParsedElement::Description(text)
         }
         SsmlElement::Custom(ref s) => {
+            if let Some(element) = dialects::parse_element(s, &elem, reader, dialects)? {
+                return Ok((elem_type, ParsedElement::Dialect(element)));
+            }
             let mut attributes = BTreeMap::new();
             for attr in elem.attributes() {
                 let attr = attr?;
                 attributes.insert(
-                    String::from_utf8(attr.key.0.to_vec())?,
-                    String::from_utf8(attr.value.to_vec())?,
+                    from_utf8(attr.key.0)
+                        .map_err(EncodingError::from)?
+                        .to_string(),
+                    from_utf8(&attr.value)
+                        .map_err(EncodingError::from)?
+                        .to_string(),
                 );
             }
             ParsedElement::Custom((s.to_string(), attributes))
         }
     };
+    dialects::check_element(&res, dialects)?;
 
     Ok((elem_type, res))
 }
 
-// TODO: handle start mark and end mark
 fn parse_speak<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let version = elem.try_get_attribute("version")?;
-
     // Technically spec non-compliant however commercial TTS such as amazon, google and microsoft
     // don't require the version and just assume 1.1
-    let version = if let Some(v) = version {
-        let version = v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        match version.as_ref() {
-            "1.0" | "1.1" => (),
-            v => bail!("Unsupported SSML spec version: {}", v),
-        }
-        version.to_string()
-    } else {
-        "1.1".to_string()
+    let version = match get_attribute(&elem, reader, "version")? {
+        Some(version) => match version.as_ref() {
+            "1.0" | "1.1" => version,
+            _ => {
+                return Err(SsmlError::UnsupportedVersion {
+                    version,
+                    position: Position::default(),
+                })
+            }
+        },
+        None => "1.1".to_string(),
     };
 
-    let lang = elem.try_get_attribute("xml:lang")?;
-    let lang = if let Some(lang) = lang {
-        Some(
-            lang.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string(),
-        )
-    } else {
-        None
-    };
-    let base = elem.try_get_attribute("xml:base")?;
-    let base = if let Some(base) = base {
-        Some(
-            base.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string(),
-        )
-    } else {
-        None
-    };
-    let on_lang_failure = elem.try_get_attribute("onlangfailure")?;
-    let on_lang_failure = if let Some(lang) = on_lang_failure {
-        let value = lang.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        Some(OnLanguageFailure::from_str(&value)?)
-    } else {
-        None
-    };
+    let lang = parse_attribute(&elem, reader, "xml:lang", LanguageTag::from_str)?;
+    let base = get_attribute(&elem, reader, "xml:base")?;
+    let on_lang_failure =
+        parse_attribute(&elem, reader, "onlangfailure", OnLanguageFailure::from_str)?;
+    let start_mark = get_attribute(&elem, reader, "startmark")?;
+    let end_mark = get_attribute(&elem, reader, "endmark")?;
 
     let mut xml_root_attrs = BTreeMap::new();
     for attr in elem.attributes() {
         let attr = attr?;
 
         match std::str::from_utf8(attr.key.0).unwrap() {
-            "xml:base" | "xml:lang" | "onlangfailure" | "version" => continue,
+            "xml:base" | "xml:lang" | "onlangfailure" | "version" | "startmark" | "endmark" => {
+                continue
+            }
             attr_name => {
                 xml_root_attrs.insert(
                     String::from(attr_name),
-                    String::from_utf8(attr.value.into())?,
+                    from_utf8(&attr.value)
+                        .map_err(EncodingError::from)?
+                        .to_string(),
                 );
             }
         }
@@ -403,45 +789,22 @@ fn parse_speak<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<P
         base,
         on_lang_failure,
         version,
+        start_mark,
+        end_mark,
         xml_root_attrs,
     }))
 }
 
 fn parse_lexicon<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let xml_id = elem
-        .try_get_attribute("xml:id")?
-        .context("xml:id attribute is required with a lexicon element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
-
-    let uri: http::Uri = elem
-        .try_get_attribute("uri")?
-        .context("uri attribute is required with a lexicon element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string()
-        .parse()?;
-
-    let fetch_timeout = match elem.try_get_attribute("fetchtimeout")? {
-        Some(fetchtimeout) => {
-            let fetchtimeout = fetchtimeout
-                .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            Some(TimeDesignation::from_str(&fetchtimeout)?)
-        }
-        None => None,
-    };
+    let xml_id = get_required_attribute(&elem, reader, "xml:id")?;
 
-    let ty = match elem.try_get_attribute("type")? {
-        Some(ty) => {
-            let ty = ty
-                .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string();
-            let ty = MediaTypeBuf::from_string(ty)
-                .context("invalid media type for type attribute of lexicon element")?;
+    let uri = parse_required_attribute(&elem, reader, "uri", http::Uri::from_str)?;
 
-            Some(ty)
-        }
-        None => None,
-    };
+    let fetch_timeout = parse_attribute(&elem, reader, "fetchtimeout", TimeDesignation::from_str)?;
+
+    let ty = parse_attribute(&elem, reader, "type", |ty| {
+        MediaTypeBuf::from_string(ty.to_string())
+    })?;
 
     Ok(ParsedElement::Lexicon(LexiconAttributes {
         uri,
@@ -452,45 +815,36 @@ fn parse_lexicon<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result
 }
 
 fn parse_lookup<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let lookup_ref = elem
-        .try_get_attribute("ref")?
-        .context("ref attribute is required with a lookup element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
+    let lookup_ref = get_required_attribute(&elem, reader, "ref")?;
 
     Ok(ParsedElement::Lookup(LookupAttributes { lookup_ref }))
 }
 
 fn parse_meta<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let content = elem
-        .try_get_attribute("content")?
-        .context("content attribute is required with a meta element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
-
-    let name = elem.try_get_attribute("name")?;
-    let http_equiv = elem.try_get_attribute("http-equiv")?;
-
-    let (name, http_equiv) = match (name, http_equiv) {
-        (Some(name), None) => (
-            Some(
-                name.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                    .to_string(),
-            ),
-            None,
-        ),
-        (None, Some(http_equiv)) => (
-            None,
-            Some(
-                http_equiv
-                    .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                    .to_string(),
-            ),
-        ),
-        _ => {
-            bail!("either name or http-equiv attr must be set in meta element (but not both)")
+    let content = get_required_attribute(&elem, reader, "content")?;
+
+    let name = get_attribute(&elem, reader, "name")?;
+    let http_equiv = get_attribute(&elem, reader, "http-equiv")?;
+
+    // Either name or http-equiv must be set in the meta element but not both
+    match (&name, &http_equiv) {
+        (Some(_), None) | (None, Some(_)) => {}
+        (None, None) => {
+            return Err(SsmlError::MissingAttribute {
+                element: SsmlElement::Meta.to_string(),
+                attribute: "name".to_string(),
+                position: Position::default(),
+            })
         }
-    };
+        (Some(_), Some(http_equiv)) => {
+            return Err(invalid_attribute(
+                &elem,
+                "http-equiv",
+                http_equiv,
+                "name and http-equiv cannot both be set",
+            ))
+        }
+    }
 
     Ok(ParsedElement::Meta(MetaAttributes {
         name,
@@ -500,52 +854,24 @@ fn parse_meta<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<Pa
 }
 
 fn parse_token<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let role = match elem.try_get_attribute("role")? {
-        Some(attr) => Some(
-            attr.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string(),
-        ),
-        None => None,
-    };
+    let role = get_attribute(&elem, reader, "role")?;
 
     Ok(ParsedElement::Token(TokenAttributes { role }))
 }
 
 fn parse_word<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let role = match elem.try_get_attribute("role")? {
-        Some(attr) => Some(
-            attr.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string(),
-        ),
-        None => None,
-    };
+    let role = get_attribute(&elem, reader, "role")?;
 
     Ok(ParsedElement::Word(TokenAttributes { role }))
 }
 
 fn parse_say_as<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    // TODO: maybe rewrite the error handling in other parse functions to look like this.
-    let interpret_as = elem
-        .try_get_attribute("interpret-as")?
-        .context("interpret-as attribute is required with a say-as element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
-
-    let format = match elem.try_get_attribute("format")? {
-        Some(attr) => Some(
-            attr.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string(),
-        ),
-        None => None,
-    };
-
-    let detail = match elem.try_get_attribute("detail")? {
-        Some(attr) => Some(
-            attr.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string(),
-        ),
-        None => None,
-    };
+    let interpret_as =
+        parse_required_attribute(&elem, reader, "interpret-as", InterpretAs::from_str)?;
+    let format = parse_attribute(&elem, reader, "format", |v| {
+        SayAsFormat::parse(&interpret_as, v)
+    })?;
+    let detail = get_attribute(&elem, reader, "detail")?;
 
     Ok(ParsedElement::SayAs(SayAsAttributes {
         interpret_as,
@@ -555,76 +881,43 @@ fn parse_say_as<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<
 }
 
 fn parse_phoneme<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let phoneme = elem.try_get_attribute("ph")?;
-    let phoneme = if let Some(phoneme) = phoneme {
-        let value =
-            phoneme.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        value.to_string()
-    } else {
-        bail!("ph attribute is required with a phoneme element");
-    };
-
-    let alphabet = elem.try_get_attribute("alphabet")?;
-    let alphabet = if let Some(alpha) = alphabet {
-        let val = alpha.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        Some(PhonemeAlphabet::from_str(&val).unwrap())
-    } else {
-        None
-    };
+    let ph = get_required_attribute(&elem, reader, "ph")?;
+    let alphabet = parse_attribute(&elem, reader, "alphabet", PhonemeAlphabet::from_str)?;
 
-    Ok(ParsedElement::Phoneme(PhonemeAttributes {
-        ph: phoneme,
-        alphabet,
-    }))
+    Ok(ParsedElement::Phoneme(PhonemeAttributes { ph, alphabet }))
 }
 
 fn parse_break<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let strength = elem.try_get_attribute("strength")?;
-    let strength = if let Some(strength) = strength {
-        let value =
-            strength.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = Strength::from_str(&value)?;
-        Some(value)
-    } else {
-        None
-    };
-    let time = match elem.try_get_attribute("time")? {
-        Some(time) => {
-            let value =
-                time.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            Some(TimeDesignation::from_str(&value)?)
-        }
-        None => None,
-    };
+    let strength = parse_attribute(&elem, reader, "strength", Strength::from_str)?;
+    let time = parse_attribute(&elem, reader, "time", TimeDesignation::from_str)?;
 
     Ok(ParsedElement::Break(BreakAttributes { strength, time }))
 }
 
 fn parse_sub<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let alias = elem
-        .try_get_attribute("alias")?
-        .context("alias attribute required for sub element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
+    let alias = get_required_attribute(&elem, reader, "alias")?;
 
     Ok(ParsedElement::Sub(SubAttributes { alias }))
 }
 
+fn parse_text_structure<R: io::BufRead>(
+    elem: BytesStart,
+    reader: &Reader<R>,
+) -> Result<TextStructureAttributes> {
+    let lang = parse_attribute(&elem, reader, "xml:lang", LanguageTag::from_str)?;
+    let on_lang_failure =
+        parse_attribute(&elem, reader, "onlangfailure", OnLanguageFailure::from_str)?;
+
+    Ok(TextStructureAttributes {
+        lang,
+        on_lang_failure,
+    })
+}
+
 fn parse_language<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let lang = elem
-        .try_get_attribute("xml:lang")?
-        .context("xml:lang attribute is required with a lang element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
-
-    let on_lang_failure = match elem.try_get_attribute("onlangfailure")? {
-        Some(s) => {
-            let value =
-                s.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            Some(OnLanguageFailure::from_str(&value)?)
-        }
-        None => None,
-    };
+    let lang = parse_required_attribute(&elem, reader, "xml:lang", LanguageTag::from_str)?;
+    let on_lang_failure =
+        parse_attribute(&elem, reader, "onlangfailure", OnLanguageFailure::from_str)?;
 
     Ok(ParsedElement::Lang(LangAttributes {
         lang,
@@ -633,91 +926,18 @@ fn parse_language<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Resul
 }
 
 fn parse_emphasis<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let level = elem.try_get_attribute("level")?;
-    let level = if let Some(level) = level {
-        let value =
-            level.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = EmphasisLevel::from_str(&value)?;
-        Some(value)
-    } else {
-        None
-    };
+    let level = parse_attribute(&elem, reader, "level", EmphasisLevel::from_str)?;
 
     Ok(ParsedElement::Emphasis(EmphasisAttributes { level }))
 }
 
 fn parse_prosody<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let pitch = elem.try_get_attribute("pitch")?;
-    let pitch = if let Some(pitch) = pitch {
-        let value =
-            pitch.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = match PitchRange::from_str(&value) {
-            Ok(result) => result,
-            Err(e) => bail!("Error: {}", e),
-        };
-
-        Some(value)
-    } else {
-        None
-    };
-    let contour = elem.try_get_attribute("contour")?;
-    let contour = if let Some(contour) = contour {
-        let value =
-            contour.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = match PitchContour::from_str(&value) {
-            Ok(result) => result,
-            Err(e) => bail!("Error: {}", e),
-        };
-        Some(value)
-    } else {
-        None
-    };
-    let range = elem.try_get_attribute("range")?;
-    let range = if let Some(range) = range {
-        let value =
-            range.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = match PitchRange::from_str(&value) {
-            Ok(result) => result,
-            Err(e) => bail!("Error: {}", e),
-        };
-
-        Some(value)
-    } else {
-        None
-    };
-    let rate = elem.try_get_attribute("rate")?;
-    let rate = if let Some(rate) = rate {
-        let value = rate.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = match RateRange::from_str(&value) {
-            Ok(result) => result,
-            Err(e) => bail!("Error: {}", e),
-        };
-
-        Some(value)
-    } else {
-        None
-    };
-    let duration = match elem.try_get_attribute("duration")? {
-        Some(val) => Some(
-            val.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .parse()?,
-        ),
-        None => None,
-    };
-
-    let volume = elem.try_get_attribute("volume")?;
-    let volume = if let Some(volume) = volume {
-        let value =
-            volume.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-        let value = match VolumeRange::from_str(&value) {
-            Ok(result) => result,
-            Err(e) => bail!("Error: {}", e),
-        };
-
-        Some(value)
-    } else {
-        None
-    };
+    let pitch = parse_attribute(&elem, reader, "pitch", PitchRange::from_str)?;
+    let contour = parse_attribute(&elem, reader, "contour", PitchContour::from_str)?;
+    let range = parse_attribute(&elem, reader, "range", PitchRange::from_str)?;
+    let rate = parse_attribute(&elem, reader, "rate", RateRange::from_str)?;
+    let duration = parse_attribute(&elem, reader, "duration", TimeDesignation::from_str)?;
+    let volume = parse_attribute(&elem, reader, "volume", VolumeRange::from_str)?;
 
     Ok(ParsedElement::Prosody(ProsodyAttributes {
         pitch,
@@ -730,188 +950,81 @@ fn parse_prosody<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result
 }
 
 fn parse_mark<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let name = elem
-        .try_get_attribute("name")?
-        .context("name attribute is required with mark element")?
-        .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-        .to_string();
+    let name = get_required_attribute(&elem, reader, "name")?;
 
     Ok(ParsedElement::Mark(MarkAttributes { name }))
 }
 
 fn parse_voice<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let gender = elem.try_get_attribute("gender")?;
-    let gender = match gender {
-        Some(v) => {
-            let value =
-                v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            if value.is_empty() {
-                None
-            } else {
-                Some(Gender::from_str(&value)?)
-            }
-        }
-        None => None,
-    };
-    let age = elem.try_get_attribute("age")?;
-    let age = match age {
-        Some(v) => {
-            let value =
-                v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            if value.is_empty() {
-                None
-            } else {
-                Some(value.parse::<u8>()?)
-            }
-        }
-        None => None,
-    };
-    let variant = elem.try_get_attribute("variant")?;
-    let variant = match variant {
-        Some(v) => {
-            let value =
-                v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            if value.is_empty() {
-                None
-            } else {
-                Some(value.parse::<NonZeroUsize>()?)
-            }
-        }
-        None => None,
-    };
-    let name = elem.try_get_attribute("name")?;
-    let name = match name {
-        Some(v) => {
-            let value =
-                v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            value
-                .split(' ')
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-        }
+    // For the voice feature attributes an empty string means any voice satisfies the feature
+    let gender = parse_attribute(&elem, reader, "gender", |v| match v {
+        "" => Ok(None),
+        v => Gender::from_str(v).map(Some),
+    })?
+    .flatten();
+    let age = parse_attribute(&elem, reader, "age", |v| match v {
+        "" => Ok(None),
+        v => v.parse::<u8>().map(Some),
+    })?
+    .flatten();
+    let variant = parse_attribute(&elem, reader, "variant", |v| match v {
+        "" => Ok(None),
+        v => v.parse::<NonZeroUsize>().map(Some),
+    })?
+    .flatten();
+    let name = match get_attribute(&elem, reader, "name")? {
+        Some(value) => value
+            .split(' ')
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>(),
         None => vec![],
     };
-    let languages = elem.try_get_attribute("languages")?;
-    let languages = match languages {
-        Some(v) => {
-            let value =
-                v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            let mut res = vec![];
-            for language in value.split(' ') {
-                res.push(LanguageAccentPair::from_str(language)?);
-            }
-            res
-        }
-        None => vec![],
+    let languages = parse_attribute(&elem, reader, "languages", |v| {
+        v.split(' ')
+            .map(LanguageAccentPair::from_str)
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?
+    .unwrap_or_default();
+    let features = |v: &str| {
+        v.split_whitespace()
+            .map(VoiceFeature::from_str)
+            .collect::<anyhow::Result<Vec<_>>>()
     };
+    let required = parse_attribute(&elem, reader, "required", features)?;
+    let ordering = parse_attribute(&elem, reader, "ordering", features)?;
+    let on_voice_failure =
+        parse_attribute(&elem, reader, "onvoicefailure", OnVoiceFailure::from_str)?;
+
     Ok(ParsedElement::Voice(VoiceAttributes {
         gender,
         age,
         variant,
         name,
         languages,
+        required,
+        ordering,
+        on_voice_failure,
     }))
 }
 
 fn parse_audio<R: io::BufRead>(elem: BytesStart, reader: &Reader<R>) -> Result<ParsedElement> {
-    let src = match elem.try_get_attribute("src")? {
-        Some(s) => {
-            let src: http::Uri = s
-                .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .to_string()
-                .parse()?;
-            Some(src)
-        }
-        None => None,
-    };
-
-    let fetch_timeout = match elem.try_get_attribute("fetchtimeout")? {
-        Some(fetchtimeout) => {
-            let fetchtimeout = fetchtimeout
-                .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            Some(TimeDesignation::from_str(&fetchtimeout)?)
-        }
-        None => None,
-    };
-
-    let fetch_hint = match elem.try_get_attribute("fetchhint")? {
-        Some(fetch) => {
-            let fetch =
-                fetch.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            FetchHint::from_str(&fetch)?
-        }
-        None => FetchHint::default(),
-    };
-
-    let max_age = if let Some(v) = elem.try_get_attribute("maxage")? {
-        Some(
-            v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .parse::<usize>()?,
-        )
-    } else {
-        None
-    };
-
-    let max_stale = if let Some(v) = elem.try_get_attribute("maxstale")? {
-        Some(
-            v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-                .parse::<usize>()?,
-        )
-    } else {
-        None
-    };
-
-    let clip_begin = match elem.try_get_attribute("clipBegin")? {
-        Some(clip) => {
-            let clip =
-                clip.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            TimeDesignation::from_str(&clip)?
-        }
-        None => TimeDesignation::Seconds(0.0),
-    };
-
-    let clip_end = match elem.try_get_attribute("clipEnd")? {
-        Some(clip) => {
-            let clip =
-                clip.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            Some(TimeDesignation::from_str(&clip)?)
-        }
-        None => None,
-    };
-
-    let repeat_count = if let Some(v) = elem.try_get_attribute("repeatCount")? {
-        v.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?
-            .parse::<NonZeroUsize>()?
-    } else {
-        unsafe { NonZeroUsize::new_unchecked(1) }
-    };
-
-    let repeat_dur = match elem.try_get_attribute("repeatDur")? {
-        Some(repeat) => {
-            let repeat =
-                repeat.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            Some(TimeDesignation::from_str(&repeat)?)
-        }
-        None => None,
-    };
-
-    let sound_level = match elem.try_get_attribute("soundLevel")? {
-        Some(sound) => {
-            let sound =
-                sound.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            parse_decibel(&sound)?
-        }
-        None => 0.0,
-    };
-
-    let speed = match elem.try_get_attribute("speed")? {
-        Some(speed) => {
-            let speed =
-                speed.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?;
-            parse_unsigned_percentage(&speed)? / 100.0
-        }
-        None => 1.0,
-    };
+    let src = parse_attribute(&elem, reader, "src", http::Uri::from_str)?;
+    let fetch_timeout = parse_attribute(&elem, reader, "fetchtimeout", TimeDesignation::from_str)?;
+    let fetch_hint =
+        parse_attribute(&elem, reader, "fetchhint", FetchHint::from_str)?.unwrap_or_default();
+    let max_age = parse_attribute(&elem, reader, "maxage", usize::from_str)?;
+    let max_stale = parse_attribute(&elem, reader, "maxstale", usize::from_str)?;
+    let clip_begin = parse_attribute(&elem, reader, "clipBegin", TimeDesignation::from_str)?
+        .unwrap_or(TimeDesignation::Seconds(0.0));
+    let clip_end = parse_attribute(&elem, reader, "clipEnd", TimeDesignation::from_str)?;
+    let repeat_count = parse_attribute(&elem, reader, "repeatCount", NonZeroUsize::from_str)?
+        .unwrap_or(unsafe { NonZeroUsize::new_unchecked(1) });
+    let repeat_dur = parse_attribute(&elem, reader, "repeatDur", TimeDesignation::from_str)?;
+    let sound_level = parse_attribute(&elem, reader, "soundLevel", parse_decibel)?.unwrap_or(0.0);
+    let speed = parse_attribute(&elem, reader, "speed", |speed| {
+        parse_unsigned_percentage(speed).map(|x| x / 100.0)
+    })?
+    .unwrap_or(1.0);
 
     Ok(ParsedElement::Audio(AudioAttributes {
         src,
@@ -1033,6 +1146,272 @@ mod tests {
         assert!(parse_ssml("<speak><p>hello<p>world</p></p></speak>").is_err());
     }
 
+    #[test]
+    fn error_positions() {
+        let err =
+            parse_ssml("<speak>\n  <say-as format=\"dmy\">1/2</say-as>\n</speak>").unwrap_err();
+        assert!(matches!(err, SsmlError::MissingAttribute { .. }));
+        assert_eq!(err.element(), Some("say-as"));
+        assert_eq!(err.attribute(), Some("interpret-as"));
+        assert_eq!(
+            err.position(),
+            Position {
+                byte: 10,
+                line: 2,
+                column: 3
+            }
+        );
+
+        let err =
+            parse_ssml(r#"<speak>Hi <prosody rate="-20%">there</prosody></speak>"#).unwrap_err();
+        if let SsmlError::InvalidAttributeValue {
+            element,
+            attribute,
+            value,
+            position,
+            ..
+        } = err
+        {
+            assert_eq!(element, "prosody");
+            assert_eq!(attribute, "rate");
+            assert_eq!(value, "-20%");
+            assert_eq!(position.byte, 10);
+        } else {
+            panic!("Unexpected error: {:?}", err);
+        }
+
+        let err = parse_ssml("<speak><s>Hello <p>world</p></s></speak>").unwrap_err();
+        if let SsmlError::InvalidNesting {
+            parent,
+            child,
+            position,
+        } = err
+        {
+            assert_eq!(parent, "s");
+            assert_eq!(child, "p");
+            assert_eq!(position.column, 17);
+        } else {
+            panic!("Unexpected error: {:?}", err);
+        }
+
+        // Empty elements are checked against their parent too
+        let err = parse_ssml("<speak>\n<s>Hello <p/></s></speak>").unwrap_err();
+        if let SsmlError::InvalidNesting {
+            parent,
+            child,
+            position,
+        } = err
+        {
+            assert_eq!(parent, "s");
+            assert_eq!(child, "p");
+            assert_eq!(
+                position,
+                Position {
+                    byte: 17,
+                    line: 2,
+                    column: 10
+                }
+            );
+        } else {
+            panic!("Unexpected error: {:?}", err);
+        }
+
+        let err = parse_ssml(r#"<speak version="2.0"></speak>"#).unwrap_err();
+        assert!(
+            matches!(err, SsmlError::UnsupportedVersion { ref version, .. } if version == "2.0")
+        );
+
+        let err = parse_ssml("<speak>Fish &chips;</speak>").unwrap_err();
+        if let SsmlError::UnknownEntity { entity, position } = err {
+            assert_eq!(entity, "chips");
+            assert_eq!(position.byte, 12);
+        } else {
+            panic!("Unexpected error: {:?}", err);
+        }
+
+        let err = parse_ssml("<speak><s>Hello</p></speak>").unwrap_err();
+        assert!(matches!(err, SsmlError::Xml { .. }));
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let parser = SsmlParserBuilder::default().lenient(true).build().unwrap();
+        let ssml = r#"<speak version="3.0"><s>Hello <p>there</p> <prosody rate="-50%" volume="loud">general</prosody> <say-as>kenobi</say-as> &amp; &droids;</s></speak>"#;
+
+        assert!(parse_ssml(ssml).is_err());
+
+        let res = parser.parse(ssml).unwrap();
+        assert_eq!(res.get_text(), "Hello there general kenobi & ");
+
+        let repairs = res.warnings().iter().map(|x| x.repair).collect::<Vec<_>>();
+        assert_eq!(
+            repairs,
+            vec![
+                Repair::RemovedAttribute,
+                Repair::RemovedElement,
+                Repair::RemovedAttribute,
+                Repair::RemovedElement,
+                Repair::RemovedEntity
+            ]
+        );
+        assert_eq!(res.warnings()[0].error.attribute(), Some("version"));
+        assert_eq!(res.warnings()[1].position().column, 31);
+        assert_eq!(res.warnings()[2].error.attribute(), Some("rate"));
+
+        let tags = res.tags().map(|x| x.element.clone()).collect::<Vec<_>>();
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[1], ParsedElement::Sentence(Default::default()));
+        assert_eq!(
+            tags[2],
+            ParsedElement::Prosody(ProsodyAttributes {
+                volume: Some(VolumeRange::Strength(VolumeStrength::Loud)),
+                ..Default::default()
+            })
+        );
+        if let ParsedElement::Speak(speak) = &tags[0] {
+            assert_eq!(speak.version, "1.1");
+        } else {
+            panic!("Expected speak element: {:?}", tags[0]);
+        }
+
+        // Output is valid SSML
+        let rewritten = res.write_ssml();
+        assert_eq!(parse_ssml(&rewritten).unwrap().get_text(), res.get_text());
+    }
+
+    #[test]
+    fn lenient_self_closing_elements() {
+        let ssml = "<speak><s>Hi <p/>there</s></speak>";
+        assert!(matches!(
+            parse_ssml(ssml).unwrap_err(),
+            SsmlError::InvalidNesting { .. }
+        ));
+
+        let parser = SsmlParserBuilder::default().lenient(true).build().unwrap();
+        let res = parser.parse(ssml).unwrap();
+        assert_eq!(res.get_text(), "Hi there");
+        assert_eq!(res.warnings().len(), 1);
+        assert_eq!(res.warnings()[0].repair, Repair::RemovedElement);
+        assert_eq!(res.warnings()[0].error.element(), Some("p"));
+        assert_eq!(
+            res.write_ssml(),
+            r#"<speak version="1.1"><s>Hi there</s></speak>"#
+        );
+    }
+
+    #[test]
+    fn validate_say_as_text() {
+        let ssml = r#"<speak>Due <say-as interpret-as="date" format="dmy">31/02/2024</say-as> at <say-as interpret-as="time" format="hm24">10:30</say-as></speak>"#;
+        assert!(parse_ssml(ssml).is_ok());
+
+        let strict = SsmlParserBuilder::default()
+            .validate_say_as(true)
+            .build()
+            .unwrap();
+        let err = strict.parse(ssml).unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidContent { ref text, .. } if text == "31/02/2024"));
+        assert_eq!(err.element(), Some("say-as"));
+        assert_eq!(err.position().column, 12);
+
+        let lenient = SsmlParserBuilder::default()
+            .validate_say_as(true)
+            .lenient(true)
+            .build()
+            .unwrap();
+        let res = lenient.parse(ssml).unwrap();
+        assert_eq!(res.get_text(), "Due 31/02/2024 at 10:30");
+        assert_eq!(res.warnings().len(), 1);
+        assert_eq!(res.warnings()[0].repair, Repair::RemovedElement);
+        let say_as = res
+            .tags()
+            .filter_map(|x| match &x.element {
+                ParsedElement::SayAs(attrs) => Some(attrs.interpret_as.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(say_as, [InterpretAs::Time]);
+
+        let invalid_format =
+            r#"<speak><say-as interpret-as="date" format="yyyy">2024</say-as></speak>"#;
+        let err = parse_ssml(invalid_format).unwrap_err();
+        assert_eq!(err.attribute(), Some("format"));
+    }
+
+    #[test]
+    fn speak_marks() {
+        let ssml =
+            r#"<speak startmark="a" endmark="b">One <mark name="a"/>two <mark name="b"/></speak>"#;
+        let res = parse_ssml(ssml).unwrap();
+        assert!(matches!(
+            res.tags().next().map(|x| &x.element),
+            Some(ParsedElement::Speak(SpeakAttributes { start_mark: Some(start), end_mark: Some(end), .. }))
+                if start == "a" && end == "b"
+        ));
+
+        let missing = r#"<speak startmark="a" endmark="c">One <mark name="a"/>two</speak>"#;
+        let err = parse_ssml(missing).unwrap_err();
+        assert_eq!(err.attribute(), Some("endmark"));
+        let reversed =
+            r#"<speak startmark="b" endmark="a">One <mark name="a"/>two <mark name="b"/></speak>"#;
+        let err = parse_ssml(reversed).unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidAttributeValue { ref value, .. } if value == "a"));
+
+        let lenient = SsmlParserBuilder::default().lenient(true).build().unwrap();
+        let res = lenient.parse(missing).unwrap();
+        assert_eq!(res.warnings().len(), 1);
+        assert_eq!(res.warnings()[0].repair, Repair::RemovedAttribute);
+        assert_eq!(
+            res.write_ssml(),
+            r#"<speak version="1.1" startmark="a">One <mark name="a"/>two</speak>"#
+        );
+        assert_eq!(res.rendered_range(), 4..7);
+    }
+
+    #[test]
+    fn validate_phonemes() {
+        let ssml = r#"<speak><phoneme alphabet="ipa" ph="h@loU">hello</phoneme> <phoneme alphabet="x-sampa" ph="h@loU">hello</phoneme> <phoneme alphabet="x-foo" ph="h@loU">hello</phoneme></speak>"#;
+        assert!(parse_ssml(ssml).is_ok());
+
+        let strict = SsmlParserBuilder::default()
+            .validate_phonemes(true)
+            .build()
+            .unwrap();
+        let err = strict.parse(ssml).unwrap_err();
+        assert!(matches!(
+            err,
+            SsmlError::InvalidPhoneme { ref phone, offset: 1, ref alphabet, .. }
+                if phone == "@" && alphabet == "ipa"
+        ));
+        assert_eq!(err.element(), Some("phoneme"));
+        assert_eq!(err.attribute(), Some("ph"));
+        assert_eq!(err.position().column, 8);
+
+        // Diacritics and modifier letters are valid IPA
+        let diacritics =
+            r#"<speak><phoneme alphabet="ipa" ph="ˈhaɪ̯t̪ɫ̩ ʧʼ˥˩">hello</phoneme></speak>"#;
+        assert!(strict.parse(diacritics).is_ok());
+
+        let lenient = SsmlParserBuilder::default()
+            .validate_phonemes(true)
+            .lenient(true)
+            .build()
+            .unwrap();
+        let res = lenient.parse(ssml).unwrap();
+        assert_eq!(res.get_text(), "hello hello hello");
+        assert_eq!(res.warnings().len(), 1);
+        assert_eq!(res.warnings()[0].repair, Repair::RemovedElement);
+        assert_eq!(
+            res.tags()
+                .filter(|x| matches!(x.element, ParsedElement::Phoneme(_)))
+                .count(),
+            2
+        );
+
+        let empty = r#"<speak><phoneme alphabet="x-arpabet" ph="HH AH0 L OW1 XX"/></speak>"#;
+        let err = strict.parse(empty).unwrap_err();
+        assert!(matches!(err, SsmlError::InvalidPhoneme { offset: 13, .. }));
+    }
+
     #[test]
     fn skip_description_text() {
         let text = r#"<?xml version="1.0"?>
@@ -1067,14 +1446,14 @@ mod tests {
         assert_eq!(
             res.tags[1].element,
             ParsedElement::Lang(LangAttributes {
-                lang: "ja".to_string(),
+                lang: "ja".parse().unwrap(),
                 on_lang_failure: None
             })
         );
         assert_eq!(
             res.tags[2].element,
             ParsedElement::Lang(LangAttributes {
-                lang: "en".to_string(),
+                lang: "en".parse().unwrap(),
                 on_lang_failure: Some(OnLanguageFailure::IgnoreText)
             })
         );
@@ -0,0 +1,527 @@
+//! Reads Pronunciation Lexicon Specification (PLS) 1.0 documents, the pronunciation dictionaries
+//! the lexicon element of SSML refers to. Each lexeme in a lexicon gives the pronunciations of one
+//! or more spellings (graphemes) either as phonemes or as an alias to read instead, and can be
+//! limited to some roles such as a part of speech. These are matched against the role attribute
+//! of the SSML w element.
+//!
+//! Lexemes are looked up by their exact grapheme, after collapsing whitespace. Roles are QNames
+//! but are compared as they're written without resolving their namespace prefixes.
+use crate::elements::PhonemeAlphabet;
+use crate::error::{Position, SsmlError};
+use crate::language_tag::LanguageTag;
+use crate::parser::{
+    element_name, get_attribute, parse_attribute, parse_required_attribute, push_general_ref,
+    push_text_event,
+};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+type Result<T> = std::result::Result<T, SsmlError>;
+
+/// A pronunciation lexicon read from a PLS document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lexicon {
+    alphabet: PhonemeAlphabet,
+    lang: LanguageTag,
+    lexemes: Vec<Lexeme>,
+    /// Indexes of the lexemes for each grapheme
+    index: HashMap<String, Vec<usize>>,
+}
+
+/// The pronunciations of one or more graphemes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Lexeme {
+    /// The spellings the lexeme applies to.
+    pub graphemes: Vec<String>,
+    /// Roles the lexeme is limited to, such as "claws:VVI" for a verb. Empty if the lexeme
+    /// applies whatever the role.
+    pub roles: Vec<String>,
+    /// Pronunciations in the order they're written.
+    pub pronunciations: Vec<Pronunciation>,
+}
+
+/// A way of pronouncing a lexeme.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pronunciation {
+    /// Phonemes for the lexeme, like the ph attribute of the SSML phoneme element.
+    Phoneme {
+        /// The phonetic transcription.
+        ph: String,
+        /// Alphabet of the transcription, the lexicon's alphabet unless the phoneme sets one.
+        alphabet: PhonemeAlphabet,
+        /// Whether this is the preferred pronunciation.
+        prefer: bool,
+    },
+    /// Text to read instead of the grapheme, like the alias of the SSML sub element.
+    Alias {
+        /// The text to read.
+        alias: String,
+        /// Whether this is the preferred pronunciation.
+        prefer: bool,
+    },
+}
+
+impl Pronunciation {
+    /// Whether the lexicon marks this as the preferred pronunciation.
+    pub fn prefer(&self) -> bool {
+        match self {
+            Self::Phoneme { prefer, .. } | Self::Alias { prefer, .. } => *prefer,
+        }
+    }
+}
+
+impl Lexeme {
+    /// The pronunciation to use, the first one with prefer set or the first one if none are
+    /// preferred.
+    pub fn preferred(&self) -> Option<&Pronunciation> {
+        self.pronunciations
+            .iter()
+            .find(|x| x.prefer())
+            .or_else(|| self.pronunciations.first())
+    }
+}
+
+impl Lexicon {
+    /// Creates a lexicon from its lexemes, the alphabet and language are those given on the
+    /// lexicon element of a PLS document.
+    pub fn new(alphabet: PhonemeAlphabet, lang: LanguageTag, lexemes: Vec<Lexeme>) -> Self {
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, lexeme) in lexemes.iter().enumerate() {
+            for grapheme in &lexeme.graphemes {
+                index.entry(normalise_space(grapheme)).or_default().push(i);
+            }
+        }
+        Self {
+            alphabet,
+            lang,
+            lexemes,
+            index,
+        }
+    }
+
+    /// The default alphabet of the lexicon's phonemes.
+    pub fn alphabet(&self) -> &PhonemeAlphabet {
+        &self.alphabet
+    }
+
+    /// The language of the lexicon.
+    pub fn lang(&self) -> &LanguageTag {
+        &self.lang
+    }
+
+    /// All of the lexemes in document order.
+    pub fn lexemes(&self) -> &[Lexeme] {
+        &self.lexemes
+    }
+
+    /// Finds the lexeme for a grapheme. With a role the first lexeme with that role is used,
+    /// falling back to the first lexeme without any roles. Without a role lexemes without roles
+    /// are preferred but any lexeme for the grapheme can be returned.
+    pub fn lookup(&self, grapheme: &str, role: Option<&str>) -> Option<&Lexeme> {
+        let lexemes = self
+            .index
+            .get(&normalise_space(grapheme))?
+            .iter()
+            .map(|x| &self.lexemes[*x]);
+        let mut unrestricted = lexemes.clone().filter(|x| x.roles.is_empty());
+        match role {
+            Some(role) => lexemes
+                .clone()
+                .find(|x| x.roles.iter().any(|x| x == role))
+                .or_else(|| unrestricted.next()),
+            None => unrestricted.next().or_else(|| lexemes.clone().next()),
+        }
+    }
+
+    /// The preferred pronunciation of a grapheme, see `lookup` for how the lexeme is picked.
+    pub fn pronunciation(&self, grapheme: &str, role: Option<&str>) -> Option<&Pronunciation> {
+        self.lookup(grapheme, role)?.preferred()
+    }
+}
+
+impl FromStr for Lexicon {
+    type Err = SsmlError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_lexicon(s)
+    }
+}
+
+fn normalise_space(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The grapheme, phoneme or alias element being read.
+enum Content {
+    Grapheme,
+    Phoneme {
+        alphabet: PhonemeAlphabet,
+        prefer: bool,
+    },
+    Alias {
+        prefer: bool,
+    },
+}
+
+impl Content {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Grapheme => "grapheme",
+            Self::Phoneme { .. } => "phoneme",
+            Self::Alias { .. } => "alias",
+        }
+    }
+}
+
+/// State while reading a PLS document, each element being read has the byte offset it starts at
+/// so errors found when it's closed can point to it.
+struct LexiconReader<'a> {
+    source: &'a str,
+    lexicon: Option<(PhonemeAlphabet, LanguageTag)>,
+    lexemes: Vec<Lexeme>,
+    lexeme: Option<(Lexeme, usize)>,
+    content: Option<(Content, String, usize)>,
+}
+
+impl LexiconReader<'_> {
+    fn parent(&self) -> &'static str {
+        match (&self.lexicon, &self.lexeme, &self.content) {
+            (_, _, Some((content, _, _))) => content.name(),
+            (_, Some(_), None) => "lexeme",
+            (Some(_), None, None) => "lexicon",
+            (None, None, None) => "#document",
+        }
+    }
+
+    /// Handles an open tag, returning true if the element's content should be skipped.
+    fn open(&mut self, elem: &BytesStart, reader: &Reader<&[u8]>, start: usize) -> Result<bool> {
+        let name = String::from_utf8_lossy(elem.local_name().as_ref()).to_string();
+        match (name.as_str(), &self.lexicon, &self.lexeme, &self.content) {
+            ("lexicon", None, None, None) => {
+                parse_required_attribute(elem, reader, "version", |x| match x {
+                    "1.0" => Ok(()),
+                    _ => Err("only PLS 1.0 is supported"),
+                })?;
+                let alphabet =
+                    parse_required_attribute(elem, reader, "alphabet", PhonemeAlphabet::from_str)?;
+                let lang =
+                    parse_required_attribute(elem, reader, "xml:lang", LanguageTag::from_str)?;
+                self.lexicon = Some((alphabet, lang));
+                Ok(false)
+            }
+            ("meta" | "metadata", Some(_), None, None) => Ok(true),
+            ("lexeme", Some(_), None, None) => {
+                let roles = get_attribute(elem, reader, "role")?
+                    .map(|x| x.split_whitespace().map(String::from).collect())
+                    .unwrap_or_default();
+                let lexeme = Lexeme {
+                    roles,
+                    ..Default::default()
+                };
+                self.lexeme = Some((lexeme, start));
+                Ok(false)
+            }
+            ("example", _, Some(_), None) => Ok(true),
+            ("grapheme" | "phoneme" | "alias", Some((alphabet, _)), Some(_), None) => {
+                let prefer = parse_attribute(elem, reader, "prefer", bool::from_str)?;
+                let content = match name.as_str() {
+                    "grapheme" => Content::Grapheme,
+                    "phoneme" => Content::Phoneme {
+                        alphabet: parse_attribute(
+                            elem,
+                            reader,
+                            "alphabet",
+                            PhonemeAlphabet::from_str,
+                        )?
+                        .unwrap_or_else(|| alphabet.clone()),
+                        prefer: prefer.unwrap_or_default(),
+                    },
+                    _ => Content::Alias {
+                        prefer: prefer.unwrap_or_default(),
+                    },
+                };
+                self.content = Some((content, String::new(), start));
+                Ok(false)
+            }
+            _ => Err(SsmlError::InvalidNesting {
+                parent: self.parent().to_string(),
+                child: element_name(&elem.name())?,
+                position: Position::default(),
+            }),
+        }
+    }
+
+    /// Handles a close tag, finishing the innermost element being read.
+    fn close(&mut self) -> Result<()> {
+        let invalid = |element: &str, reason: &str, start: usize| SsmlError::InvalidContent {
+            element: element.to_string(),
+            text: String::new(),
+            reason: reason.to_string(),
+            position: Position::from_byte_offset(self.source, start),
+        };
+        if let Some((content, text, start)) = self.content.take() {
+            let text = text.trim().to_string();
+            if text.is_empty() {
+                return Err(invalid(content.name(), "it can't be empty", start));
+            }
+            let (lexeme, _) = self
+                .lexeme
+                .as_mut()
+                .expect("content is only read in a lexeme");
+            match content {
+                Content::Grapheme => lexeme.graphemes.push(text),
+                Content::Phoneme { alphabet, prefer } => {
+                    lexeme.pronunciations.push(Pronunciation::Phoneme {
+                        ph: text,
+                        alphabet,
+                        prefer,
+                    })
+                }
+                Content::Alias { prefer } => lexeme.pronunciations.push(Pronunciation::Alias {
+                    alias: text,
+                    prefer,
+                }),
+            }
+        } else if let Some((lexeme, start)) = self.lexeme.take() {
+            if lexeme.graphemes.is_empty() {
+                return Err(invalid("lexeme", "it needs at least one grapheme", start));
+            }
+            if lexeme.pronunciations.is_empty() {
+                return Err(invalid(
+                    "lexeme",
+                    "it needs at least one phoneme or alias",
+                    start,
+                ));
+            }
+            self.lexemes.push(lexeme);
+        }
+        Ok(())
+    }
+}
+
+/// Reads a PLS 1.0 document. Errors point to the place in the document they were found, using
+/// the same errors as parsing SSML.
+pub fn parse_lexicon(pls: &str) -> Result<Lexicon> {
+    let mut reader = Reader::from_str(pls);
+    reader.config_mut().check_end_names = true;
+    let mut state = LexiconReader {
+        source: pls,
+        lexicon: None,
+        lexemes: vec![],
+        lexeme: None,
+        content: None,
+    };
+
+    loop {
+        let event_start = reader.buffer_position() as usize;
+        let at = |e: SsmlError| e.with_position(Position::from_byte_offset(pls, event_start));
+        let event = reader.read_event().map_err(|e| {
+            SsmlError::from(e).with_position(Position::from_byte_offset(
+                pls,
+                reader.error_position() as usize,
+            ))
+        })?;
+        match event {
+            Event::Start(e) => {
+                if state.open(&e, &reader, event_start).map_err(at)? {
+                    reader.read_to_end(e.name()).map_err(|e| at(e.into()))?;
+                }
+            }
+            Event::Empty(e) => {
+                if !state.open(&e, &reader, event_start).map_err(at)? {
+                    state.close()?;
+                }
+            }
+            Event::End(_) => state.close()?,
+            Event::Text(e) => match &mut state.content {
+                Some((_, text, _)) => push_text_event(e, text).map_err(at)?,
+                None => {
+                    let text = e.xml10_content().map_err(|e| at(e.into()))?;
+                    if !text.trim().is_empty() {
+                        return Err(at(SsmlError::InvalidContent {
+                            element: state.parent().to_string(),
+                            text: text.trim().to_string(),
+                            reason: "text isn't allowed here".to_string(),
+                            position: Position::default(),
+                        }));
+                    }
+                }
+            },
+            Event::GeneralRef(e) => match &mut state.content {
+                Some((_, text, _)) => push_general_ref(e, text).map_err(at)?,
+                None => {
+                    return Err(at(SsmlError::InvalidContent {
+                        element: state.parent().to_string(),
+                        text: format!("&{};", String::from_utf8_lossy(&e)),
+                        reason: "text isn't allowed here".to_string(),
+                        position: Position::default(),
+                    }))
+                }
+            },
+            Event::Eof => break,
+            Event::Comment(_)
+            | Event::CData(_)
+            | Event::Decl(_)
+            | Event::PI(_)
+            | Event::DocType(_) => continue,
+        }
+    }
+
+    match state.lexicon {
+        Some((alphabet, lang)) => Ok(Lexicon::new(alphabet, lang, state.lexemes)),
+        None => Err(SsmlError::InvalidContent {
+            element: "#document".to_string(),
+            text: String::new(),
+            reason: "there's no lexicon element".to_string(),
+            position: Position::from_byte_offset(pls, pls.len()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEXICON: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<lexicon version="1.0" xmlns="http://www.w3.org/2005/01/pronunciation-lexicon"
+    xmlns:claws="http://www.example.com/claws7tags"
+    alphabet="ipa" xml:lang="en-GB">
+  <meta name="author" content="Emotech"/>
+  <metadata><dc:creator xmlns:dc="http://purl.org/dc/elements/1.1/">Emotech</dc:creator></metadata>
+  <lexeme>
+    <grapheme>tomato</grapheme>
+    <grapheme>Tomato</grapheme>
+    <phoneme>təˈmɑːtəʊ</phoneme>
+    <phoneme prefer="true" alphabet="x-sampa">t@"mA:t@U</phoneme>
+    <example>I like tomatoes</example>
+  </lexeme>
+  <lexeme>
+    <grapheme>W3C</grapheme>
+    <alias>World Wide Web Consortium</alias>
+  </lexeme>
+  <lexeme role="claws:VVI claws:VV0">
+    <grapheme>read</grapheme>
+    <phoneme>riːd</phoneme>
+  </lexeme>
+  <lexeme role="claws:VVD claws:VVN">
+    <grapheme>read</grapheme>
+    <phoneme>rɛd</phoneme>
+  </lexeme>
+  <lexeme>
+    <grapheme>New   York</grapheme>
+    <alias>New York &amp; City</alias>
+  </lexeme>
+</lexicon>"#;
+
+    #[test]
+    fn parse_lexicon_document() {
+        let lexicon = parse_lexicon(LEXICON).unwrap();
+        assert_eq!(lexicon.alphabet(), &PhonemeAlphabet::Ipa);
+        assert_eq!(lexicon.lang(), "en-GB");
+        assert_eq!(lexicon.lexemes().len(), 5);
+
+        let tomato = &lexicon.lexemes()[0];
+        assert_eq!(tomato.graphemes, ["tomato", "Tomato"]);
+        assert!(tomato.roles.is_empty());
+        assert_eq!(
+            tomato.pronunciations[0],
+            Pronunciation::Phoneme {
+                ph: "təˈmɑːtəʊ".to_string(),
+                alphabet: PhonemeAlphabet::Ipa,
+                prefer: false,
+            }
+        );
+        assert_eq!(
+            tomato.preferred(),
+            Some(&Pronunciation::Phoneme {
+                ph: "t@\"mA:t@U".to_string(),
+                alphabet: PhonemeAlphabet::Other("x-sampa".to_string()),
+                prefer: true,
+            })
+        );
+        assert_eq!(lexicon.lexemes()[2].roles, ["claws:VVI", "claws:VV0"]);
+    }
+
+    #[test]
+    fn lookup() {
+        let lexicon = parse_lexicon(LEXICON).unwrap();
+        let ph = |grapheme, role| match lexicon.pronunciation(grapheme, role) {
+            Some(Pronunciation::Phoneme { ph, .. }) => Some(ph.as_str()),
+            Some(Pronunciation::Alias { alias, .. }) => Some(alias.as_str()),
+            None => None,
+        };
+        assert_eq!(ph("Tomato", None), Some("t@\"mA:t@U"));
+        assert_eq!(ph("TOMATO", None), None);
+        assert_eq!(
+            ph("W3C", Some("claws:NP1")),
+            Some("World Wide Web Consortium")
+        );
+        assert_eq!(ph("read", Some("claws:VVD")), Some("rɛd"));
+        assert_eq!(ph("read", Some("claws:VV0")), Some("riːd"));
+        assert_eq!(ph("read", None), Some("riːd"));
+        assert_eq!(ph("read", Some("claws:NN1")), None);
+        assert_eq!(ph(" New York\n", None), Some("New York & City"));
+        assert!(lexicon.lookup("York", None).is_none());
+    }
+
+    #[test]
+    fn invalid_lexicons() {
+        let header = r#"<lexicon version="1.0" alphabet="ipa" xml:lang="en-GB">"#;
+        let invalid = [
+            (
+                r#"<lexicon version="1.0" alphabet="ipa">"#.to_string(),
+                "xml:lang",
+            ),
+            (
+                r#"<lexicon version="2.0" alphabet="ipa" xml:lang="en">"#.to_string(),
+                "version",
+            ),
+            (
+                r#"<lexicon version="1.0" alphabet="ipa" xml:lang="en_GB">"#.to_string(),
+                "xml:lang",
+            ),
+            (
+                format!(
+                    r#"{}<lexeme><grapheme>a</grapheme><phoneme prefer="yes">a</phoneme></lexeme>"#,
+                    header
+                ),
+                "prefer",
+            ),
+        ];
+        for (xml, attribute) in invalid {
+            let xml = format!("{}</lexicon>", xml);
+            let error = parse_lexicon(&xml).unwrap_err();
+            assert_eq!(error.attribute(), Some(attribute), "{}", xml);
+        }
+
+        let error = parse_lexicon(&format!(
+            "{}\n<lexeme>\n<grapheme>a</grapheme>\n</lexeme></lexicon>",
+            header
+        ))
+        .unwrap_err();
+        assert!(matches!(error, SsmlError::InvalidContent { .. }));
+        assert_eq!(error.position().line, 2);
+
+        let error = parse_lexicon(&format!(
+            "{}<lexeme><grapheme>a<phoneme>a</phoneme></grapheme></lexeme></lexicon>",
+            header
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            SsmlError::InvalidNesting { ref parent, ref child, .. }
+                if parent == "grapheme" && child == "phoneme"
+        ));
+
+        assert!(parse_lexicon("<speak>Hello</speak>").is_err());
+        assert!(parse_lexicon("<!-- nothing -->").is_err());
+        assert!(parse_lexicon(&format!("{}hello</lexicon>", header)).is_err());
+        assert!(parse_lexicon(&format!(
+            "{}<lexeme><grapheme> </grapheme><alias>a</alias></lexeme></lexicon>",
+            header
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,394 @@
+//! Capability profiles describe the subset of SSML a TTS engine supports: which elements it
+//! accepts, which attributes they can have and what values those attributes can take. A parsed
+//! document can be checked against a profile with `Ssml::validate_for` to find everything the
+//! engine would reject or ignore before sending the request.
+//!
+//! Profiles are plain data so they can be kept outside the code. With the `profiles` feature
+//! enabled they can be loaded from TOML or JSON, for example:
+//!
+//! ```toml
+//! name = "example"
+//!
+//! [elements.speak]
+//! [elements.s]
+//! [elements.break.attributes.strength]
+//! values = ["none", "x-weak", "weak", "medium", "strong", "x-strong"]
+//! [elements.break.attributes.time]
+//! min = 0
+//! max = 5000
+//! unit = "ms"
+//! ```
+//!
+//! Elements not listed in the profile aren't supported. An element listed without an
+//! `attributes` table accepts any attributes, otherwise only the listed attributes are allowed.
+//! Namespace declarations (`xmlns` attributes) are always allowed.
+use crate::elements::{ParsedElement, SsmlElement};
+use crate::parser::Span;
+use crate::Ssml;
+use lazy_static::lazy_static;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use quick_xml::XmlVersion;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The SSML a TTS engine supports.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Profile {
+    /// Name of the engine the profile describes.
+    pub name: String,
+    /// Supported elements by element name, names of dialect elements include their prefix.
+    pub elements: BTreeMap<String, ElementRule>,
+}
+
+/// What an engine supports for an element.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ElementRule {
+    /// Supported attributes by attribute name, when this is `None` any attribute is allowed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attributes: Option<BTreeMap<String, AttributeRule>>,
+}
+
+/// Values an engine supports for an attribute. With no values or limits set any value is
+/// allowed.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct AttributeRule {
+    /// Values which are allowed as written, such as the keywords "slow" or "x-loud".
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub values: Option<Vec<String>>,
+    /// Smallest allowed number for numeric values.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub min: Option<f32>,
+    /// Largest allowed number for numeric values.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max: Option<f32>,
+    /// Unit numeric values must use, such as "ms", "%" or "dB". Times in seconds are converted
+    /// to milliseconds before they're checked.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unit: Option<String>,
+}
+
+/// Something in a document an engine doesn't support.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Incompatibility {
+    /// The span of the element which isn't supported.
+    pub span: Span,
+    /// Name of the element.
+    pub element: String,
+    /// Name of the attribute if the problem is with one of the element's attributes.
+    pub attribute: Option<String>,
+    /// The attribute value which isn't supported.
+    pub value: Option<String>,
+    /// Why the element or attribute isn't supported.
+    pub reason: String,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.attribute, &self.value) {
+            (Some(attribute), Some(value)) => write!(
+                f,
+                "value '{}' for {} attribute of {} element: {}",
+                value, attribute, self.element, self.reason
+            ),
+            (Some(attribute), None) => write!(
+                f,
+                "{} attribute of {} element: {}",
+                attribute, self.element, self.reason
+            ),
+            _ => write!(f, "{} element: {}", self.element, self.reason),
+        }
+    }
+}
+
+#[cfg(feature = "profiles")]
+impl Profile {
+    /// Loads a profile from a TOML document.
+    pub fn from_toml_str(s: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Loads a profile from a JSON document.
+    pub fn from_json_str(s: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// Loads a profile from a file, files with a `.json` extension are read as JSON and anything
+    /// else as TOML.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        use anyhow::Context;
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read profile {}", path.display()))?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json_str(&contents)
+        } else {
+            Self::from_toml_str(&contents)
+        }
+    }
+}
+
+impl Profile {
+    /// Checks an element against the profile, adding any problems to `incompatibilities`.
+    fn check(&self, span: &Span, incompatibilities: &mut Vec<Incompatibility>) {
+        let element = SsmlElement::from(&span.element).to_string();
+        let mut incompatible = |attribute: Option<&str>, value: Option<&str>, reason: &str| {
+            incompatibilities.push(Incompatibility {
+                span: span.clone(),
+                element: element.clone(),
+                attribute: attribute.map(|x| x.to_string()),
+                value: value.map(|x| x.to_string()),
+                reason: reason.to_string(),
+            })
+        };
+        let Some(rule) = self.elements.get(&element) else {
+            incompatible(None, None, "element isn't supported");
+            return;
+        };
+        let Some(allowed) = &rule.attributes else {
+            return;
+        };
+        for (name, value) in attributes(&span.element) {
+            if name == "xmlns" || name.starts_with("xmlns:") {
+                continue;
+            }
+            match allowed.get(&name) {
+                None => incompatible(Some(&name), None, "attribute isn't supported"),
+                Some(rule) => {
+                    if let Err(reason) = rule.check(&value) {
+                        incompatible(Some(&name), Some(&value), &reason);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl AttributeRule {
+    fn check(&self, value: &str) -> Result<(), String> {
+        if self.values.iter().flatten().any(|x| x == value) {
+            return Ok(());
+        }
+        if self.min.is_none() && self.max.is_none() && self.unit.is_none() {
+            return match &self.values {
+                Some(values) => Err(format!("value must be one of: {}", values.join(", "))),
+                None => Ok(()),
+            };
+        }
+        let Some((number, unit)) = quantity(value) else {
+            return Err("value isn't a supported keyword or number".to_string());
+        };
+        if let Some(expected) = &self.unit {
+            if expected != &unit {
+                return Err(format!("value must be in {}", expected));
+            }
+        }
+        let unit = self.unit.as_deref().unwrap_or_default();
+        match (self.min, self.max) {
+            (Some(min), _) if number < min => {
+                Err(format!("value must be at least {}{}", min, unit))
+            }
+            (_, Some(max)) if number > max => Err(format!("value must be at most {}{}", max, unit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Splits a value such as "+6dB" or "1.5s" into its number and unit. Times are converted to
+/// milliseconds.
+fn quantity(value: &str) -> Option<(f32, String)> {
+    lazy_static! {
+        static ref QUANTITY_RE: Regex =
+            Regex::new(r"^\s*([+-]?(?:\d*\.)?\d+)\s*([a-zA-Z%]*)\s*$").unwrap();
+    }
+    let caps = QUANTITY_RE.captures(value)?;
+    let number = caps[1].parse::<f32>().ok()?;
+    match &caps[2] {
+        "s" => Some((number * 1000.0, "ms".to_string())),
+        unit => Some((number, unit.to_string())),
+    }
+}
+
+/// The attributes of an element as they're written out in SSML.
+fn attributes(element: &ParsedElement) -> Vec<(String, String)> {
+    let xml = format!("<element{}/>", element.attribute_string());
+    let mut reader = Reader::from_str(&xml);
+    let Ok(Event::Empty(elem)) = reader.read_event() else {
+        return vec![];
+    };
+    elem.attributes()
+        .filter_map(|attr| {
+            let attr = attr.ok()?;
+            let name = std::str::from_utf8(attr.key.as_ref()).ok()?.to_string();
+            let value = attr
+                .decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())
+                .ok()?
+                .to_string();
+            Some((name, value))
+        })
+        .collect()
+}
+
+impl Ssml {
+    /// Checks every element in the document against a capability profile, returning everything
+    /// the engine it describes doesn't support.
+    pub fn validate_for(&self, profile: &Profile) -> Vec<Incompatibility> {
+        let mut incompatibilities = vec![];
+        for span in self.root().descendants().filter_map(|node| node.span()) {
+            profile.check(span, &mut incompatibilities);
+        }
+        incompatibilities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_ssml;
+
+    fn profile() -> Profile {
+        let attribute = |values: Option<&[&str]>, min, max, unit: Option<&str>| AttributeRule {
+            values: values.map(|v| v.iter().map(|x| x.to_string()).collect()),
+            min,
+            max,
+            unit: unit.map(|x| x.to_string()),
+        };
+        let mut elements = BTreeMap::new();
+        elements.insert("speak".to_string(), ElementRule::default());
+        elements.insert("s".to_string(), ElementRule::default());
+        elements.insert(
+            "break".to_string(),
+            ElementRule {
+                attributes: Some(BTreeMap::from([
+                    (
+                        "time".to_string(),
+                        attribute(None, Some(0.0), Some(5000.0), Some("ms")),
+                    ),
+                    (
+                        "strength".to_string(),
+                        attribute(Some(&["weak", "strong"]), None, None, None),
+                    ),
+                ])),
+            },
+        );
+        elements.insert(
+            "prosody".to_string(),
+            ElementRule {
+                attributes: Some(BTreeMap::from([(
+                    "rate".to_string(),
+                    attribute(Some(&["slow", "fast"]), Some(20.0), Some(200.0), Some("%")),
+                )])),
+            },
+        );
+        Profile {
+            name: "test".to_string(),
+            elements,
+        }
+    }
+
+    #[test]
+    fn validate_document() {
+        let ssml = parse_ssml(
+            r#"<speak xmlns="http://www.w3.org/2001/10/synthesis">
+            <s>Hello <break time="6s"/> <break time="250ms" strength="medium"/></s>
+            <prosody rate="slow" pitch="high">there</prosody>
+            <prosody rate="300%">fast</prosody>
+            <emphasis>world</emphasis>
+            </speak>"#,
+        )
+        .unwrap();
+
+        let problems = ssml
+            .validate_for(&profile())
+            .into_iter()
+            .map(|x| {
+                (
+                    x.element,
+                    x.attribute.unwrap_or_default(),
+                    x.value.unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("break", "time", "6s"),
+            ("break", "strength", "medium"),
+            ("prosody", "pitch", ""),
+            ("prosody", "rate", "300%"),
+            ("emphasis", "", ""),
+        ]
+        .map(|(e, a, v)| (e.to_string(), a.to_string(), v.to_string()));
+        assert_eq!(problems, expected);
+
+        let emphasis = ssml.validate_for(&profile()).pop().unwrap();
+        assert_eq!(ssml.get_text_from_span(&emphasis.span), "world");
+        assert_eq!(
+            emphasis.to_string(),
+            "emphasis element: element isn't supported"
+        );
+    }
+
+    #[test]
+    fn attribute_rules() {
+        let time = AttributeRule {
+            values: None,
+            min: Some(0.0),
+            max: Some(5000.0),
+            unit: Some("ms".to_string()),
+        };
+        assert!(time.check("5s").is_ok());
+        assert!(time.check("5001ms").is_err());
+        assert!(time.check("1dB").is_err());
+        assert!(time.check("soon").is_err());
+        assert!(AttributeRule::default().check("anything").is_ok());
+    }
+
+    #[cfg(feature = "profiles")]
+    #[test]
+    fn load_profiles() {
+        let toml = Profile::from_toml_str(
+            r#"
+            name = "test"
+            [elements.speak]
+            [elements.s]
+            [elements.break.attributes.time]
+            min = 0
+            max = 5000
+            unit = "ms"
+            [elements.break.attributes.strength]
+            values = ["weak", "strong"]
+            [elements.prosody.attributes.rate]
+            values = ["slow", "fast"]
+            min = 20
+            max = 200
+            unit = "%"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(toml, profile());
+
+        let json = serde_json::to_string(&toml).unwrap();
+        assert_eq!(Profile::from_json_str(&json).unwrap(), toml);
+
+        for file in std::fs::read_dir("profiles").unwrap() {
+            let path = file.unwrap().path();
+            let profile = Profile::from_file(&path).unwrap();
+            assert!(profile.elements.contains_key("speak"), "{}", path.display());
+        }
+
+        let ssml =
+            parse_ssml(r#"<speak><phoneme alphabet="x-sampa" ph="t@"/><break time="6s"/></speak>"#)
+                .unwrap();
+        let google = Profile::from_file("profiles/google.toml").unwrap();
+        let issues = ssml.validate_for(&google);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].element, "phoneme");
+        let azure = Profile::from_file("profiles/azure.toml").unwrap();
+        let issues = ssml.validate_for(&azure);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].element, "break");
+    }
+}
@@ -0,0 +1,287 @@
+//! Works out the prosody in effect for each run of text in a document. Prosody elements nest and
+//! most of their values are relative to the enclosing element's, a `rate="80%"` inside a
+//! `rate="fast"` is 80% of fast. The keyword values like "fast" or "x-low" are left up to the
+//! synthesis processor by the SSML specification, so these are taken from a `ProsodyBaseline`
+//! along with the voice's default pitch.
+use crate::elements::*;
+use crate::Ssml;
+use std::ops::Range;
+
+/// The default prosody of a voice and the values used for keywords. Pitches are in Hertz, rates
+/// are multiples of the voice's default speaking rate and volumes are gains in decibels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProsodyBaseline {
+    /// The voice's default pitch in Hertz.
+    pub pitch: f32,
+    /// The voice's default pitch range in Hertz.
+    pub range: f32,
+    /// Pitch keywords "x-low", "low", "medium", "high" and "x-high" as a change in semitones from
+    /// the default pitch, these are also used for the range.
+    pub pitch_keywords: [f32; 5],
+    /// Rate keywords "x-slow", "slow", "medium", "fast" and "x-fast" as multiples of the default
+    /// rate.
+    pub rate_keywords: [f32; 5],
+    /// Volume keywords "x-soft", "soft", "medium", "loud" and "x-loud" in decibels, "silent" is
+    /// always negative infinity.
+    pub volume_keywords: [f32; 5],
+}
+
+impl Default for ProsodyBaseline {
+    fn default() -> Self {
+        Self {
+            pitch: 120.0,
+            range: 40.0,
+            pitch_keywords: [-6.0, -3.0, 0.0, 3.0, 6.0],
+            rate_keywords: [0.5, 0.75, 1.0, 1.25, 1.5],
+            volume_keywords: [-12.0, -6.0, 0.0, 6.0, 12.0],
+        }
+    }
+}
+
+impl ProsodyBaseline {
+    /// The prosody of text outside of any prosody elements.
+    pub fn prosody(&self) -> EffectiveProsody {
+        EffectiveProsody {
+            pitch: self.pitch,
+            range: self.range,
+            rate: 1.0,
+            volume: 0.0,
+        }
+    }
+
+    fn pitch_keyword(&self, default: f32, strength: PitchStrength) -> f32 {
+        let semitones = match strength {
+            PitchStrength::XLow => self.pitch_keywords[0],
+            PitchStrength::Low => self.pitch_keywords[1],
+            PitchStrength::Medium => self.pitch_keywords[2],
+            PitchStrength::High => self.pitch_keywords[3],
+            PitchStrength::XHigh => self.pitch_keywords[4],
+            PitchStrength::Default => 0.0,
+        };
+        default * semitone_ratio(semitones)
+    }
+
+    fn resolve_pitch(&self, inherited: f32, default: f32, pitch: &PitchRange) -> f32 {
+        match pitch {
+            PitchRange::Strength(strength) => self.pitch_keyword(default, *strength),
+            PitchRange::Frequency(hz) => *hz,
+            PitchRange::RelativeChange((value, sign, unit)) => {
+                let change = match sign {
+                    Sign::Plus => value.abs(),
+                    Sign::Minus => -value.abs(),
+                };
+                let pitch = match unit {
+                    Unit::Hz => inherited + change,
+                    Unit::St => inherited * semitone_ratio(change),
+                    Unit::Percentage => inherited * (1.0 + change / 100.0),
+                };
+                pitch.max(0.0)
+            }
+        }
+    }
+
+    fn resolve_rate(&self, inherited: f32, rate: &RateRange) -> f32 {
+        match rate {
+            RateRange::Strength(RateStrength::XSlow) => self.rate_keywords[0],
+            RateRange::Strength(RateStrength::Slow) => self.rate_keywords[1],
+            RateRange::Strength(RateStrength::Medium) => self.rate_keywords[2],
+            RateRange::Strength(RateStrength::Fast) => self.rate_keywords[3],
+            RateRange::Strength(RateStrength::XFast) => self.rate_keywords[4],
+            RateRange::Strength(RateStrength::Default) => 1.0,
+            RateRange::Percentage(PositiveNumber::FloatNumber(x)) => inherited * x / 100.0,
+            RateRange::Percentage(PositiveNumber::RoundNumber(x)) => inherited * *x as f32 / 100.0,
+        }
+    }
+
+    fn resolve_volume(&self, inherited: f32, volume: &VolumeRange) -> f32 {
+        match volume {
+            VolumeRange::Strength(VolumeStrength::Silent) => f32::NEG_INFINITY,
+            VolumeRange::Strength(VolumeStrength::XSoft) => self.volume_keywords[0],
+            VolumeRange::Strength(VolumeStrength::Soft) => self.volume_keywords[1],
+            VolumeRange::Strength(VolumeStrength::Medium) => self.volume_keywords[2],
+            VolumeRange::Strength(VolumeStrength::Loud) => self.volume_keywords[3],
+            VolumeRange::Strength(VolumeStrength::XLoud) => self.volume_keywords[4],
+            VolumeRange::Strength(VolumeStrength::Default) => 0.0,
+            VolumeRange::Decibel(db) => inherited + db,
+        }
+    }
+
+    /// Applies a prosody element to the prosody of the text around it.
+    pub fn apply(
+        &self,
+        inherited: &EffectiveProsody,
+        attrs: &ProsodyAttributes,
+    ) -> EffectiveProsody {
+        let mut prosody = inherited.clone();
+        if let Some(pitch) = &attrs.pitch {
+            prosody.pitch = self.resolve_pitch(inherited.pitch, self.pitch, pitch);
+        }
+        if let Some(range) = &attrs.range {
+            prosody.range = self.resolve_pitch(inherited.range, self.range, range);
+        }
+        if let Some(rate) = &attrs.rate {
+            prosody.rate = self.resolve_rate(inherited.rate, rate);
+        }
+        if let Some(volume) = &attrs.volume {
+            prosody.volume = self.resolve_volume(inherited.volume, volume);
+        }
+        prosody
+    }
+}
+
+fn semitone_ratio(semitones: f32) -> f32 {
+    2.0f32.powf(semitones / 12.0)
+}
+
+/// The prosody in effect for some text after combining all the prosody elements around it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectiveProsody {
+    /// Baseline pitch in Hertz.
+    pub pitch: f32,
+    /// Pitch range in Hertz.
+    pub range: f32,
+    /// Speaking rate as a multiple of the voice's default rate.
+    pub rate: f32,
+    /// Volume as a gain in decibels relative to the voice's default volume, negative infinity
+    /// for silent text.
+    pub volume: f32,
+}
+
+impl EffectiveProsody {
+    /// The pitch as a multiple of the baseline's default pitch.
+    pub fn relative_pitch(&self, baseline: &ProsodyBaseline) -> f32 {
+        self.pitch / baseline.pitch
+    }
+
+    /// The pitch range as a multiple of the baseline's default range.
+    pub fn relative_range(&self, baseline: &ProsodyBaseline) -> f32 {
+        self.range / baseline.range
+    }
+}
+
+/// A run of text with the same effective prosody.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProsodyRun {
+    /// Range of the text in unicode scalar values, the same unit as `Span`.
+    pub range: Range<usize>,
+    /// The prosody of the text.
+    pub prosody: EffectiveProsody,
+}
+
+impl Ssml {
+    /// Splits the text into runs with the same prosody, resolving nested prosody elements against
+    /// the baseline. The pitch contour and duration of prosody elements apply to the element as a
+    /// whole so they aren't included, read them from the element's attributes.
+    pub fn prosody_runs(&self, baseline: &ProsodyBaseline) -> Vec<ProsodyRun> {
+        let mut runs: Vec<ProsodyRun> = vec![];
+        for node in self.root().descendants().filter(|x| x.is_text()) {
+            let mut elements = node
+                .ancestors()
+                .filter_map(|x| match x.element() {
+                    Some(ParsedElement::Prosody(attrs)) => Some(attrs),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            elements.reverse();
+            let prosody = elements
+                .into_iter()
+                .fold(baseline.prosody(), |prosody, attrs| {
+                    baseline.apply(&prosody, attrs)
+                });
+            let range = node.char_range();
+            match runs.last_mut() {
+                Some(last) if last.prosody == prosody && last.range.end == range.start => {
+                    last.range.end = range.end;
+                }
+                _ => runs.push(ProsodyRun { range, prosody }),
+            }
+        }
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::char_to_byte_range;
+    use crate::parse_ssml;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn runs(ssml: &str) -> Vec<(String, EffectiveProsody)> {
+        let ssml = parse_ssml(ssml).unwrap();
+        ssml.prosody_runs(&ProsodyBaseline::default())
+            .into_iter()
+            .map(|run| {
+                let text = ssml.get_text();
+                (
+                    text[char_to_byte_range(text, run.range)].to_string(),
+                    run.prosody,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_prosody() {
+        let runs = runs(
+            r#"<speak>Normal <prosody rate="fast" pitch="200Hz" volume="loud">fast <prosody rate="80%" pitch="+10%" volume="-6dB">slower <break/>still</prosody> <prosody pitch="-2st" range="x-high">low</prosody></prosody> <prosody rate="default" volume="silent">quiet</prosody></speak>"#,
+        );
+        let text = runs
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            text,
+            ["Normal ", "fast ", "slower still", " ", "low", " ", "quiet"]
+        );
+
+        let baseline = ProsodyBaseline::default();
+        assert_eq!(runs[0].1, baseline.prosody());
+        assert_eq!(runs[0].1, runs[5].1);
+
+        let fast = &runs[1].1;
+        assert_approx_eq!(fast.rate, 1.25);
+        assert_approx_eq!(fast.pitch, 200.0);
+        assert_approx_eq!(fast.volume, 6.0);
+        assert_eq!(runs[3].1, *fast);
+
+        let slower = &runs[2].1;
+        assert_approx_eq!(slower.rate, 1.0);
+        assert_approx_eq!(slower.pitch, 220.0);
+        assert_approx_eq!(slower.volume, 0.0);
+        assert_approx_eq!(slower.relative_pitch(&baseline), 220.0 / 120.0);
+
+        let low = &runs[4].1;
+        assert_approx_eq!(low.pitch, 200.0 * 2.0f32.powf(-2.0 / 12.0));
+        assert_approx_eq!(low.range, 40.0 * 2.0f32.powf(0.5));
+        assert_approx_eq!(low.rate, 1.25);
+
+        let quiet = &runs[6].1;
+        assert_approx_eq!(quiet.rate, 1.0);
+        assert_eq!(quiet.volume, f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn custom_baseline() {
+        let baseline = ProsodyBaseline {
+            pitch: 200.0,
+            rate_keywords: [0.6, 0.8, 1.0, 1.2, 1.4],
+            ..Default::default()
+        };
+        let attrs = ProsodyAttributes {
+            pitch: Some(PitchRange::Strength(PitchStrength::High)),
+            rate: Some(RateRange::Strength(RateStrength::XSlow)),
+            ..Default::default()
+        };
+        let prosody = baseline.apply(&baseline.prosody(), &attrs);
+        assert_approx_eq!(prosody.pitch, 200.0 * 2.0f32.powf(0.25));
+        assert_approx_eq!(prosody.rate, 0.6);
+
+        // Relative changes can't take the pitch below zero
+        let attrs = ProsodyAttributes {
+            pitch: Some(PitchRange::RelativeChange((300.0, Sign::Minus, Unit::Hz))),
+            ..Default::default()
+        };
+        assert_eq!(baseline.apply(&prosody, &attrs).pitch, 0.0);
+    }
+}
@@ -0,0 +1,516 @@
+//! Checks the text inside say-as elements is what the interpret-as and format attributes say it
+//! is, so a date like "31/02/2024" or a cardinal like "12a" can be caught when parsing rather
+//! than being read out strangely. Dates, times, numbers and amounts of money are parsed into their
+//! fields which are also used to read them out, see `normalise`.
+use crate::elements::*;
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Number pattern allowing thousands separators and a decimal part. Only ASCII digits are
+/// accepted as numbers in other scripts can't be read as them.
+const NUMBER: &str = r"(?:[0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)(?:\.[0-9]+)?";
+
+lazy_static! {
+    static ref CARDINAL_RE: Regex = Regex::new(&format!(r"^[+-]?{}$", NUMBER)).unwrap();
+    static ref ORDINAL_RE: Regex = Regex::new(r"^([0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)(st|nd|rd|th)?$").unwrap();
+    static ref DIGITS_RE: Regex = Regex::new(r"^[0-9][0-9\s]*$").unwrap();
+    static ref TELEPHONE_RE: Regex = Regex::new(r"^\+?[0-9\s().-]*[0-9][0-9\s().-]*$").unwrap();
+    static ref CURRENCY_RE: Regex = Regex::new(&format!(
+        r"^(?P<sign>-)?(?:(?P<before>[A-Z]{{0,3}}\p{{Sc}})\s?|(?P<code_before>[A-Z]{{3}})\s)?(?P<amount>[+-]?{})(?:\s?(?P<after>\p{{Sc}})|\s(?P<code_after>[A-Z]{{3}}))?$",
+        NUMBER
+    ))
+    .unwrap();
+    static ref UNIT_RE: Regex = Regex::new(&format!(r"^[+-]?{}\s*[^0-9].*$", NUMBER)).unwrap();
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// A date read from the text of a say-as element, only the fields in the format are set.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Date {
+    /// The year as written, so two digit years aren't expanded.
+    pub year: Option<u32>,
+    /// Month from 1 to 12.
+    pub month: Option<u8>,
+    /// Day of the month starting from 1.
+    pub day: Option<u8>,
+}
+
+/// A number read from the text of a say-as element.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Number {
+    /// Whether the number has a minus sign.
+    pub negative: bool,
+    /// The whole part of the number with any thousands separators removed.
+    pub integer: u64,
+    /// Digits after the decimal point as written, so leading zeros are kept.
+    pub fraction: Option<String>,
+}
+
+/// An amount of money read from the text of a say-as element.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Currency {
+    /// The currency symbol or ISO 4217 code as written, such as "$", "US$" or "EUR". `None` if
+    /// the amount is just a number.
+    pub currency: Option<String>,
+    /// The amount of money.
+    pub amount: Number,
+}
+
+/// Whether a time on the 12 hour clock is before or after midday.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Meridiem {
+    /// Before midday, "am".
+    Am,
+    /// After midday, "pm".
+    Pm,
+}
+
+/// A time read from the text of a say-as element.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Time {
+    /// The hour as written, 1 to 12 on the 12 hour clock or 0 to 23 on the 24 hour clock.
+    pub hour: u8,
+    /// Minutes if the format includes them.
+    pub minute: Option<u8>,
+    /// Seconds if the format includes them.
+    pub second: Option<u8>,
+    /// Whether a 12 hour time is am or pm, if the text says.
+    pub meridiem: Option<Meridiem>,
+}
+
+fn days_in_month(month: u8, year: Option<u32>) -> u8 {
+    match month {
+        2 => match year {
+            Some(year) if !(year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)) => 28,
+            _ => 29,
+        },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn parse_month(field: &str) -> Option<u8> {
+    if let Ok(month) = field.parse::<u8>() {
+        return Some(month);
+    }
+    let field = field.to_lowercase();
+    let field = field.trim_end_matches('.');
+    MONTHS
+        .iter()
+        .position(|x| *x == field || (field.len() >= 3 && x.starts_with(field)))
+        .map(|x| x as u8 + 1)
+}
+
+/// Reads a date written in the given format. Fields can be separated by any punctuation or
+/// whitespace and months can be written as numbers or English names.
+pub fn parse_date(text: &str, format: DateFormat) -> anyhow::Result<Date> {
+    let fields = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    let order = format.fields();
+    if fields.len() != order.len() {
+        bail!(
+            "'{}' doesn't have the {} fields of a {} date",
+            text,
+            order.len(),
+            format
+        );
+    }
+    let mut date = Date::default();
+    for (field, kind) in fields.iter().zip(order.chars()) {
+        let invalid = || format!("'{}' isn't a valid {} date", text, format);
+        match kind {
+            'd' => {
+                // Allow ordinal suffixes such as "1st"
+                let day = field.trim_end_matches(|c: char| c.is_alphabetic());
+                date.day = Some(day.parse().with_context(invalid)?);
+            }
+            'm' => date.month = Some(parse_month(field).with_context(invalid)?),
+            _ => {
+                if field.len() > 4 {
+                    bail!("{}", invalid());
+                }
+                date.year = Some(field.parse().with_context(invalid)?);
+            }
+        }
+    }
+    if let Some(month) = date.month {
+        if !(1..=12).contains(&month) {
+            bail!("'{}' has month {} which doesn't exist", text, month);
+        }
+    }
+    if let Some(day) = date.day {
+        let days = date
+            .month
+            .map(|x| days_in_month(x, date.year))
+            .unwrap_or(31);
+        if !(1..=days).contains(&day) {
+            bail!("'{}' has day {} which isn't in the month", text, day);
+        }
+    }
+    Ok(date)
+}
+
+/// Reads a time written in the given format. Fields are separated by ':' or '.' and 12 hour
+/// times can end with "am" or "pm".
+pub fn parse_time(text: &str, format: TimeFormat) -> anyhow::Result<Time> {
+    let lower = text.trim().to_lowercase();
+    let mut time = Time::default();
+    let mut clock = lower.as_str();
+    for (suffix, meridiem) in [
+        ("am", Meridiem::Am),
+        ("a.m.", Meridiem::Am),
+        ("pm", Meridiem::Pm),
+        ("p.m.", Meridiem::Pm),
+    ] {
+        if let Some(rest) = lower.strip_suffix(suffix) {
+            if !format.is_12_hour() {
+                bail!("'{}' has {} but is a {} time", text, suffix, format);
+            }
+            time.meridiem = Some(meridiem);
+            clock = rest.trim_end();
+            break;
+        }
+    }
+    let fields = clock.split([':', '.']).collect::<Vec<_>>();
+    if fields.len() != format.field_count() {
+        bail!(
+            "'{}' doesn't have the {} fields of a {} time",
+            text,
+            format.field_count(),
+            format
+        );
+    }
+    let mut values = vec![];
+    for field in fields {
+        if field.is_empty() || field.len() > 2 || !field.chars().all(|c| c.is_ascii_digit()) {
+            bail!("'{}' isn't a valid {} time", text, format);
+        }
+        values.push(field.parse::<u8>()?);
+    }
+    time.hour = values[0];
+    time.minute = values.get(1).copied();
+    time.second = values.get(2).copied();
+
+    let hours = if format.is_12_hour() { 1..=12 } else { 0..=23 };
+    if !hours.contains(&time.hour) {
+        bail!("'{}' has hour {} which isn't on the clock", text, time.hour);
+    }
+    if time.minute.into_iter().chain(time.second).any(|x| x > 59) {
+        bail!("'{}' has minutes or seconds over 59", text);
+    }
+    Ok(time)
+}
+
+/// Reads a number which can have a sign, thousands separators and a decimal part.
+pub fn parse_number(text: &str) -> anyhow::Result<Number> {
+    let text = text.trim();
+    if !CARDINAL_RE.is_match(text) {
+        bail!("'{}' isn't a cardinal number", text);
+    }
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.trim_start_matches('+')),
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction.to_string())),
+        None => (unsigned, None),
+    };
+    let integer = integer
+        .replace(',', "")
+        .parse()
+        .with_context(|| format!("'{}' is too large", text))?;
+    Ok(Number {
+        negative,
+        integer,
+        fraction,
+    })
+}
+
+/// Reads an ordinal number written either as a plain number or with its English suffix, "21st".
+pub fn parse_ordinal(text: &str) -> anyhow::Result<u64> {
+    let text = text.trim();
+    let caps = ORDINAL_RE
+        .captures(text)
+        .with_context(|| format!("'{}' isn't an ordinal number", text))?;
+    if let Some(suffix) = caps.get(2) {
+        if suffix.as_str() != ordinal_suffix(&caps[1]) {
+            bail!("'{}' has the wrong ordinal suffix", text);
+        }
+    }
+    caps[1]
+        .replace(',', "")
+        .parse()
+        .with_context(|| format!("'{}' is too large", text))
+}
+
+/// Reads an amount of money with an optional currency symbol or code before or after it. A minus
+/// sign can be written before the symbol or the number.
+pub fn parse_currency(text: &str) -> anyhow::Result<Currency> {
+    let text = text.trim();
+    let caps = CURRENCY_RE
+        .captures(text)
+        .with_context(|| format!("'{}' isn't a currency amount", text))?;
+    let currency = ["before", "code_before", "after", "code_after"]
+        .iter()
+        .find_map(|x| caps.name(x))
+        .map(|x| x.as_str().to_string());
+    let mut amount = parse_number(&caps["amount"])?;
+    if caps.name("sign").is_some() {
+        if amount.negative || caps["amount"].starts_with('+') {
+            bail!("'{}' has two signs", text);
+        }
+        amount.negative = true;
+    }
+    Ok(Currency { currency, amount })
+}
+
+/// The suffix written after an ordinal number, "st" for 1, "nd" for 2 and so on.
+fn ordinal_suffix(number: &str) -> &'static str {
+    let last_two = &number[number.len().saturating_sub(2)..];
+    match last_two.parse::<u8>().unwrap_or_default() {
+        11..=13 => "th",
+        n if n % 10 == 1 => "st",
+        n if n % 10 == 2 => "nd",
+        n if n % 10 == 3 => "rd",
+        _ => "th",
+    }
+}
+
+impl SayAsAttributes {
+    /// Checks the text of the element is written the way the interpret-as and format attributes
+    /// say. Dates and times without a format can be in any of the formats. Content types other
+    /// than those in `InterpretAs` and characters, which can be any text, aren't checked.
+    pub fn check_text(&self, text: &str) -> anyhow::Result<()> {
+        let text = text.trim();
+        let matches = |re: &Regex, kind: &str| {
+            if re.is_match(text) {
+                Ok(())
+            } else {
+                bail!("'{}' isn't a {}", text, kind)
+            }
+        };
+        match self.interpret_as {
+            InterpretAs::Date => self.date(text).map(|_| ()),
+            InterpretAs::Time => self.time(text).map(|_| ()),
+            InterpretAs::Cardinal => parse_number(text).map(|_| ()),
+            InterpretAs::Ordinal => parse_ordinal(text).map(|_| ()),
+            InterpretAs::Digits => matches(&DIGITS_RE, "sequence of digits"),
+            InterpretAs::Telephone => matches(&TELEPHONE_RE, "telephone number"),
+            InterpretAs::Currency => parse_currency(text).map(|_| ()),
+            InterpretAs::Unit => matches(&UNIT_RE, "measurement with a unit"),
+            InterpretAs::Characters | InterpretAs::Other(_) => Ok(()),
+        }
+    }
+
+    /// Reads the text as a date in the element's format, or the first of month-day-year,
+    /// day-month-year and year-month-day it can be read as if there's no format.
+    pub fn date(&self, text: &str) -> anyhow::Result<Date> {
+        let text = text.trim();
+        match &self.format {
+            Some(SayAsFormat::Date(format)) => parse_date(text, *format),
+            _ => [DateFormat::Mdy, DateFormat::Dmy, DateFormat::Ymd]
+                .iter()
+                .find_map(|x| parse_date(text, *x).ok())
+                .with_context(|| format!("'{}' isn't a date", text)),
+        }
+    }
+
+    /// Reads the text as a time in the element's format, or any of the time formats if there's
+    /// no format.
+    pub fn time(&self, text: &str) -> anyhow::Result<Time> {
+        let text = text.trim();
+        match &self.format {
+            Some(SayAsFormat::Time(format)) => parse_time(text, *format),
+            _ => [
+                TimeFormat::Hms12,
+                TimeFormat::Hms24,
+                TimeFormat::Hm12,
+                TimeFormat::Hm24,
+                TimeFormat::H12,
+                TimeFormat::H24,
+            ]
+            .iter()
+            .find_map(|x| parse_time(text, *x).ok())
+            .with_context(|| format!("'{}' isn't a time", text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn say_as(interpret_as: &str, format: Option<&str>) -> SayAsAttributes {
+        let interpret_as = interpret_as.parse().unwrap();
+        let format = format.map(|x| SayAsFormat::parse(&interpret_as, x).unwrap());
+        SayAsAttributes {
+            interpret_as,
+            format,
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            parse_date("31/12/2024", DateFormat::Dmy).unwrap(),
+            Date {
+                year: Some(2024),
+                month: Some(12),
+                day: Some(31),
+            }
+        );
+        assert_eq!(
+            parse_date("Feb 29th", DateFormat::Md).unwrap(),
+            Date {
+                year: None,
+                month: Some(2),
+                day: Some(29),
+            }
+        );
+        assert_eq!(parse_date("2024-3", DateFormat::Ym).unwrap().month, Some(3));
+
+        assert!(parse_date("31/02/2024", DateFormat::Dmy).is_err());
+        assert!(parse_date("29/02/2023", DateFormat::Dmy).is_err());
+        assert!(parse_date("29/02/2000", DateFormat::Dmy).is_ok());
+        assert!(parse_date("12/31/2024", DateFormat::Dmy).is_err());
+        assert!(parse_date("12/31/2024", DateFormat::Mdy).is_ok());
+        assert!(parse_date("31/12", DateFormat::Dmy).is_err());
+        assert!(parse_date("Smarch 3", DateFormat::Md).is_err());
+
+        let date = say_as("date", Some("dmy"));
+        assert!(date.check_text("31/02/2024").is_err());
+        assert!(date.check_text(" 28/02/2024 ").is_ok());
+        assert!(say_as("date", None).check_text("2024-02-28").is_ok());
+        assert!(say_as("date", None).check_text("tomorrow").is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(
+            parse_time("9:05pm", TimeFormat::Hm12).unwrap(),
+            Time {
+                hour: 9,
+                minute: Some(5),
+                second: None,
+                meridiem: Some(Meridiem::Pm),
+            }
+        );
+        assert_eq!(
+            parse_time("23:59:59", TimeFormat::Hms24).unwrap().second,
+            Some(59)
+        );
+        assert!(parse_time("13:00", TimeFormat::Hm12).is_err());
+        assert!(parse_time("24:00", TimeFormat::Hm24).is_err());
+        assert!(parse_time("10:60", TimeFormat::Hm24).is_err());
+        assert!(parse_time("10:00 pm", TimeFormat::Hm24).is_err());
+        assert!(parse_time("10:00", TimeFormat::Hms24).is_err());
+
+        assert!(say_as("time", Some("hms24")).check_text("12:30").is_err());
+        assert!(say_as("time", None).check_text("12:30").is_ok());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            parse_number("-1,234.05").unwrap(),
+            Number {
+                negative: true,
+                integer: 1234,
+                fraction: Some("05".to_string()),
+            }
+        );
+        assert_eq!(parse_number("+7").unwrap().integer, 7);
+        assert!(parse_number("99999999999999999999").is_err());
+        assert_eq!(parse_ordinal("1,001st").unwrap(), 1001);
+        assert!(parse_ordinal("2th").is_err());
+
+        // Digits from other scripts aren't numbers, and can't be sliced as ASCII
+        let err = parse_number("٣٤").unwrap_err().to_string();
+        assert!(err.contains("isn't a cardinal number"), "{}", err);
+        assert!(parse_ordinal("٣1st").is_err());
+        let ssml = r#"<speak><say-as interpret-as="ordinal">٣1st</say-as></speak>"#;
+        let strict = crate::parser::SsmlParserBuilder::default()
+            .validate_say_as(true)
+            .build()
+            .unwrap();
+        assert!(strict.parse(ssml).is_err());
+        let expanded = crate::parse_ssml(ssml)
+            .unwrap()
+            .expand_say_as(&crate::normalise::English);
+        assert_eq!(expanded.unexpanded.len(), 1);
+
+        let currency = parse_currency("US$ 5.99").unwrap();
+        assert_eq!(currency.currency.as_deref(), Some("US$"));
+        assert_eq!(currency.amount.integer, 5);
+        assert_eq!(
+            parse_currency("12 EUR").unwrap().currency.as_deref(),
+            Some("EUR")
+        );
+        assert_eq!(parse_currency("12").unwrap().currency, None);
+    }
+
+    #[test]
+    fn other_content_types() {
+        let valid = [
+            ("cardinal", "-1,234.5"),
+            ("cardinal", "42"),
+            ("ordinal", "3"),
+            ("ordinal", "21st"),
+            ("ordinal", "112th"),
+            ("digits", "0123 456"),
+            ("telephone", "+44 (0)20 7946-0958"),
+            ("currency", "$45.30"),
+            ("currency", "12.50 €"),
+            ("currency", "EUR 12"),
+            ("currency", "US$5"),
+            ("unit", "10kg"),
+            ("unit", "5.5 km/h"),
+            ("characters", "anything at all"),
+            ("expletive", "darn"),
+        ];
+        for (interpret_as, text) in valid {
+            assert!(
+                say_as(interpret_as, None).check_text(text).is_ok(),
+                "{} {}",
+                interpret_as,
+                text
+            );
+        }
+        let invalid = [
+            ("cardinal", "12a"),
+            ("cardinal", "1,23"),
+            ("ordinal", "1th"),
+            ("ordinal", "12nd"),
+            ("digits", "12a"),
+            ("telephone", "call me"),
+            ("currency", "lots"),
+            ("unit", "kg"),
+        ];
+        for (interpret_as, text) in invalid {
+            assert!(
+                say_as(interpret_as, None).check_text(text).is_err(),
+                "{} {}",
+                interpret_as,
+                text
+            );
+        }
+    }
+}
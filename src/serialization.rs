@@ -0,0 +1,380 @@
+//! Serde support for the parsed document. Attribute values which have a textual form in SSML,
+//! such as times, pitches and keywords like "x-loud", are serialised as the string they'd have in
+//! an SSML document so the JSON representation reads the same as the markup. Everything else uses
+//! the derived implementations, see the README for the layout of a serialised `Ssml`.
+use crate::dialects::amazon::{
+    BreathDuration, BreathFrequency, BreathVolume, Domain, Effect, Phonation, VocalTractLength,
+};
+use crate::dialects::google::{MediaTime, SyncEvent};
+#[cfg(feature = "azure")]
+use crate::dialects::microsoft::{Role, SilenceType, VisemeType};
+use crate::elements::*;
+use crate::language_tag::{LanguageRange, LanguageTag};
+use crate::{ParserLogEvent, Ssml};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Display;
+use std::str::FromStr;
+
+fn serialize_str<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+fn deserialize_str<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+macro_rules! string_serde {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serialize_str(self, serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserialize_str(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+string_serde!(
+    SsmlElement,
+    OnLanguageFailure,
+    TimeDesignation,
+    PhonemeAlphabet,
+    InterpretAs,
+    DateFormat,
+    TimeFormat,
+    Strength,
+    PitchStrength,
+    VolumeStrength,
+    RateStrength,
+    PitchRange,
+    VolumeRange,
+    RateRange,
+    ContourElement,
+    PitchContour,
+    PositiveNumber,
+    EmphasisLevel,
+    Gender,
+    LanguageAccentPair,
+    LanguageTag,
+    LanguageRange,
+    VoiceFeature,
+    OnVoiceFailure,
+    FetchHint,
+    BreathDuration,
+    BreathVolume,
+    BreathFrequency,
+    Domain,
+    Effect,
+    Phonation,
+    VocalTractLength,
+    MediaTime,
+    SyncEvent,
+);
+
+#[cfg(feature = "azure")]
+string_serde!(Role, SilenceType, VisemeType);
+
+// A say-as format is only known to be a date or time format from the interpret-as value next to
+// it, so it's written as a string and parsed along with the rest of the attributes.
+impl Serialize for SayAsFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_str(self, serializer)
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SayAsAttributesDef {
+    interpret_as: InterpretAs,
+    format: Option<String>,
+    detail: Option<String>,
+}
+
+impl TryFrom<SayAsAttributesDef> for SayAsAttributes {
+    type Error = anyhow::Error;
+
+    fn try_from(def: SayAsAttributesDef) -> anyhow::Result<Self> {
+        let format = def
+            .format
+            .map(|format| SayAsFormat::parse(&def.interpret_as, &format))
+            .transpose()?;
+        Ok(Self {
+            interpret_as: def.interpret_as,
+            format,
+            detail: def.detail,
+        })
+    }
+}
+
+/// Serialises an `http::Uri` as a string.
+pub(crate) mod uri {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        uri: &http::Uri,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_str(uri, serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<http::Uri, D::Error> {
+        deserialize_str(deserializer)
+    }
+}
+
+/// Serialises an optional `http::Uri` as a string or null.
+pub(crate) mod option_uri {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        uri: &Option<http::Uri>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match uri {
+            Some(uri) => serializer.serialize_some(&uri.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<http::Uri>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|uri| uri.parse().map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// The serialised form of a document, the element tree is rebuilt from the event log when it's
+/// deserialised. Warnings from a lenient parse describe the source document rather than the parsed
+/// one so they aren't kept.
+#[derive(Serialize, Deserialize)]
+struct SerializedSsml {
+    text: String,
+    events: Vec<ParserLogEvent>,
+}
+
+impl Serialize for Ssml {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedSsml {
+            text: self.text.clone(),
+            events: self.event_log.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ssml {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let SerializedSsml { text, events } = SerializedSsml::deserialize(deserializer)?;
+        check_events(&text, &events).map_err(de::Error::custom)?;
+        Ok(Ssml::new(text, events))
+    }
+}
+
+/// Checks the event log describes the text, the text events have to cover every byte of the text
+/// in order, every close has to match the innermost open element and every open element has to
+/// be closed.
+fn check_events(text: &str, events: &[ParserLogEvent]) -> Result<(), String> {
+    let mut byte = 0;
+    let mut open: Vec<SsmlElement> = vec![];
+    for event in events {
+        match event {
+            ParserLogEvent::Text((start, end)) => {
+                if *start != byte || end < start || !text.is_char_boundary(*end) {
+                    return Err(format!(
+                        "text event {}..{} doesn't follow on from byte {}",
+                        start, end, byte
+                    ));
+                }
+                byte = *end;
+            }
+            ParserLogEvent::Open(element) => open.push(element.into()),
+            ParserLogEvent::Close(element) => {
+                let element = SsmlElement::from(element);
+                match open.pop() {
+                    Some(x) if x == element => {}
+                    Some(x) => {
+                        return Err(format!("close event for {} while {} is open", element, x))
+                    }
+                    None => return Err(format!("close event for {} without an open", element)),
+                }
+            }
+            ParserLogEvent::Empty(_) => {}
+        }
+    }
+    if byte != text.len() {
+        return Err(format!(
+            "text events end at byte {} but the text is {} bytes long",
+            byte,
+            text.len()
+        ));
+    }
+    if let Some(element) = open.last() {
+        return Err(format!("{} is never closed", element));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SsmlParserBuilder;
+
+    #[test]
+    fn document_round_trip() {
+        let input = r#"<speak version="1.1" xml:lang="en-GB">
+            <p><s>The time is <say-as interpret-as="time" format="hms24">10:30</say-as>.</s>
+            <s><prosody rate="120%" pitch="+2st" volume="-6dB" contour="(0%,+20Hz) (50%,x-high)">Quick</prosody>
+            <break time="1.5s"/><break strength="x-weak"/></s></p>
+            <voice gender="female" languages="en-GB fr-FR:fr-CA" name="Alice">Bonjour</voice>
+            <audio src="https://example.com/beep.wav" clipBegin="10ms">beep</audio>
+            <lexicon uri="https://example.com/lexicon.pls" xml:id="lex" type="application/pls+xml"/>
+            <amazon:breath duration="x-long"/><custom attr="value">Café ☕</custom>
+            </speak>"#;
+        let ssml = SsmlParserBuilder::default()
+            .dialect(crate::dialects::Dialect::Amazon)
+            .build()
+            .unwrap()
+            .parse(input)
+            .unwrap();
+
+        let json = serde_json::to_string(&ssml).unwrap();
+        let decoded: Ssml = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded.get_text(), ssml.get_text());
+        assert_eq!(
+            decoded.tags().collect::<Vec<_>>(),
+            ssml.tags().collect::<Vec<_>>()
+        );
+        assert_eq!(decoded.write_ssml(), ssml.write_ssml());
+    }
+
+    #[test]
+    fn json_layout() {
+        let ssml = crate::parse_ssml(
+            r#"<speak version="1.1"><prosody rate="slow"><break time="200ms"/>Hi</prosody></speak>"#,
+        )
+        .unwrap();
+        let json = serde_json::to_value(&ssml).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "text": "Hi",
+                "events": [
+                    {"type": "open", "value": {"element": "speak", "attributes": {
+                        "lang": null,
+                        "base": null,
+                        "on_lang_failure": null,
+                        "version": "1.1",
+                        "start_mark": null,
+                        "end_mark": null,
+                        "xml_root_attrs": {}
+                    }}},
+                    {"type": "open", "value": {"element": "prosody", "attributes": {
+                        "pitch": null,
+                        "contour": null,
+                        "range": null,
+                        "rate": "slow",
+                        "duration": null,
+                        "volume": null
+                    }}},
+                    {"type": "empty", "value": {"element": "break", "attributes": {
+                        "strength": null,
+                        "time": "200ms"
+                    }}},
+                    {"type": "text", "value": [0, 2]},
+                    {"type": "close", "value": {"element": "prosody", "attributes": {
+                        "pitch": null,
+                        "contour": null,
+                        "range": null,
+                        "rate": "slow",
+                        "duration": null,
+                        "volume": null
+                    }}},
+                    {"type": "close", "value": {"element": "speak", "attributes": {
+                        "lang": null,
+                        "base": null,
+                        "on_lang_failure": null,
+                        "version": "1.1",
+                        "start_mark": null,
+                        "end_mark": null,
+                        "xml_root_attrs": {}
+                    }}}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_event_logs() {
+        let invalid = [
+            r#"{"text": "Hello", "events": [{"type": "text", "value": [0, 3]}]}"#,
+            r#"{"text": "Hello", "events": [{"type": "text", "value": [1, 5]}]}"#,
+            r#"{"text": "Café", "events": [{"type": "text", "value": [0, 4]}, {"type": "text", "value": [4, 5]}]}"#,
+            r#"{"text": "", "events": [{"type": "close", "value": {"element": "p"}}]}"#,
+            r#"{"text": "", "events": [{"type": "open", "value": {"element": "p", "attributes": {}}}, {"type": "close", "value": {"element": "s", "attributes": {}}}]}"#,
+            r#"{"text": "", "events": [{"type": "open", "value": {"element": "p", "attributes": {}}}]}"#,
+        ];
+        for json in invalid {
+            assert!(serde_json::from_str::<Ssml>(json).is_err(), "{}", json);
+        }
+        let valid = r#"{"text": "Café", "events": [
+            {"type": "open", "value": {"element": "p", "attributes": {"lang": "fr"}}},
+            {"type": "text", "value": [0, 5]},
+            {"type": "close", "value": {"element": "p", "attributes": {}}}
+        ]}"#;
+        let ssml = serde_json::from_str::<Ssml>(valid).unwrap();
+        assert_eq!(ssml.get_text(), "Café");
+        assert_eq!(
+            ssml.tags().next().unwrap().element,
+            ParsedElement::Paragraph(TextStructureAttributes {
+                lang: Some("fr".parse().unwrap()),
+                on_lang_failure: None,
+            })
+        );
+    }
+
+    #[test]
+    fn say_as_formats() {
+        let attrs = SayAsAttributes {
+            interpret_as: InterpretAs::Date,
+            format: Some(SayAsFormat::Date(DateFormat::Dmy)),
+            detail: None,
+        };
+        let json = serde_json::to_value(&attrs).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"interpret_as": "date", "format": "dmy", "detail": null})
+        );
+        assert_eq!(
+            serde_json::from_value::<SayAsAttributes>(json).unwrap(),
+            attrs
+        );
+
+        let telephone = serde_json::json!({"interpret_as": "telephone", "format": "39"});
+        assert_eq!(
+            serde_json::from_value::<SayAsAttributes>(telephone)
+                .unwrap()
+                .format,
+            Some(SayAsFormat::Other("39".to_string()))
+        );
+        let invalid = serde_json::json!({"interpret_as": "date", "format": "dd/mm"});
+        assert!(serde_json::from_value::<SayAsAttributes>(invalid).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Maps offsets in the text extracted from a document back to where that text came from in the
+//! source SSML and the other way round. The parser collapses whitespace, resolves entities and
+//! can insert text of its own, like the space added before a sentence or the alias of an expanded
+//! `<sub>`, so the text is recorded as a list of segments each covering a piece of the text and
+//! the part of the source it was produced from.
+use crate::error::Position;
+use crate::offset::OffsetUnit;
+use crate::Ssml;
+use std::ops::Range;
+
+/// Where each piece of a document's text came from in the source SSML. Offsets are in bytes,
+/// `Ssml::source_range` and `Ssml::text_range` take offsets into the text in other units.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    /// The SSML the document was parsed from.
+    source: String,
+    /// Segments covering all of the text in order, their source ranges are in order too.
+    segments: Vec<Segment>,
+}
+
+/// A piece of the text and the part of the source it was produced from.
+#[derive(Clone, Debug, PartialEq)]
+struct Segment {
+    /// Byte range in the text.
+    text: Range<usize>,
+    /// Byte range in the source.
+    source: Range<usize>,
+    /// Whether the text was copied from the source unchanged, if so offsets within the segment
+    /// map one to one. Otherwise the segment can only be mapped as a whole.
+    exact: bool,
+}
+
+impl SourceMap {
+    pub(crate) fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            segments: vec![],
+        }
+    }
+
+    /// Removes all the segments, used when text before the `<speak>` element is thrown away.
+    pub(crate) fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    /// Records that `text` was produced from `source`, where neither can be mapped any more
+    /// precisely than as a whole. Used for entities and text the parser inserts.
+    pub(crate) fn push(&mut self, text: Range<usize>, source: Range<usize>) {
+        self.push_segment(Segment {
+            text,
+            source,
+            exact: false,
+        });
+    }
+
+    /// Records where text copied from the source ended up. The text has had its whitespace
+    /// collapsed so it's matched up with the source a word at a time, whitespace in the text maps
+    /// to the whitespace between those words in the source.
+    pub(crate) fn push_text(&mut self, text: &str, text_start: usize, source: Range<usize>) {
+        let raw = &self.source[source.clone()];
+        let mut segments = vec![];
+        let mut consumed = 0;
+        let mut cursor = 0;
+        while consumed < text.len() {
+            let rest = &text[consumed..];
+            let word_start = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            let word_len = rest[word_start..]
+                .find(char::is_whitespace)
+                .unwrap_or(rest.len() - word_start);
+            let word = &rest[word_start..(word_start + word_len)];
+            let Some(found) = raw[cursor..].find(word).filter(|_| !word.is_empty()) else {
+                // Whatever's left can only be mapped as a whole
+                segments.push(Segment {
+                    text: (text_start + consumed)..(text_start + text.len()),
+                    source: (source.start + cursor)..source.end,
+                    exact: false,
+                });
+                break;
+            };
+            let word_text = text_start + consumed + word_start;
+            let word_source = source.start + cursor + found;
+            segments.push(Segment {
+                text: (text_start + consumed)..word_text,
+                source: (source.start + cursor)..word_source,
+                exact: false,
+            });
+            segments.push(Segment {
+                text: word_text..(word_text + word_len),
+                source: word_source..(word_source + word_len),
+                exact: true,
+            });
+            consumed += word_start + word_len;
+            cursor += found + word_len;
+        }
+        for segment in segments {
+            self.push_segment(segment);
+        }
+    }
+
+    fn push_segment(&mut self, segment: Segment) {
+        if segment.text.is_empty() {
+            return;
+        }
+        if let Some(last) = self.segments.last_mut() {
+            if last.exact
+                && segment.exact
+                && last.text.end == segment.text.start
+                && last.source.end == segment.source.start
+            {
+                last.text.end = segment.text.end;
+                last.source.end = segment.source.end;
+                return;
+            }
+        }
+        self.segments.push(segment);
+    }
+
+    /// The SSML the document was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The byte range in the source which produced a byte range of the text. Text the parser
+    /// inserted maps to the element which caused it to be inserted.
+    pub fn to_source(&self, text: Range<usize>) -> Range<usize> {
+        // Segments are in order in both the text and the source so they can be binary searched
+        let index = self.segments.partition_point(|x| x.text.end <= text.start);
+        let start = match self.segments.get(index) {
+            Some(x) if x.exact => x.source.start + text.start.saturating_sub(x.text.start),
+            Some(x) => x.source.start,
+            None => self.segments.last().map(|x| x.source.end).unwrap_or(0),
+        };
+        if text.end <= text.start {
+            return start..start;
+        }
+        let index = self.segments.partition_point(|x| x.text.end < text.end);
+        let end = match self.segments.get(index) {
+            Some(x) if x.exact => x.source.start + text.end.saturating_sub(x.text.start),
+            Some(x) => x.source.end,
+            None => self.segments.last().map(|x| x.source.end).unwrap_or(0),
+        };
+        start..end.max(start)
+    }
+
+    /// The byte range in the text produced from a byte range of the source. Markup which didn't
+    /// produce any text maps to an empty range where the text following it starts.
+    pub fn to_text(&self, source: Range<usize>) -> Range<usize> {
+        let text_end = self.segments.last().map(|x| x.text.end).unwrap_or(0);
+        let index = self
+            .segments
+            .partition_point(|x| x.source.end <= source.start);
+        let start = match self.segments.get(index) {
+            Some(x) if x.exact && source.start > x.source.start => {
+                x.text.start + source.start - x.source.start
+            }
+            Some(x) => x.text.start,
+            None => text_end,
+        };
+        let index = self
+            .segments
+            .partition_point(|x| x.source.start < source.end);
+        let end = match index.checked_sub(1).map(|i| &self.segments[i]) {
+            Some(x) if x.exact && source.end < x.source.end => {
+                x.text.start + source.end - x.source.start
+            }
+            Some(x) => x.text.end,
+            None => 0,
+        };
+        start..end.max(start)
+    }
+
+    /// The line and column of a byte offset in the source.
+    pub fn position(&self, source: usize) -> Position {
+        Position::from_byte_offset(&self.source, source)
+    }
+}
+
+impl Ssml {
+    /// Where the text came from in the source SSML, this is `None` for documents which weren't
+    /// parsed such as ones created with `SsmlBuilder`.
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+
+    /// The byte range in the source SSML which produced a range of the text, measured in the
+    /// given unit. Returns `None` if the document wasn't parsed.
+    pub fn source_range(&self, text: Range<usize>, unit: OffsetUnit) -> Option<Range<usize>> {
+        let source_map = self.source_map()?;
+        let start = unit.to_byte_offset(&self.text, text.start);
+        let end = unit.to_byte_offset(&self.text, text.end);
+        Some(source_map.to_source(start..end))
+    }
+
+    /// The range of the text, measured in the given unit, produced from a byte range of the
+    /// source SSML. A range starting or ending inside a multi-byte character of the text takes in
+    /// the whole character. Returns `None` if the document wasn't parsed.
+    pub fn text_range(&self, source: Range<usize>, unit: OffsetUnit) -> Option<Range<usize>> {
+        let bytes = self.source_map()?.to_text(source);
+        let start = OffsetUnit::Bytes.to_byte_offset(&self.text, bytes.start);
+        let mut end = match bytes.is_empty() {
+            true => start,
+            false => bytes.end.min(self.text.len()),
+        };
+        while !self.text.is_char_boundary(end) {
+            end += 1;
+        }
+        Some(unit.from_byte_offset(&self.text, start)..unit.from_byte_offset(&self.text, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::char_to_byte_range;
+    use crate::parser::SsmlParserBuilder;
+    use crate::{parse_ssml, ParsedElement};
+
+    #[test]
+    fn text_to_source() {
+        let source = "<speak>\n  Hello   <emphasis>wörld</emphasis>\n  and\tgood&amp;bye<s>End</s>\n</speak>";
+        let ssml = parse_ssml(source).unwrap();
+        assert_eq!(ssml.get_text(), " Hello wörld and\tgood&bye End ");
+
+        let word_in_source = |word: &str| {
+            let start = ssml.get_text().find(word).unwrap();
+            let range = ssml
+                .source_range(start..(start + word.len()), OffsetUnit::Bytes)
+                .unwrap();
+            &source[range]
+        };
+        assert_eq!(word_in_source("Hello"), "Hello");
+        assert_eq!(word_in_source("wörld"), "wörld");
+        assert_eq!(word_in_source("Hello wörld"), "Hello   <emphasis>wörld");
+        assert_eq!(word_in_source(" Hello"), "\n  Hello");
+        assert_eq!(word_in_source("and\tgood"), "and\tgood");
+        assert_eq!(word_in_source("good&bye"), "good&amp;bye");
+        assert_eq!(word_in_source("&"), "&amp;");
+        // The space inserted before the sentence points at the sentence
+        assert_eq!(word_in_source("bye End"), "bye<s>End");
+
+        // Character offsets, "l" in "wörld"
+        let range = ssml.source_range(10..11, OffsetUnit::Chars).unwrap();
+        assert_eq!(&source[range.clone()], "l");
+        let position = ssml.source_map().unwrap().position(range.start);
+        assert_eq!((position.line, position.column), (2, 24));
+    }
+
+    #[test]
+    fn source_to_text() {
+        let source = "<speak>Hi <break time=\"1s\"/>  thére &lt;3 <sub alias=\"Doctor\">Dr</sub> Who</speak>";
+        let ssml = SsmlParserBuilder::default()
+            .expand_sub(true)
+            .build()
+            .unwrap()
+            .parse(source)
+            .unwrap();
+        assert_eq!(ssml.get_text(), "Hi thére <3  Doctor Who");
+
+        let text_of = |part: &str| {
+            let start = source.find(part).unwrap();
+            let range = ssml
+                .text_range(start..(start + part.len()), OffsetUnit::Chars)
+                .unwrap();
+            let text = ssml.get_text();
+            text[char_to_byte_range(text, range)].to_string()
+        };
+        assert_eq!(text_of("thére"), "thére");
+        assert_eq!(text_of("hér"), "hér");
+        assert_eq!(text_of("&lt;"), "<");
+        assert_eq!(text_of("&l"), "<");
+        assert_eq!(text_of("<break time=\"1s\"/>"), "");
+        assert_eq!(text_of("<sub alias=\"Doctor\">"), " Doctor ");
+        assert_eq!(text_of("Hi <break time=\"1s\"/>  thé"), "Hi thé");
+
+        // Spans can be found in the source through the text
+        let brk = ssml
+            .tags()
+            .find(|x| matches!(x.element, ParsedElement::Break(_)))
+            .unwrap();
+        let range = ssml
+            .source_range(brk.start..brk.end, OffsetUnit::Chars)
+            .unwrap();
+        assert!(source[range.end..].starts_with("thére"));
+
+        // Ranges inside a character of the text take in the whole character
+        let ssml = parse_ssml("<speak>héllo</speak>").unwrap();
+        assert_eq!(ssml.text_range(9..10, OffsetUnit::Chars), Some(1..2));
+        assert_eq!(ssml.text_range(9..9, OffsetUnit::Chars), Some(1..1));
+    }
+
+    #[test]
+    fn text_before_speak() {
+        let source = "<?xml version=\"1.0\"?>ignored<speak>Kept</speak>";
+        let ssml = parse_ssml(source).unwrap();
+        let range = ssml.source_range(0..4, OffsetUnit::Bytes).unwrap();
+        assert_eq!(&source[range], "Kept");
+        assert!(crate::SsmlBuilder::speak()
+            .text("Hi")
+            .build()
+            .unwrap()
+            .source_map()
+            .is_none());
+    }
+}
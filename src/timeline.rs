@@ -0,0 +1,549 @@
+//! Estimates how long a document takes to say and when each part of it is reached, without
+//! synthesising it. Text takes time from a speaking rate for its language, adjusted by the
+//! prosody elements around it, and breaks and audio take the time their attributes give. These
+//! are estimates, real voices vary their pace and the pauses they put between sentences.
+use crate::elements::*;
+use crate::language_tag::{LanguageRange, LanguageTag};
+use crate::marks::mark_name;
+use crate::prosody::ProsodyBaseline;
+use crate::tree::Node;
+use crate::{OffsetUnit, Ssml};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Duration;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How quickly text in a language is spoken at the voice's default rate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpeakingRate {
+    /// Words per minute, for languages which put spaces between words.
+    WordsPerMinute(f32),
+    /// Characters per second not counting whitespace or punctuation, for languages such as
+    /// Chinese and Japanese which don't separate words.
+    CharactersPerSecond(f32),
+}
+
+impl SpeakingRate {
+    /// How long the text takes to say at this rate.
+    pub fn duration(&self, text: &str) -> Duration {
+        let seconds = match self {
+            Self::WordsPerMinute(rate) => text.unicode_words().count() as f32 * 60.0 / rate,
+            Self::CharactersPerSecond(rate) => {
+                text.chars().filter(|c| c.is_alphanumeric()).count() as f32 / rate
+            }
+        };
+        Duration::try_from_secs_f32(seconds).unwrap_or_default()
+    }
+}
+
+/// Settings used to estimate the timing of a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeechTiming {
+    /// Rate for text in a language without a rate of its own, or with no language.
+    pub default_rate: SpeakingRate,
+    /// Rates for languages, the first range matching the text's language is used.
+    pub language_rates: Vec<(LanguageRange, SpeakingRate)>,
+    /// Break strengths "none", "x-weak", "weak", "medium", "strong" and "x-strong" as pauses. A
+    /// break without a time or strength is medium.
+    pub break_strengths: [Duration; 6],
+    /// Used to resolve the rate of prosody elements.
+    pub prosody: ProsodyBaseline,
+    /// Lengths of audio files by the src of the audio element, used when the element doesn't set
+    /// clipEnd or repeatDur. Audio of unknown length is timed by its fallback text instead.
+    pub audio_lengths: HashMap<String, Duration>,
+}
+
+impl Default for SpeechTiming {
+    fn default() -> Self {
+        Self {
+            default_rate: SpeakingRate::WordsPerMinute(150.0),
+            language_rates: vec![
+                (
+                    "zh".parse().unwrap(),
+                    SpeakingRate::CharactersPerSecond(4.5),
+                ),
+                (
+                    "ja".parse().unwrap(),
+                    SpeakingRate::CharactersPerSecond(7.0),
+                ),
+                (
+                    "th".parse().unwrap(),
+                    SpeakingRate::CharactersPerSecond(5.0),
+                ),
+            ],
+            break_strengths: [0, 100, 250, 500, 750, 1000].map(Duration::from_millis),
+            prosody: ProsodyBaseline::default(),
+            audio_lengths: HashMap::new(),
+        }
+    }
+}
+
+impl SpeechTiming {
+    /// The speaking rate for text in the language.
+    pub fn rate(&self, lang: Option<&LanguageTag>) -> SpeakingRate {
+        lang.and_then(|lang| self.language_rates.iter().find(|(x, _)| x.matches(lang)))
+            .map(|(_, rate)| *rate)
+            .unwrap_or(self.default_rate)
+    }
+
+    /// How long a break lasts, the time is used if it's given otherwise the strength.
+    pub fn break_duration(&self, attrs: &BreakAttributes) -> Duration {
+        if let Some(time) = &attrs.time {
+            return time.duration();
+        }
+        let index = match attrs.strength.unwrap_or(Strength::Medium) {
+            Strength::No => 0,
+            Strength::ExtraWeak => 1,
+            Strength::Weak => 2,
+            Strength::Medium => 3,
+            Strength::Strong => 4,
+            Strength::ExtraStrong => 5,
+        };
+        self.break_strengths[index]
+    }
+
+    /// How long an audio element plays for, `None` if the length of the audio isn't known.
+    pub fn audio_duration(&self, attrs: &AudioAttributes) -> Option<Duration> {
+        if let Some(repeat) = &attrs.repeat_dur {
+            return Some(repeat.duration());
+        }
+        let end = match &attrs.clip_end {
+            Some(end) => end.duration(),
+            None => *self.audio_lengths.get(&attrs.src.as_ref()?.to_string())?,
+        };
+        let clip = end.saturating_sub(attrs.clip_begin.duration());
+        let speed = if attrs.speed > 0.0 { attrs.speed } else { 1.0 };
+        Some(clip.div_f32(speed) * attrs.repeat_count.get() as u32)
+    }
+}
+
+/// What a timeline entry is.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimelineKind<'a> {
+    /// A sentence, the closest `<s>` element or a sentence found from unicode sentence
+    /// boundaries outside of them.
+    Sentence,
+    /// A `<mark/>` or Azure `<bookmark/>` with its name.
+    Mark(&'a str),
+    /// A `<break/>`.
+    Break(&'a BreakAttributes),
+    /// An `<audio>` element.
+    Audio(&'a AudioAttributes),
+}
+
+/// A part of the document and when it's spoken.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineEntry<'a> {
+    /// What the entry is.
+    pub kind: TimelineKind<'a>,
+    /// Range of the text in unicode scalar values, empty for marks and breaks.
+    pub range: Range<usize>,
+    /// Time from the start of the document the entry starts.
+    pub start: Duration,
+    /// Time from the start of the document the entry ends.
+    pub end: Duration,
+}
+
+/// Estimated timing of a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline<'a> {
+    /// The entries in order of when they start.
+    pub entries: Vec<TimelineEntry<'a>>,
+    /// How long the whole document takes to say.
+    pub duration: Duration,
+}
+
+/// Something which takes time to say, or a point in time for marks.
+struct Item<'a> {
+    node: Node<'a>,
+    kind: Option<TimelineKind<'a>>,
+    duration: Duration,
+    start: Duration,
+    /// Byte offsets in the node's text of the end of each word, for text items.
+    words: Vec<usize>,
+    /// Number of items straight after this one which are inside it, for audio timed by its
+    /// fallback text.
+    within: usize,
+}
+
+impl Item<'_> {
+    fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+
+    /// Whether a prosody duration changes how long the item takes, which it does for text and
+    /// breaks but not audio.
+    fn scalable(&self) -> bool {
+        !matches!(self.kind, Some(TimelineKind::Audio(_)))
+    }
+
+    /// The time the text at a char offset within a text item is spoken, going by the number of
+    /// words in the item before the offset.
+    fn time_at(&self, offset: usize) -> Duration {
+        if self.words.is_empty() {
+            return self.start;
+        }
+        let text = self.node.text();
+        let byte = OffsetUnit::Chars.to_byte_offset(text, offset - self.node.char_range().start);
+        let said = self.words.partition_point(|x| *x <= byte);
+        self.start + self.duration.mul_f64(said as f64 / self.words.len() as f64)
+    }
+}
+
+/// Moves the index forward to the run at the offset and returns it. The runs are sorted and don't
+/// overlap, and offsets are given in order so runs before the offset are never needed again.
+fn run_at<'a, T>(
+    runs: &'a [T],
+    index: &mut usize,
+    offset: usize,
+    range: impl Fn(&T) -> &Range<usize>,
+) -> Option<&'a T> {
+    while runs.get(*index).is_some_and(|x| range(x).end <= offset) {
+        *index += 1;
+    }
+    runs.get(*index).filter(|x| range(x).contains(&offset))
+}
+
+impl Ssml {
+    /// Estimates when each sentence, mark, break and audio element in the document is reached
+    /// and how long the whole document takes to say.
+    pub fn timeline(&self, timing: &SpeechTiming) -> Timeline<'_> {
+        let languages = self.language_runs();
+        let prosody = self.prosody_runs(&timing.prosody);
+        let (mut language_index, mut prosody_index) = (0, 0);
+        // Prosody elements with a duration, with the items inside them
+        let mut timed_prosody: Vec<(Duration, Vec<usize>)> = vec![];
+        // Elements are open until their last descendant, nodes are in document order so they're
+        // closed in the reverse of the order they're opened
+        let mut open_prosody: Vec<(usize, Node)> = vec![];
+        let mut open_audio: Vec<(usize, Node)> = vec![];
+        // Audio with a known length is timed by its attributes so its content is skipped
+        let mut known_audio: Option<Node> = None;
+        let mut items: Vec<Item> = vec![];
+
+        for node in self.root().descendants() {
+            let item = match node.element() {
+                _ if known_audio.is_some() => None,
+                None if node.is_text() => {
+                    let start = node.char_range().start;
+                    let lang = run_at(&languages, &mut language_index, start, |x| &x.range)
+                        .and_then(|x| x.lang);
+                    let rate = run_at(&prosody, &mut prosody_index, start, |x| &x.range)
+                        .map(|x| x.prosody.rate)
+                        .unwrap_or(1.0);
+                    // Substitutions say the alias instead of the text
+                    let text = match node.parent().and_then(|x| x.element()) {
+                        Some(ParsedElement::Sub(attrs)) => &attrs.alias,
+                        _ => node.text(),
+                    };
+                    let duration = timing.rate(lang).duration(text);
+                    let duration = Duration::try_from_secs_f32(duration.as_secs_f32() / rate);
+                    Some((None, duration.unwrap_or_default()))
+                }
+                Some(ParsedElement::Break(attrs)) => Some((
+                    Some(TimelineKind::Break(attrs)),
+                    timing.break_duration(attrs),
+                )),
+                Some(ParsedElement::Audio(attrs)) => {
+                    let duration = timing.audio_duration(attrs);
+                    if let Some(last) = node.descendants().next_back() {
+                        match duration {
+                            Some(_) => known_audio = Some(last),
+                            None => open_audio.push((items.len(), last)),
+                        }
+                    }
+                    Some((
+                        Some(TimelineKind::Audio(attrs)),
+                        duration.unwrap_or_default(),
+                    ))
+                }
+                Some(ParsedElement::Prosody(ProsodyAttributes {
+                    duration: Some(duration),
+                    ..
+                })) => {
+                    if let Some(last) = node.descendants().next_back() {
+                        open_prosody.push((timed_prosody.len(), last));
+                        timed_prosody.push((duration.duration(), vec![]));
+                    }
+                    None
+                }
+                Some(element) => {
+                    mark_name(element).map(|name| (Some(TimelineKind::Mark(name)), Duration::ZERO))
+                }
+                None => None,
+            };
+            if let Some((kind, duration)) = item {
+                for (index, _) in &open_prosody {
+                    timed_prosody[*index].1.push(items.len());
+                }
+                let words = match kind {
+                    None => node
+                        .text()
+                        .unicode_word_indices()
+                        .map(|(start, word)| start + word.len())
+                        .collect(),
+                    Some(_) => vec![],
+                };
+                items.push(Item {
+                    node,
+                    kind,
+                    duration,
+                    start: Duration::ZERO,
+                    words,
+                    within: 0,
+                });
+            }
+
+            if known_audio == Some(node) {
+                known_audio = None;
+            }
+            while open_prosody.last().is_some_and(|(_, last)| *last == node) {
+                open_prosody.pop();
+            }
+            while open_audio.last().is_some_and(|(_, last)| *last == node) {
+                let (index, _) = open_audio.pop().unwrap();
+                items[index].within = items.len() - index - 1;
+            }
+        }
+
+        // Inner prosody elements are scaled to their duration first, then the outer ones scale
+        // everything inside them including the inner ones. Inner elements come after the
+        // elements around them so going backwards reaches them first. The duration is the time
+        // to read the text, audio plays at its own length so only the rest is scaled to fit
+        for (duration, inside) in timed_prosody.iter().rev() {
+            let (scaled, fixed): (Vec<usize>, Vec<usize>) =
+                inside.iter().partition(|x| items[**x].scalable());
+            let natural: Duration = scaled.iter().map(|x| items[*x].duration).sum();
+            if natural.is_zero() {
+                continue;
+            }
+            let fixed: Duration = fixed.iter().map(|x| items[*x].duration).sum();
+            let scale = duration.saturating_sub(fixed).as_secs_f64() / natural.as_secs_f64();
+            for index in scaled {
+                items[index].duration = items[index].duration.mul_f64(scale);
+            }
+        }
+
+        let mut clock = Duration::ZERO;
+        for item in items.iter_mut() {
+            item.start = clock;
+            clock += item.duration;
+        }
+
+        let mut entries = vec![];
+        for (index, item) in items.iter().enumerate() {
+            let Some(kind) = &item.kind else {
+                continue;
+            };
+            // Audio without a known length lasts as long as its fallback text, items follow each
+            // other so the last item inside it ends last
+            let end = match kind {
+                TimelineKind::Audio(_) => items[index + item.within].end(),
+                _ => item.end(),
+            };
+            entries.push(TimelineEntry {
+                kind: kind.clone(),
+                range: item.node.char_range(),
+                start: item.start,
+                end,
+            });
+        }
+
+        // Text items and sentences are both in order and don't overlap, so text before a sentence
+        // isn't in any of the sentences after it
+        let text = items
+            .iter()
+            .filter(|x| x.kind.is_none())
+            .collect::<Vec<_>>();
+        let mut first = 0;
+        for range in self.sentence_ranges() {
+            while text
+                .get(first)
+                .is_some_and(|x| x.node.char_range().end <= range.start)
+            {
+                first += 1;
+            }
+            let overlapping = &text[first..];
+            let count = overlapping.partition_point(|x| x.node.char_range().start < range.end);
+            let (Some(first), Some(last)) = (overlapping.first(), overlapping[..count].last())
+            else {
+                continue;
+            };
+            entries.push(TimelineEntry {
+                kind: TimelineKind::Sentence,
+                start: first.time_at(range.start.max(first.node.char_range().start)),
+                end: last.time_at(range.end.min(last.node.char_range().end)),
+                range,
+            });
+        }
+        entries.sort_by_key(|x| x.start);
+
+        Timeline {
+            entries,
+            duration: clock,
+        }
+    }
+
+    /// The sentences in the text in unicode scalar values. These are the `<s>` elements, and
+    /// sentences found from unicode sentence boundaries in any text which isn't in one.
+    fn sentence_ranges(&self) -> Vec<Range<usize>> {
+        let elements = self
+            .root()
+            .descendants()
+            .filter(|x| matches!(x.element(), Some(ParsedElement::Sentence(_))))
+            .map(|x| x.char_range())
+            .collect::<Vec<_>>();
+        let text = self.get_text();
+        let chars = |byte| OffsetUnit::Chars.from_byte_offset(text, byte);
+        let mut index = 0;
+        let mut sentences = text
+            .split_sentence_bound_indices()
+            .map(|(start, sentence)| chars(start)..chars(start + sentence.trim_end().len()))
+            .filter(|x| !x.is_empty())
+            .filter(|x| {
+                // Both are in order so elements ending before this sentence can be passed over
+                while elements.get(index).is_some_and(|s| s.end <= x.start) {
+                    index += 1;
+                }
+                elements.get(index).is_none_or(|s| s.start >= x.end)
+            })
+            .collect::<Vec<_>>();
+        sentences.extend(elements);
+        sentences.sort_by_key(|x| x.start);
+        sentences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::char_to_byte_range;
+    use crate::parse_ssml;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn assert_close(a: Duration, b: Duration) {
+        assert!(a.abs_diff(b) < ms(1), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn document_timeline() {
+        // 150 words per minute is 400ms a word
+        let ssml = parse_ssml(
+            r#"<speak>Hello there world. <break time="500ms"/><mark name="m"/><prosody rate="50%">Slow words here.</prosody> <audio src="ad.wav" clipBegin="1s" clipEnd="3s" repeatCount="2">Fallback text.</audio> <prosody duration="2s">One two <break/>three four.</prosody></speak>"#,
+        )
+        .unwrap();
+        let timeline = ssml.timeline(&SpeechTiming::default());
+        assert_close(timeline.duration, ms(10_100));
+
+        let text = ssml.get_text();
+        let entries = timeline
+            .entries
+            .iter()
+            .map(|x| {
+                let text = &text[char_to_byte_range(text, x.range.clone())];
+                match x.kind {
+                    TimelineKind::Sentence => format!("sentence {}", text),
+                    TimelineKind::Mark(name) => format!("mark {}", name),
+                    TimelineKind::Break(_) => "break".to_string(),
+                    TimelineKind::Audio(_) => format!("audio {}", text),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                "sentence Hello there world.",
+                "break",
+                "mark m",
+                "sentence Slow words here.",
+                "audio Fallback text.",
+                "sentence One two three four.",
+                "break",
+            ]
+        );
+        let times = timeline
+            .entries
+            .iter()
+            .map(|x| (x.start, x.end))
+            .collect::<Vec<_>>();
+        assert_close(times[0].1, ms(1200));
+        assert_close(times[1].0, ms(1200));
+        assert_close(times[1].1, ms(1700));
+        assert_close(times[2].0, ms(1700));
+        assert_close(times[3].0, ms(1700));
+        assert_close(times[3].1, ms(4100));
+        assert_close(times[4].1, ms(8100));
+        assert_close(times[5].0, ms(8100));
+        assert_close(times[5].1, ms(10_100));
+        // The medium break and four words are squeezed into two seconds
+        assert_close(times[6].0, ms(8100 + 2000 * 800 / 2100));
+    }
+
+    #[test]
+    fn languages_and_audio() {
+        let ssml = parse_ssml(
+            r#"<speak>Two words <lang xml:lang="ja">こんにちは</lang> <audio src="https://example.com/a.wav">three more words</audio> <sub alias="World Wide Web Consortium">W3C</sub></speak>"#,
+        )
+        .unwrap();
+        let timing = SpeechTiming::default();
+        // Unknown audio is timed by its fallback text, 2 + 3 + 4 words and 5 characters
+        assert_close(ssml.timeline(&timing).duration, ms(9 * 400 + 5000 / 7));
+
+        let timing = SpeechTiming {
+            audio_lengths: [("https://example.com/a.wav".to_string(), ms(1500))].into(),
+            ..Default::default()
+        };
+        let timeline = ssml.timeline(&timing);
+        assert_close(timeline.duration, ms(6 * 400 + 5000 / 7 + 1500));
+        let audio = timeline
+            .entries
+            .iter()
+            .find(|x| matches!(x.kind, TimelineKind::Audio(_)))
+            .unwrap();
+        assert_close(audio.end - audio.start, ms(1500));
+    }
+
+    #[test]
+    fn nested_elements() {
+        // The outer prosody ends at the text of the audio inside it, which is skipped as the
+        // audio's length is known
+        let ssml = parse_ssml(
+            r#"<speak><prosody duration="4s">One <prosody duration="1s">two three</prosody> <audio src="a.wav" clipEnd="2s">skipped</audio></prosody> <audio src="b.wav">four <break time="1s"/>five</audio> six</speak>"#,
+        )
+        .unwrap();
+        let timeline = ssml.timeline(&SpeechTiming::default());
+        assert_close(timeline.duration, ms(4000 + 1800 + 400));
+        let audio = timeline
+            .entries
+            .iter()
+            .filter(|x| matches!(x.kind, TimelineKind::Audio(_)))
+            .map(|x| (x.start, x.end))
+            .collect::<Vec<_>>();
+        assert_eq!(audio.len(), 2);
+        // The audio keeps its two seconds, one word and the inner prosody are scaled from 1.4
+        // seconds to the other two
+        assert_close(audio[0].0, ms(2000));
+        assert_close(audio[0].1, ms(4000));
+        // Audio without a length lasts until the end of its fallback
+        assert_close(audio[1].0, ms(4000));
+        assert_close(audio[1].1, ms(5800));
+    }
+
+    #[test]
+    fn breaks() {
+        let timing = SpeechTiming::default();
+        let duration = |ssml: &str| parse_ssml(ssml).unwrap().timeline(&timing).duration;
+        assert_eq!(duration(r#"<speak><break/></speak>"#), ms(500));
+        assert_eq!(
+            duration(r#"<speak><break strength="x-strong"/></speak>"#),
+            ms(1000)
+        );
+        assert_eq!(
+            duration(r#"<speak><break strength="none" time="2s"/></speak>"#),
+            ms(2000)
+        );
+    }
+}
@@ -0,0 +1,567 @@
+//! Rewrites SSML written for one TTS vendor so it can be sent to another. Elements from other
+//! vendors' dialects are mapped to an equivalent where there is one and otherwise removed, keeping
+//! any text inside them. Attributes the target doesn't support are removed and values outside the
+//! target's limits are clamped. Everything which is changed is reported so the caller can decide
+//! whether the result is still good enough to use.
+//!
+//! Mapping dialect elements relies on them having been parsed as typed elements, so the source
+//! document should be parsed with its dialect enabled on `SsmlParserBuilder`.
+#[cfg(feature = "azure")]
+use crate::dialects::amazon::{AmazonElement, Domain, DomainAttributes, Effect, EffectAttributes};
+#[cfg(feature = "azure")]
+use crate::dialects::microsoft::{BookmarkAttributes, ExpressAsAttributes, MicrosoftElement};
+use crate::dialects::{Dialect, DialectElement};
+use crate::elements::*;
+use crate::{ParserEvent, Ssml, TransformedSsml};
+use std::fmt;
+use std::time::Duration;
+
+/// The vendor the SSML is being rewritten for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Target {
+    /// Plain SSML 1.1 with no vendor extensions.
+    Ssml11,
+    /// Amazon Polly.
+    Amazon,
+    /// Google Cloud Text-to-Speech.
+    Google,
+    /// Microsoft Azure.
+    #[cfg(feature = "azure")]
+    Microsoft,
+}
+
+impl Target {
+    /// The dialect the target understands, if any.
+    pub fn dialect(&self) -> Option<Dialect> {
+        match self {
+            Self::Ssml11 => None,
+            Self::Amazon => Some(Dialect::Amazon),
+            Self::Google => Some(Dialect::Google),
+            #[cfg(feature = "azure")]
+            Self::Microsoft => Some(Dialect::Microsoft),
+        }
+    }
+
+    /// The longest break the target allows.
+    fn max_break(&self) -> Option<Duration> {
+        match self {
+            Self::Amazon | Self::Google => Some(Duration::from_secs(10)),
+            #[cfg(feature = "azure")]
+            Self::Microsoft => Some(Duration::from_millis(5000)),
+            Self::Ssml11 => None,
+        }
+    }
+
+    /// Prosody attributes the target doesn't support.
+    fn unsupported_prosody(&self) -> &'static [&'static str] {
+        match self {
+            // Polly's neural voices can't change pitch
+            Self::Amazon => &["pitch", "contour", "range", "duration"],
+            Self::Google => &["contour", "range", "duration"],
+            #[cfg(feature = "azure")]
+            Self::Microsoft => &["duration"],
+            Self::Ssml11 => &[],
+        }
+    }
+
+    /// The smallest and largest volume change the target allows in decibels.
+    fn volume_limits(&self) -> Option<(f32, f32)> {
+        match self {
+            Self::Amazon => Some((-40.0, 40.0)),
+            Self::Google => Some((-96.0, 16.0)),
+            _ => None,
+        }
+    }
+
+    /// The unit the target takes relative pitch changes in, with the smallest and largest change.
+    fn pitch_limits(&self) -> Option<(Unit, f32, f32)> {
+        match self {
+            Self::Google => Some((Unit::St, -20.0, 20.0)),
+            #[cfg(feature = "azure")]
+            Self::Microsoft => Some((Unit::Percentage, -50.0, 50.0)),
+            _ => None,
+        }
+    }
+}
+
+/// Result of rewriting a document for a target.
+#[derive(Clone, Debug)]
+pub struct Transpiled {
+    /// The rewritten SSML and its synthesisable text.
+    pub output: TransformedSsml,
+    /// Everything that was changed to make the document suitable for the target.
+    pub losses: Vec<Loss>,
+}
+
+/// A change made to the document when rewriting it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loss {
+    /// Position of the element in the source document's text in unicode scalar values.
+    pub position: usize,
+    /// Name of the element which was changed.
+    pub element: String,
+    /// What was done to the element.
+    pub change: Change,
+}
+
+/// The changes made by the transpiler.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// The element was removed, anything inside it was kept.
+    RemovedElement,
+    /// The element was replaced with the named element.
+    MappedElement(String),
+    /// The named attribute was removed.
+    RemovedAttribute(String),
+    /// The named attribute was changed to the given value to fit within the target's limits.
+    ClampedAttribute {
+        /// Name of the attribute
+        attribute: String,
+        /// The value now used
+        value: String,
+    },
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            Change::RemovedElement => write!(f, "removed {} element", self.element),
+            Change::MappedElement(to) => write!(f, "replaced {} element with {}", self.element, to),
+            Change::RemovedAttribute(attribute) => {
+                write!(f, "removed {} attribute from {}", attribute, self.element)
+            }
+            Change::ClampedAttribute { attribute, value } => write!(
+                f,
+                "changed {} attribute of {} to {}",
+                attribute, self.element, value
+            ),
+        }?;
+        write!(f, " at character {}", self.position)
+    }
+}
+
+impl Ssml {
+    /// Rewrites the document for the given target, see the `transpile` module for details.
+    pub fn transpile(&self, target: Target) -> Transpiled {
+        let mut transpiler = Transpiler {
+            target,
+            position: 0,
+            open_tags: vec![],
+            losses: vec![],
+        };
+        let output = self.write_ssml_with_transform(|event| transpiler.apply(event));
+        Transpiled {
+            output,
+            losses: transpiler.losses,
+        }
+    }
+}
+
+struct Transpiler {
+    target: Target,
+    /// Character position in the source text
+    position: usize,
+    /// For each open element what it was replaced with, `None` if it was removed
+    open_tags: Vec<Option<ParsedElement>>,
+    losses: Vec<Loss>,
+}
+
+impl Transpiler {
+    fn apply(&mut self, event: ParserEvent) -> Option<ParserEvent> {
+        match event {
+            ParserEvent::Text(text) => {
+                self.position += text.chars().count();
+                Some(ParserEvent::Text(text))
+            }
+            ParserEvent::Open(element) => {
+                let converted = self.convert(element);
+                self.open_tags.push(converted.clone());
+                converted.map(ParserEvent::Open)
+            }
+            ParserEvent::Close(_) => self.open_tags.pop().flatten().map(ParserEvent::Close),
+            ParserEvent::Empty(element) => self.convert(element).map(ParserEvent::Empty),
+        }
+    }
+
+    fn loss(&mut self, element: &ParsedElement, change: Change) {
+        self.losses.push(Loss {
+            position: self.position,
+            element: SsmlElement::from(element).to_string(),
+            change,
+        });
+    }
+
+    fn convert(&mut self, element: ParsedElement) -> Option<ParsedElement> {
+        let converted = match &element {
+            // Only Google allows speak elements inside others and those are in media elements
+            // which will have been removed for any other target.
+            ParsedElement::Speak(_)
+                if !self.open_tags.is_empty() && self.target != Target::Google =>
+            {
+                None
+            }
+            ParsedElement::Dialect(dialect) if Some(dialect.dialect()) == self.target.dialect() => {
+                return Some(element);
+            }
+            ParsedElement::Dialect(dialect) => self.map_dialect(dialect),
+            #[cfg(feature = "azure")]
+            ParsedElement::Mark(mark) if self.target == Target::Microsoft => {
+                Some(ParsedElement::Dialect(DialectElement::Microsoft(
+                    MicrosoftElement::Bookmark(BookmarkAttributes {
+                        mark: mark.name.clone(),
+                    }),
+                )))
+            }
+            // None of the targets will know what to do with an unknown element
+            ParsedElement::Custom(_) => None,
+            // Polly's neural voices don't support emphasis
+            ParsedElement::Emphasis(_) if self.target == Target::Amazon => None,
+            ParsedElement::Prosody(attrs) => {
+                return Some(ParsedElement::Prosody(self.convert_prosody(attrs.clone())))
+            }
+            ParsedElement::Break(attrs) => {
+                return Some(ParsedElement::Break(self.convert_break(attrs.clone())))
+            }
+            _ => return Some(element),
+        };
+        match &converted {
+            Some(new) => {
+                let name = SsmlElement::from(new).to_string();
+                self.loss(&element, Change::MappedElement(name));
+            }
+            None => self.loss(&element, Change::RemovedElement),
+        }
+        converted
+    }
+
+    /// Finds an equivalent element in the target's dialect, or a standard SSML element.
+    fn map_dialect(&self, element: &DialectElement) -> Option<ParsedElement> {
+        match element {
+            #[cfg(feature = "azure")]
+            DialectElement::Microsoft(element) => self.map_microsoft(element),
+            #[cfg(feature = "azure")]
+            DialectElement::Amazon(element) if self.target == Target::Microsoft => {
+                map_amazon_to_microsoft(element)
+            }
+            _ => None,
+        }
+    }
+
+    fn convert_prosody(&mut self, mut attrs: ProsodyAttributes) -> ProsodyAttributes {
+        let element = ParsedElement::Prosody(ProsodyAttributes::default());
+        for attribute in self.target.unsupported_prosody() {
+            let removed = match *attribute {
+                "pitch" => attrs.pitch.take().is_some(),
+                "contour" => attrs.contour.take().is_some(),
+                "range" => attrs.range.take().is_some(),
+                "duration" => attrs.duration.take().is_some(),
+                _ => unreachable!(),
+            };
+            if removed {
+                self.loss(&element, Change::RemovedAttribute(attribute.to_string()));
+            }
+        }
+        // Polly only allows speaking rates between 20% and 200% of the default
+        if self.target == Target::Amazon {
+            if let Some(RateRange::Percentage(rate)) = attrs.rate {
+                let value = match rate {
+                    PositiveNumber::FloatNumber(x) => x,
+                    PositiveNumber::RoundNumber(x) => x as f32,
+                };
+                let clamped = value.clamp(20.0, 200.0);
+                if clamped != value {
+                    let rate = RateRange::Percentage(PositiveNumber::RoundNumber(clamped as isize));
+                    attrs.rate = Some(rate);
+                    self.loss(
+                        &element,
+                        Change::ClampedAttribute {
+                            attribute: "rate".to_string(),
+                            value: rate.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+        // Azure takes volumes as keywords or numbers but not in decibels
+        #[cfg(feature = "azure")]
+        if self.target == Target::Microsoft && matches!(attrs.volume, Some(VolumeRange::Decibel(_)))
+        {
+            attrs.volume = None;
+            self.loss(&element, Change::RemovedAttribute("volume".to_string()));
+        }
+        if let (Some(VolumeRange::Decibel(db)), Some((min, max))) =
+            (attrs.volume, self.target.volume_limits())
+        {
+            let clamped = db.clamp(min, max);
+            if clamped != db {
+                let volume = VolumeRange::Decibel(clamped);
+                attrs.volume = Some(volume);
+                self.loss(
+                    &element,
+                    Change::ClampedAttribute {
+                        attribute: "volume".to_string(),
+                        value: volume.to_string(),
+                    },
+                );
+            }
+        }
+        if let (Some(pitch), Some((unit, min, max))) = (attrs.pitch, self.target.pitch_limits()) {
+            let converted = match pitch {
+                PitchRange::Strength(_) => Some(pitch),
+                // Changes in hertz depend on the voice so can't be converted
+                PitchRange::Frequency(_) | PitchRange::RelativeChange((_, _, Unit::Hz)) => None,
+                PitchRange::RelativeChange((value, sign, from)) => {
+                    let value = match sign {
+                        Sign::Plus => value,
+                        Sign::Minus => -value,
+                    };
+                    let value = convert_pitch(value, from, unit).clamp(min, max);
+                    let value = (value * 100.0).round() / 100.0;
+                    let sign = if value < 0.0 { Sign::Minus } else { Sign::Plus };
+                    Some(PitchRange::RelativeChange((value.abs(), sign, unit)))
+                }
+            };
+            attrs.pitch = converted;
+            match converted {
+                None => self.loss(&element, Change::RemovedAttribute("pitch".to_string())),
+                Some(converted) if converted != pitch => self.loss(
+                    &element,
+                    Change::ClampedAttribute {
+                        attribute: "pitch".to_string(),
+                        value: converted.to_string(),
+                    },
+                ),
+                _ => {}
+            }
+        }
+        attrs
+    }
+
+    fn convert_break(&mut self, mut attrs: BreakAttributes) -> BreakAttributes {
+        if let (Some(time), Some(max)) = (attrs.time, self.target.max_break()) {
+            if time.duration() > max {
+                let time = TimeDesignation::Milliseconds(max.as_millis() as f32);
+                attrs.time = Some(time);
+                self.loss(
+                    &ParsedElement::Break(attrs.clone()),
+                    Change::ClampedAttribute {
+                        attribute: "time".to_string(),
+                        value: time.to_string(),
+                    },
+                );
+            }
+        }
+        attrs
+    }
+}
+
+/// Converts a relative pitch change between semitones and percentages.
+fn convert_pitch(value: f32, from: Unit, to: Unit) -> f32 {
+    match (from, to) {
+        (Unit::St, Unit::Percentage) => (2f32.powf(value / 12.0) - 1.0) * 100.0,
+        // Lowering the pitch by 100% or more is as low as it goes
+        (Unit::Percentage, Unit::St) => 12.0 * (1.0 + value / 100.0).max(0.0).log2(),
+        _ => value,
+    }
+}
+
+#[cfg(feature = "azure")]
+impl Transpiler {
+    fn map_microsoft(&self, element: &MicrosoftElement) -> Option<ParsedElement> {
+        match (element, self.target) {
+            (MicrosoftElement::Bookmark(bookmark), _) => {
+                Some(ParsedElement::Mark(MarkAttributes {
+                    name: bookmark.mark.clone(),
+                }))
+            }
+            (MicrosoftElement::ExpressAs(attrs), Target::Amazon) => {
+                let element = match attrs.style.as_str() {
+                    "whispering" => AmazonElement::Effect(EffectAttributes {
+                        name: Some(Effect::Whispered),
+                        ..Default::default()
+                    }),
+                    "newscast" => AmazonElement::Domain(DomainAttributes { name: Domain::News }),
+                    _ => return None,
+                };
+                Some(ParsedElement::Dialect(DialectElement::Amazon(element)))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "azure")]
+fn map_amazon_to_microsoft(element: &AmazonElement) -> Option<ParsedElement> {
+    let style = match element {
+        AmazonElement::Effect(EffectAttributes {
+            name: Some(Effect::Whispered),
+            ..
+        }) => "whispering",
+        AmazonElement::Domain(domain) if domain.name == Domain::News => "newscast",
+        _ => return None,
+    };
+    Some(ParsedElement::Dialect(DialectElement::Microsoft(
+        MicrosoftElement::ExpressAs(ExpressAsAttributes {
+            style: style.to_string(),
+            style_degree: None,
+            role: None,
+        }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SsmlParserBuilder;
+    use crate::OffsetUnit;
+
+    fn parse_with(dialect: Dialect, ssml: &str) -> Ssml {
+        SsmlParserBuilder::default()
+            .dialect(dialect)
+            .build()
+            .unwrap()
+            .parse(ssml)
+            .unwrap()
+    }
+
+    #[test]
+    fn google_to_amazon() {
+        let ssml = parse_with(
+            Dialect::Google,
+            r#"<speak>
+            <par>
+              <media xml:id="question"><speak>Who invented the Internet?</speak></media>
+              <media begin="question.end+1s"><speak>Cats.</speak></media>
+            </par>
+            <prosody rate="10%" contour="(0%,+20Hz) (10%,+30Hz)">Slowly</prosody>
+            <break time="20s"/>
+            <mstts:viseme type="redlips_front"/>
+            </speak>"#,
+        );
+
+        let transpiled = ssml.transpile(Target::Amazon);
+        let output = SsmlParserBuilder::default()
+            .dialect(Dialect::Amazon)
+            .build()
+            .unwrap()
+            .parse(&transpiled.output.ssml_string)
+            .unwrap();
+        assert_eq!(output.get_text(), ssml.get_text());
+        assert!(!transpiled.output.ssml_string.contains("media"));
+        assert!(!transpiled.output.ssml_string.contains("contour"));
+        assert!(transpiled.output.ssml_string.contains(r#"rate="20%""#));
+        assert!(transpiled.output.ssml_string.contains(r#"time="10000ms""#));
+
+        let removed = transpiled
+            .losses
+            .iter()
+            .filter(|x| x.change == Change::RemovedElement)
+            .map(|x| x.element.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            removed,
+            ["par", "media", "speak", "media", "speak", "mstts:viseme"]
+        );
+        let text = ssml.get_text();
+        let slowly = OffsetUnit::Chars.from_byte_offset(text, text.find("Slowly").unwrap());
+        assert!(transpiled.losses.contains(&Loss {
+            position: slowly,
+            element: "prosody".to_string(),
+            change: Change::RemovedAttribute("contour".to_string()),
+        }));
+    }
+
+    #[test]
+    fn plain_ssml() {
+        let ssml = parse_with(
+            Dialect::Amazon,
+            r#"<speak><amazon:effect name="whispered">Hello <break time="20s"/><math/></amazon:effect></speak>"#,
+        );
+        let transpiled = ssml.transpile(Target::Ssml11);
+        assert_eq!(
+            transpiled.output.ssml_string,
+            r#"<speak version="1.1">Hello <break time="20s"/></speak>"#
+        );
+        assert_eq!(transpiled.losses.len(), 2);
+        assert_eq!(
+            transpiled.losses[1].to_string(),
+            "removed math element at character 6"
+        );
+    }
+
+    #[cfg(feature = "profiles")]
+    #[test]
+    fn output_fits_profiles() {
+        use crate::profile::Profile;
+
+        let ssml = crate::parse_ssml(
+            r#"<speak><emphasis level="strong">hi</emphasis> <prosody pitch="high" rate="300%" volume="+50dB">there</prosody> <prosody pitch="+30%" volume="-100dB" range="+10%" duration="2s">now</prosody> <prosody pitch="-2st">and</prosody> <prosody pitch="+80Hz">then</prosody></speak>"#,
+        )
+        .unwrap();
+        let mut targets = vec![(Target::Amazon, "amazon-polly"), (Target::Google, "google")];
+        #[cfg(feature = "azure")]
+        targets.push((Target::Microsoft, "azure"));
+        for (target, name) in targets {
+            let profile = Profile::from_file(format!("profiles/{}.toml", name)).unwrap();
+            let transpiled = ssml.transpile(target);
+            let output = parse_with(target.dialect().unwrap(), &transpiled.output.ssml_string);
+            let incompatibilities = output.validate_for(&profile);
+            assert!(
+                incompatibilities.is_empty(),
+                "{}: {:?}",
+                transpiled.output.ssml_string,
+                incompatibilities
+            );
+        }
+
+        let amazon = ssml.transpile(Target::Amazon).output.ssml_string;
+        assert!(!amazon.contains("emphasis"));
+        assert!(!amazon.contains("pitch"));
+        assert!(amazon.contains(r#"volume="40dB""#));
+        assert!(amazon.contains(r#"volume="-40dB""#));
+
+        let google = ssml.transpile(Target::Google);
+        let output = &google.output.ssml_string;
+        assert!(output.contains(r#"pitch="+4.54st""#));
+        assert!(output.contains(r#"volume="16dB""#));
+        assert!(!output.contains("80Hz"));
+        assert!(google.losses.iter().any(|x| x.change
+            == Change::ClampedAttribute {
+                attribute: "pitch".to_string(),
+                value: "+4.54st".to_string(),
+            }));
+
+        #[cfg(feature = "azure")]
+        {
+            let azure = ssml.transpile(Target::Microsoft).output.ssml_string;
+            assert!(azure.contains(r#"pitch="-10.91%""#));
+            assert!(!azure.contains("dB"));
+        }
+    }
+
+    #[cfg(feature = "azure")]
+    #[test]
+    fn microsoft_and_amazon() {
+        let ssml = parse_with(
+            Dialect::Microsoft,
+            r#"<speak><mstts:express-as style="whispering" styledegree="2">Quiet <bookmark mark="here"/>please</mstts:express-as><break time="8s"/></speak>"#,
+        );
+        let transpiled = ssml.transpile(Target::Amazon);
+        assert_eq!(
+            transpiled.output.ssml_string,
+            r#"<speak version="1.1"><amazon:effect name="whispered">Quiet <mark name="here"/>please</amazon:effect><break time="8s"/></speak>"#
+        );
+        assert_eq!(
+            transpiled.losses[0].change,
+            Change::MappedElement("amazon:effect".to_string())
+        );
+
+        let amazon = parse_with(Dialect::Amazon, &transpiled.output.ssml_string);
+        let transpiled = amazon.transpile(Target::Microsoft);
+        assert_eq!(
+            transpiled.output.ssml_string,
+            r#"<speak version="1.1"><mstts:express-as style="whispering">Quiet <bookmark mark="here"/>please</mstts:express-as><break time="5000ms"/></speak>"#
+        );
+    }
+}
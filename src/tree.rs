@@ -0,0 +1,379 @@
+//! A tree view of a parsed SSML document. The parser records the document as a flat list of
+//! events, this module builds the element tree from those events so the document can be walked
+//! structurally - going from an element to its children or parent without having to work out the
+//! nesting from the character offsets of the spans.
+use crate::elements::ParsedElement;
+use crate::offset::OffsetUnit;
+use crate::parser::Span;
+use crate::{ParserLogEvent, Ssml};
+use std::fmt;
+use std::ops::Range;
+
+/// The nodes of a document stored in the order they appear in the document, the document root is
+/// always the first node.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DocumentTree {
+    nodes: Vec<NodeData>,
+}
+
+#[derive(Clone, Debug)]
+struct NodeData {
+    kind: NodeKind,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// Byte range of the text within this node
+    bytes: Range<usize>,
+    /// Character range of the text within this node
+    chars: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum NodeKind {
+    Document,
+    Element(Span),
+    Text,
+}
+
+impl DocumentTree {
+    /// Builds the tree from the event log. The text events in the log must cover the whole of the
+    /// text so that the position of each element in the text can be worked out.
+    pub(crate) fn new(text: &str, event_log: &[ParserLogEvent]) -> Self {
+        let mut nodes = vec![NodeData {
+            kind: NodeKind::Document,
+            parent: None,
+            children: vec![],
+            bytes: 0..text.len(),
+            chars: 0..text.chars().count(),
+        }];
+        let mut stack = vec![0];
+        let mut byte = 0;
+        let mut char = 0;
+
+        let add_node = |nodes: &mut Vec<NodeData>, parent: usize, kind, bytes, chars| {
+            let id = nodes.len();
+            nodes.push(NodeData {
+                kind,
+                parent: Some(parent),
+                children: vec![],
+                bytes,
+                chars,
+            });
+            nodes[parent].children.push(id);
+            id
+        };
+
+        for event in event_log {
+            let parent = *stack.last().unwrap();
+            match event {
+                ParserLogEvent::Text((start, end)) => {
+                    let chars = char..(char + text[*start..*end].chars().count());
+                    byte = *end;
+                    char = chars.end;
+                    add_node(&mut nodes, parent, NodeKind::Text, *start..*end, chars);
+                }
+                ParserLogEvent::Open(element) => {
+                    let span = Span {
+                        start: char,
+                        end: char,
+                        element: element.clone(),
+                    };
+                    let id = add_node(
+                        &mut nodes,
+                        parent,
+                        NodeKind::Element(span),
+                        byte..byte,
+                        char..char,
+                    );
+                    stack.push(id);
+                }
+                ParserLogEvent::Close(_) => {
+                    // A document which stops part way through may leave elements unclosed, these
+                    // are closed at the end of the text.
+                    if stack.len() > 1 {
+                        let id = stack.pop().unwrap();
+                        close_node(&mut nodes[id], byte, char);
+                    }
+                }
+                ParserLogEvent::Empty(element) => {
+                    let span = Span {
+                        start: char,
+                        end: char,
+                        element: element.clone(),
+                    };
+                    add_node(
+                        &mut nodes,
+                        parent,
+                        NodeKind::Element(span),
+                        byte..byte,
+                        char..char,
+                    );
+                }
+            }
+        }
+        while stack.len() > 1 {
+            let id = stack.pop().unwrap();
+            close_node(&mut nodes[id], byte, char);
+        }
+        Self { nodes }
+    }
+
+    /// The spans of every element in the document, sorted by their position in the text.
+    pub(crate) fn spans(&self) -> Vec<Span> {
+        let mut spans = self
+            .nodes
+            .iter()
+            .filter_map(|node| match &node.kind {
+                NodeKind::Element(span) => Some(span.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        spans.sort();
+        spans
+    }
+}
+
+fn close_node(node: &mut NodeData, byte: usize, char: usize) {
+    node.bytes.end = byte;
+    node.chars.end = char;
+    if let NodeKind::Element(span) = &mut node.kind {
+        span.end = char;
+    }
+}
+
+/// A node in the document tree. This is either the document root, an element or a piece of text
+/// within an element.
+#[derive(Copy, Clone)]
+pub struct Node<'a> {
+    ssml: &'a Ssml,
+    id: usize,
+}
+
+impl<'a> Node<'a> {
+    pub(crate) fn root(ssml: &'a Ssml) -> Self {
+        Self { ssml, id: 0 }
+    }
+
+    fn data(&self) -> &'a NodeData {
+        &self.ssml.tree.nodes[self.id]
+    }
+
+    fn node(&self, id: usize) -> Self {
+        Self {
+            ssml: self.ssml,
+            id,
+        }
+    }
+
+    /// Returns true if this is the root of the document. The root isn't an element, its children
+    /// are the top level elements and text in the document - normally just the speak element.
+    pub fn is_root(&self) -> bool {
+        matches!(self.data().kind, NodeKind::Document)
+    }
+
+    /// Returns true if this node is text within an element.
+    pub fn is_text(&self) -> bool {
+        matches!(self.data().kind, NodeKind::Text)
+    }
+
+    /// Returns true if this node is an element.
+    pub fn is_element(&self) -> bool {
+        matches!(self.data().kind, NodeKind::Element(_))
+    }
+
+    /// The element for this node, this is `None` for text and the document root.
+    pub fn element(&self) -> Option<&'a ParsedElement> {
+        self.span().map(|span| &span.element)
+    }
+
+    /// The span of the text covered by the element, this is `None` for text and the document
+    /// root.
+    pub fn span(&self) -> Option<&'a Span> {
+        match &self.data().kind {
+            NodeKind::Element(span) => Some(span),
+            _ => None,
+        }
+    }
+
+    /// The text within this node. For elements this is all the text of the element's
+    /// descendants.
+    pub fn text(&self) -> &'a str {
+        &self.ssml.get_text()[self.data().bytes.clone()]
+    }
+
+    /// The range of the node's text in the document text in unicode scalar values.
+    pub fn char_range(&self) -> Range<usize> {
+        self.data().chars.clone()
+    }
+
+    /// The range of the node's text in the document text in UTF-8 bytes.
+    pub fn byte_range(&self) -> Range<usize> {
+        self.data().bytes.clone()
+    }
+
+    /// The range of the node's text in the document text, measured in the given unit.
+    pub fn range(&self, unit: OffsetUnit) -> Range<usize> {
+        let text = self.ssml.get_text();
+        let bytes = self.byte_range();
+        unit.from_byte_offset(text, bytes.start)..unit.from_byte_offset(text, bytes.end)
+    }
+
+    /// The element or document root containing this node, this is `None` for the document root.
+    pub fn parent(&self) -> Option<Node<'a>> {
+        self.data().parent.map(|id| self.node(id))
+    }
+
+    /// The elements and text directly within this node in document order.
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        let node = *self;
+        self.data().children.iter().map(move |id| node.node(*id))
+    }
+
+    /// The parent of this node, followed by its parent and so on up to the document root.
+    pub fn ancestors(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        std::iter::successors(self.parent(), |node| node.parent())
+    }
+
+    /// Every node within this node in document order, not including this node.
+    pub fn descendants(&self) -> impl DoubleEndedIterator<Item = Node<'a>> + 'a {
+        let node = *self;
+        // Nodes are stored in document order so a node's descendants are the nodes straight after
+        // it up to the first node which isn't in its subtree.
+        let end = self.last_descendant() + 1;
+        (self.id + 1..end).map(move |id| node.node(id))
+    }
+
+    fn last_descendant(&self) -> usize {
+        let mut node = *self;
+        while let Some(&last) = node.data().children.last() {
+            node = node.node(last);
+        }
+        node.id
+    }
+}
+
+impl PartialEq for Node<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.ssml, other.ssml) && self.id == other.id
+    }
+}
+
+impl Eq for Node<'_> {}
+
+impl fmt::Debug for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.data().kind {
+            NodeKind::Document => f.write_str("Document"),
+            NodeKind::Element(span) => f.debug_tuple("Element").field(&span.element).finish(),
+            NodeKind::Text => f.debug_tuple("Text").field(&self.text()).finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::elements::*;
+    use crate::parser::*;
+
+    #[test]
+    fn walk_tree() {
+        let ssml = parse_ssml(
+            r#"<speak>
+            <p><s>Hello <emphasis>world</emphasis>.</s><s>Second <break/>sentence</s></p>
+            </speak>"#,
+        )
+        .unwrap();
+
+        let root = ssml.root();
+        assert!(root.is_root());
+        assert!(root.parent().is_none());
+        assert_eq!(root.text(), ssml.get_text());
+
+        let speak = root.children().next().unwrap();
+        assert!(matches!(speak.element(), Some(ParsedElement::Speak(_))));
+        assert_eq!(speak.parent(), Some(root));
+
+        let paragraph = speak
+            .children()
+            .find(|x| x.is_element())
+            .expect("no paragraph");
+        assert_eq!(
+            paragraph.element(),
+            Some(&ParsedElement::Paragraph(Default::default()))
+        );
+        // There's a space between the sentences so they don't run into each other
+        let sentences = paragraph
+            .children()
+            .filter(|x| x.is_element())
+            .collect::<Vec<_>>();
+        assert_eq!(sentences.len(), 2);
+        assert_eq!(paragraph.children().count(), 3);
+        assert_eq!(sentences[0].text(), "Hello world.");
+        assert_eq!(sentences[1].text().trim(), "Second sentence");
+
+        let emphasis = sentences[0]
+            .children()
+            .find(|x| x.is_element())
+            .expect("no emphasis");
+        assert!(matches!(
+            emphasis.element(),
+            Some(ParsedElement::Emphasis(_))
+        ));
+        assert_eq!(emphasis.text(), "world");
+        let text = emphasis.children().next().unwrap();
+        assert!(text.is_text());
+        assert!(text.element().is_none());
+        assert_eq!(text.text(), "world");
+        assert_eq!(
+            text.ancestors().collect::<Vec<_>>(),
+            vec![emphasis, sentences[0], paragraph, speak, root]
+        );
+
+        let brk = sentences[1]
+            .children()
+            .find(|x| x.is_element())
+            .expect("no break");
+        assert!(matches!(brk.element(), Some(ParsedElement::Break(_))));
+        assert!(brk.children().next().is_none());
+        assert_eq!(brk.text(), "");
+
+        // Every span in the tag list has a matching node in the tree
+        let elements = root
+            .descendants()
+            .filter_map(|x| x.span())
+            .collect::<Vec<_>>();
+        assert_eq!(elements.len(), ssml.tags().count());
+        for span in ssml.tags() {
+            assert!(elements.contains(&span));
+        }
+    }
+
+    #[test]
+    fn char_ranges() {
+        let ssml = parse_ssml("<speak>Héllo <s>wörld</s> again</speak>").unwrap();
+        for node in ssml.root().descendants() {
+            let chars = ssml
+                .get_text()
+                .chars()
+                .skip(node.char_range().start)
+                .take(node.char_range().len())
+                .collect::<String>();
+            assert_eq!(chars, node.text());
+            if let Some(span) = node.span() {
+                assert_eq!(span.start..span.end, node.char_range());
+            }
+        }
+    }
+
+    #[test]
+    fn empty_elements_with_same_position() {
+        // Sorting the spans puts the sentence before the mark, the tree keeps the document order
+        let ssml = parse_ssml(r#"<speak>Hi <mark name="a"/><s>there</s></speak>"#).unwrap();
+        let speak = ssml.root().children().next().unwrap();
+        let elements = speak
+            .children()
+            .filter_map(|x| x.element())
+            .collect::<Vec<_>>();
+        assert!(matches!(elements[0], ParsedElement::Mark(_)));
+        assert_eq!(elements[1], &ParsedElement::Sentence(Default::default()));
+    }
+}
@@ -0,0 +1,505 @@
+//! Selects voices for the `<voice>` elements in a document. The SSML specification leaves the set
+//! of available voices to the synthesis processor, so they're provided by a `VoiceCatalogue` and
+//! matched against the voice feature attributes using the selection algorithm described on
+//! `VoiceAttributes`. Voice attributes are inherited, a nested `<voice>` only changes the features
+//! it sets and keeps the rest from the voice elements around it.
+use crate::elements::*;
+use crate::language_tag::LanguageTag;
+use crate::tree::Node;
+use crate::Ssml;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+
+const ALL_FEATURES: [VoiceFeature; 5] = [
+    VoiceFeature::Name,
+    VoiceFeature::Languages,
+    VoiceFeature::Gender,
+    VoiceFeature::Age,
+    VoiceFeature::Variant,
+];
+
+/// A voice made available to the synthesis processor, described the way the specification asks
+/// voice vendors to document them.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct VoiceDescription {
+    /// Name of the voice, matched against the name attribute.
+    pub name: String,
+    /// Gender of the voice.
+    pub gender: Option<Gender>,
+    /// Age of the voice in years.
+    pub age: Option<u8>,
+    /// Which variant this voice is of voices with the same other characteristics, starting at 1.
+    pub variant: Option<NonZeroUsize>,
+    /// The languages the voice can read.
+    pub languages: Vec<VoiceLanguage>,
+}
+
+/// A language a voice can read and the accent it reads it with.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct VoiceLanguage {
+    /// The language.
+    pub lang: LanguageTag,
+    /// The accent, if not given the voice speaks the language with its native accent.
+    pub accent: Option<LanguageTag>,
+}
+
+impl VoiceLanguage {
+    fn accent(&self) -> &LanguageTag {
+        self.accent.as_ref().unwrap_or(&self.lang)
+    }
+}
+
+/// Why a voice element couldn't be given a voice that has all the required features.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SelectionFailure {
+    /// The catalogue doesn't have any voices.
+    NoVoices,
+    /// No voice has all the required features. Lists the required features that no voice
+    /// matches, or all of them if each is matched by some voice but never by the same one.
+    Unmatched(Vec<VoiceFeature>),
+}
+
+impl fmt::Display for SelectionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoVoices => write!(f, "no voices available"),
+            Self::Unmatched(features) => {
+                let features = features
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "no voice matches the required features: {}", features)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SelectionFailure {}
+
+/// The voice chosen for a voice element.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoiceSelection<'a> {
+    /// The voice to use.
+    pub voice: &'a VoiceDescription,
+    /// Set if no voice had all the required features and the voice was chosen as the
+    /// onvoicefailure attribute says. The failure should still be reported.
+    pub failure: Option<SelectionFailure>,
+}
+
+/// A set of voices to select from. Implementors only need to list their voices, the provided
+/// `select` follows the selection algorithm from the specification and can be replaced to choose
+/// voices some other way.
+pub trait VoiceCatalogue {
+    /// All the voices available.
+    fn voices(&self) -> &[VoiceDescription];
+
+    /// The voice used for text outside of any voice element, the first voice by default.
+    fn default_voice(&self) -> Option<&VoiceDescription> {
+        self.voices().first()
+    }
+
+    /// Selects a voice for a voice element with the given attributes, `current` is the voice in
+    /// effect around the element. If no voice has the required features a voice is still chosen
+    /// as onvoicefailure says, keeping the current voice or choosing by feature priority from all
+    /// the voices. With no current voice "keepexisting" chooses by priority as well, and so does
+    /// "processorchoice".
+    fn select<'a>(
+        &'a self,
+        attrs: &VoiceAttributes,
+        current: Option<&'a VoiceDescription>,
+    ) -> Result<VoiceSelection<'a>, SelectionFailure> {
+        let voices = self.voices();
+        if voices.is_empty() {
+            return Err(SelectionFailure::NoVoices);
+        }
+        let required = attrs.required_features();
+        let ordering = attrs.feature_ordering();
+
+        let candidates = voices
+            .iter()
+            .filter(|voice| required.iter().all(|x| voice_matches(voice, attrs, *x)))
+            .collect::<Vec<_>>();
+        if !candidates.is_empty() {
+            let remaining = ALL_FEATURES
+                .into_iter()
+                .filter(|x| !required.contains(x))
+                .collect::<Vec<_>>();
+            return Ok(VoiceSelection {
+                voice: select_by_priority(candidates, attrs, &remaining, &ordering),
+                failure: None,
+            });
+        }
+
+        let mut unmatched = required
+            .iter()
+            .copied()
+            .filter(|x| !voices.iter().any(|voice| voice_matches(voice, attrs, *x)))
+            .collect::<Vec<_>>();
+        if unmatched.is_empty() {
+            unmatched = required;
+        }
+        let voice = match (attrs.on_voice_failure.unwrap_or_default(), current) {
+            (OnVoiceFailure::KeepExisting, Some(current)) => current,
+            _ => select_by_priority(voices.iter().collect(), attrs, &ALL_FEATURES, &ordering),
+        };
+        Ok(VoiceSelection {
+            voice,
+            failure: Some(SelectionFailure::Unmatched(unmatched)),
+        })
+    }
+}
+
+/// A catalogue holding a fixed list of voices.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StaticCatalogue {
+    voices: Vec<VoiceDescription>,
+}
+
+impl StaticCatalogue {
+    /// Creates a catalogue from a list of voices, the first is the default voice.
+    pub fn new(voices: Vec<VoiceDescription>) -> Self {
+        Self { voices }
+    }
+}
+
+impl VoiceCatalogue for StaticCatalogue {
+    fn voices(&self) -> &[VoiceDescription] {
+        &self.voices
+    }
+}
+
+impl VoiceAttributes {
+    /// The attributes in effect for this voice element when it's inside another, features this
+    /// element doesn't set are taken from the outer element.
+    pub fn inherit(&self, outer: &VoiceAttributes) -> VoiceAttributes {
+        fn or_outer<T: Clone>(inner: &[T], outer: &[T]) -> Vec<T> {
+            if inner.is_empty() {
+                outer.to_vec()
+            } else {
+                inner.to_vec()
+            }
+        }
+        VoiceAttributes {
+            gender: self.gender.or(outer.gender),
+            age: self.age.or(outer.age),
+            variant: self.variant.or(outer.variant),
+            name: or_outer(&self.name, &outer.name),
+            languages: or_outer(&self.languages, &outer.languages),
+            required: self.required.clone().or_else(|| outer.required.clone()),
+            ordering: self.ordering.clone().or_else(|| outer.ordering.clone()),
+            on_voice_failure: self.on_voice_failure.or(outer.on_voice_failure),
+        }
+    }
+}
+
+/// Whether a voice has one of the features asked for, features which aren't set are satisfied by
+/// any voice.
+fn voice_matches(voice: &VoiceDescription, attrs: &VoiceAttributes, feature: VoiceFeature) -> bool {
+    match feature {
+        VoiceFeature::Name => {
+            let mut names = attrs.name.iter().filter(|x| !x.is_empty()).peekable();
+            names.peek().is_none() || names.any(|x| *x == voice.name)
+        }
+        VoiceFeature::Languages => attrs.languages.iter().all(|pair| {
+            voice
+                .languages
+                .iter()
+                .any(|x| pair.matches(&x.lang, x.accent()))
+        }),
+        VoiceFeature::Gender => attrs.gender.is_none() || attrs.gender == voice.gender,
+        VoiceFeature::Age => attrs.age.is_none() || attrs.age == voice.age,
+        VoiceFeature::Variant => attrs.variant.is_none() || attrs.variant == voice.variant,
+    }
+}
+
+/// Chooses a voice by feature priority. Each of the features in the ordering is taken in turn,
+/// dropping the candidates which don't match it as long as some do. The features which aren't in
+/// the ordering have equal priority so the candidates matching the most of them are kept. If
+/// more than one candidate is left the first is used.
+fn select_by_priority<'a>(
+    mut candidates: Vec<&'a VoiceDescription>,
+    attrs: &VoiceAttributes,
+    features: &[VoiceFeature],
+    ordering: &[VoiceFeature],
+) -> &'a VoiceDescription {
+    let mut ordered = vec![];
+    for feature in ordering {
+        if features.contains(feature) && !ordered.contains(feature) {
+            ordered.push(*feature);
+        }
+    }
+    for feature in &ordered {
+        if candidates.len() == 1 {
+            break;
+        }
+        let matching = match feature {
+            VoiceFeature::Name => preferred_names(&candidates, attrs),
+            feature => candidates
+                .iter()
+                .copied()
+                .filter(|voice| voice_matches(voice, attrs, *feature))
+                .collect(),
+        };
+        if !matching.is_empty() {
+            candidates = matching;
+        }
+    }
+
+    let unordered = features
+        .iter()
+        .filter(|x| !ordered.contains(x))
+        .collect::<Vec<_>>();
+    let score = |voice: &VoiceDescription| {
+        unordered
+            .iter()
+            .filter(|x| voice_matches(voice, attrs, ***x))
+            .count()
+    };
+    let best = candidates
+        .iter()
+        .map(|x| score(x))
+        .max()
+        .unwrap_or_default();
+    candidates.retain(|x| score(x) == best);
+    if unordered.contains(&&VoiceFeature::Name) {
+        let preferred = preferred_names(&candidates, attrs);
+        if !preferred.is_empty() {
+            candidates = preferred;
+        }
+    }
+    candidates[0]
+}
+
+/// The candidates with the most preferred of the names asked for, names are listed from top
+/// preference down.
+fn preferred_names<'a>(
+    candidates: &[&'a VoiceDescription],
+    attrs: &VoiceAttributes,
+) -> Vec<&'a VoiceDescription> {
+    attrs
+        .name
+        .iter()
+        .filter(|x| !x.is_empty())
+        .map(|name| {
+            candidates
+                .iter()
+                .copied()
+                .filter(|voice| voice.name == *name)
+                .collect::<Vec<_>>()
+        })
+        .find(|x| !x.is_empty())
+        .unwrap_or_default()
+}
+
+/// The voice selected for a voice element.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoiceScope<'a> {
+    /// Range of the element's text in unicode scalar values, the same unit as `Span`.
+    pub range: Range<usize>,
+    /// The element's attributes combined with those it inherits.
+    pub attributes: VoiceAttributes,
+    /// The voice selected, or why there wasn't one.
+    pub selection: Result<VoiceSelection<'a>, SelectionFailure>,
+}
+
+impl Ssml {
+    /// Selects a voice from the catalogue for each voice element in document order. Text outside
+    /// of any voice element uses the catalogue's default voice.
+    pub fn voice_selections<'a, C>(&self, catalogue: &'a C) -> Vec<VoiceScope<'a>>
+    where
+        C: VoiceCatalogue + ?Sized,
+    {
+        let mut scopes = vec![];
+        select_voices(
+            self.root(),
+            None,
+            catalogue.default_voice(),
+            catalogue,
+            &mut scopes,
+        );
+        scopes
+    }
+}
+
+fn select_voices<'a, C>(
+    node: Node<'_>,
+    inherited: Option<&VoiceAttributes>,
+    current: Option<&'a VoiceDescription>,
+    catalogue: &'a C,
+    scopes: &mut Vec<VoiceScope<'a>>,
+) where
+    C: VoiceCatalogue + ?Sized,
+{
+    for child in node.children() {
+        match child.element() {
+            Some(ParsedElement::Voice(attrs)) => {
+                let attributes = match inherited {
+                    Some(outer) => attrs.inherit(outer),
+                    None => attrs.clone(),
+                };
+                let selection = catalogue.select(&attributes, current);
+                let voice = selection.as_ref().map(|x| x.voice).ok().or(current);
+                scopes.push(VoiceScope {
+                    range: child.char_range(),
+                    attributes: attributes.clone(),
+                    selection,
+                });
+                select_voices(child, Some(&attributes), voice, catalogue, scopes);
+            }
+            Some(_) => select_voices(child, inherited, current, catalogue, scopes),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::char_to_byte_range;
+    use crate::parse_ssml;
+
+    fn voice(name: &str, gender: Gender, age: u8, languages: &[(&str, &str)]) -> VoiceDescription {
+        VoiceDescription {
+            name: name.to_string(),
+            gender: Some(gender),
+            age: Some(age),
+            variant: None,
+            languages: languages
+                .iter()
+                .map(|(lang, accent)| VoiceLanguage {
+                    lang: lang.parse().unwrap(),
+                    accent: Some(accent.parse().unwrap()),
+                })
+                .collect(),
+        }
+    }
+
+    fn catalogue() -> StaticCatalogue {
+        StaticCatalogue::new(vec![
+            voice("Amy", Gender::Female, 30, &[("en-GB", "en-GB")]),
+            voice("Brian", Gender::Male, 40, &[("en-GB", "en-GB")]),
+            voice("Joey", Gender::Male, 25, &[("en-US", "en-US")]),
+            voice(
+                "Lea",
+                Gender::Female,
+                35,
+                &[("fr-FR", "fr-FR"), ("en-US", "fr-FR")],
+            ),
+            voice(
+                "Chantal",
+                Gender::Female,
+                50,
+                &[("fr-CA", "fr-CA"), ("en-US", "pt-BR")],
+            ),
+        ])
+    }
+
+    fn selected(ssml: &str) -> Vec<(String, Option<SelectionFailure>)> {
+        let catalogue = catalogue();
+        let ssml = parse_ssml(ssml).unwrap();
+        ssml.voice_selections(&catalogue)
+            .into_iter()
+            .map(|scope| {
+                let selection = scope.selection.unwrap();
+                (selection.voice.name.clone(), selection.failure)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select_voices() {
+        let selections = selected(
+            r#"<speak>
+            <voice gender="male">Brian is the first male voice speaking English</voice>
+            <voice languages="en-US" gender="female">Lea matches first</voice>
+            <voice languages="en:pt" age="50">Only Chantal speaks English with a Portuguese accent</voice>
+            <voice name="Nobody Joey Amy">Joey is preferred to Amy</voice>
+            <voice languages="en-US" ordering="age gender" gender="male" age="50">Age first</voice>
+            <voice languages="en-US" ordering="gender age" gender="male" age="50">Gender first</voice>
+            </speak>"#,
+        );
+        assert_eq!(
+            selections,
+            [
+                ("Brian".to_string(), None),
+                ("Lea".to_string(), None),
+                ("Chantal".to_string(), None),
+                ("Joey".to_string(), None),
+                ("Chantal".to_string(), None),
+                ("Joey".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn selection_failure() {
+        let unmatched =
+            |features: &[VoiceFeature]| Some(SelectionFailure::Unmatched(features.to_vec()));
+        let selections = selected(
+            r#"<speak>
+            <voice languages="de-DE" gender="male">Priority selects the first male voice</voice>
+            <voice name="Lea"><voice languages="de" onvoicefailure="keepexisting">Kept Lea</voice></voice>
+            <voice required="gender age" gender="male" age="30">No 30 year old man</voice>
+            <voice required="name languages" ordering="name" name="Chantal" languages="en-GB">Name and language</voice>
+            </speak>"#,
+        );
+        assert_eq!(
+            selections,
+            [
+                ("Brian".to_string(), unmatched(&[VoiceFeature::Languages])),
+                ("Lea".to_string(), None),
+                ("Lea".to_string(), unmatched(&[VoiceFeature::Languages])),
+                (
+                    "Amy".to_string(),
+                    unmatched(&[VoiceFeature::Gender, VoiceFeature::Age])
+                ),
+                (
+                    "Chantal".to_string(),
+                    unmatched(&[VoiceFeature::Name, VoiceFeature::Languages])
+                ),
+            ]
+        );
+
+        let failure = SelectionFailure::Unmatched(vec![VoiceFeature::Gender, VoiceFeature::Age]);
+        assert_eq!(
+            failure.to_string(),
+            "no voice matches the required features: gender, age"
+        );
+
+        let empty = StaticCatalogue::default();
+        let ssml = parse_ssml(r#"<speak><voice gender="male">Hi</voice></speak>"#).unwrap();
+        assert_eq!(
+            ssml.voice_selections(&empty)[0].selection,
+            Err(SelectionFailure::NoVoices)
+        );
+    }
+
+    #[test]
+    fn inherited_attributes() {
+        let ssml = parse_ssml(
+            r#"<speak><voice gender="female" required="gender" onvoicefailure="keepexisting"><p><voice age="50" languages="en-US">Chantal</voice></p></voice></speak>"#,
+        )
+        .unwrap();
+        let catalogue = catalogue();
+        let scopes = ssml.voice_selections(&catalogue);
+        assert_eq!(scopes.len(), 2);
+        let inner = &scopes[1];
+        assert_eq!(inner.attributes.gender, Some(Gender::Female));
+        assert_eq!(inner.attributes.age, Some(50));
+        assert_eq!(inner.attributes.required, Some(vec![VoiceFeature::Gender]));
+        assert_eq!(
+            inner.attributes.on_voice_failure,
+            Some(OnVoiceFailure::KeepExisting)
+        );
+        assert_eq!(inner.selection.as_ref().unwrap().voice.name, "Chantal");
+        let text = ssml.get_text();
+        assert_eq!(
+            &text[char_to_byte_range(text, inner.range.clone())],
+            "Chantal"
+        );
+    }
+}
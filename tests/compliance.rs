@@ -42,13 +42,13 @@ fn simple_example() {
         panic!("Tag 0 wrong: {:?}", tags[0]);
     }
 
-    if let ParsedElement::Paragraph = &tags[1].element {
+    if let ParsedElement::Paragraph(_) = &tags[1].element {
         assert_eq!(result.get_text_from_span(tags[1]).trim(), whole_sentence);
     } else {
         panic!("Tag 1 wrong: {:?}", tags[1]);
     }
 
-    if let ParsedElement::Sentence = &tags[2].element {
+    if let ParsedElement::Sentence(_) = &tags[2].element {
         assert_eq!(
             result.get_text_from_span(tags[2]).trim(),
             "You have 4 new messages."
@@ -57,7 +57,7 @@ fn simple_example() {
         panic!("Tag 2 wrong: {:?}", tags[2]);
     }
 
-    if let ParsedElement::Sentence = &tags[3].element {
+    if let ParsedElement::Sentence(_) = &tags[3].element {
         assert_eq!(
             result.get_text_from_span(tags[3]).trim(),
             "The first is from Stephanie Williams and arrived at 3:45pm."
@@ -73,7 +73,7 @@ fn simple_example() {
         panic!("Tag 4 wrong {:?}", tags[4]);
     }
 
-    if let ParsedElement::Sentence = &tags[5].element {
+    if let ParsedElement::Sentence(_) = &tags[5].element {
         assert_eq!(
             result.get_text_from_span(tags[5]).trim(),
             "The subject is ski trip"
@@ -100,6 +100,7 @@ fn simple_example() {
 }
 
 #[test]
+#[allow(clippy::excessive_precision)]
 fn break_tag_handling() {
     let ssml = r#"<break strength='medium' time='2s' />
         this is a test for parsing of break tags
@@ -274,7 +275,7 @@ fn ipa_support() {
         r#"The title of the movie is: La vita è bella (Life is beautiful), which is directed by Roberto Benigni"#
     );
 
-    let phonemes = vec![
+    let phonemes = [
         (Some(PhonemeAlphabet::Ipa), "ˈlɑ ˈviːɾə ˈʔeɪ ˈbɛlə"),
         (Some(PhonemeAlphabet::Ipa), "ɹəˈbɛːɹɾoʊ bɛˈniːnji"),
     ];
@@ -325,14 +326,14 @@ fn microsoft_custom_tags() {
     // I've had to modify the Microsoft example as:
     // 1. It was invalid XML (closing an already closed tag)
     // 2. Invalid parameter values that don't match the standard
-    let ssml = r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xmlns:mstts="https://www.w3.org/2001/mstts" xml:lang="string">
+    let ssml = r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xmlns:mstts="https://www.w3.org/2001/mstts" xml:lang="en-US">
     <mstts:backgroundaudio src="string" volume="string" fadein="string" fadeout="string"/>
     <voice name="string">
         <audio src="string"></audio>
         <bookmark mark="string"/>
         <break strength="medium" time="5s" />
         <emphasis level="reduced"></emphasis>
-        <lang xml:lang="string"></lang>
+        <lang xml:lang="fr-FR"></lang>
         <lexicon xml:id="some_id" uri="string"/>
         <math xmlns="http://www.w3.org/1998/Math/MathML"></math>
         <mstts:express-as style="string" styledegree="value" role="string"></mstts:express-as>